@@ -0,0 +1,4 @@
+DROP TABLE fcm_calendar_feed;
+ALTER TABLE fcm_schedule DROP COLUMN rrule;
+DELETE FROM fcm_schedule WHERE cron_pattern IS NULL;
+ALTER TABLE fcm_schedule ALTER COLUMN cron_pattern SET NOT NULL;
@@ -0,0 +1,8 @@
+ALTER TABLE fcm_schedule ALTER COLUMN cron_pattern DROP NOT NULL;
+ALTER TABLE fcm_schedule ADD COLUMN rrule TEXT;
+
+CREATE TABLE fcm_calendar_feed (
+    fb_user_id TEXT PRIMARY KEY,
+    token TEXT NOT NULL UNIQUE,
+    created_at TIMESTAMP NOT NULL
+);
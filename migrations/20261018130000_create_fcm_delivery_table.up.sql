@@ -0,0 +1,12 @@
+CREATE TABLE fcm_delivery (
+    id BIGSERIAL PRIMARY KEY,
+    schedule_id INTEGER REFERENCES fcm_schedule(id) ON DELETE SET NULL,
+    fb_user_id TEXT NOT NULL,
+    fb_project_id TEXT NOT NULL,
+    status TEXT NOT NULL,
+    error TEXT,
+    created_at TIMESTAMP NOT NULL
+);
+
+CREATE INDEX fcm_delivery_user_created_at_idx ON fcm_delivery (fb_user_id, created_at);
+CREATE INDEX fcm_delivery_project_created_at_idx ON fcm_delivery (fb_project_id, created_at);
@@ -0,0 +1,2 @@
+DROP TABLE fcm_schedule_audit;
+ALTER TABLE fcm_schedule DROP COLUMN paused;
@@ -0,0 +1,14 @@
+ALTER TABLE fcm_schedule ADD COLUMN paused BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE TABLE fcm_schedule_audit (
+    id BIGSERIAL PRIMARY KEY,
+    schedule_id INTEGER NOT NULL,
+    fb_user_id TEXT NOT NULL,
+    actor TEXT NOT NULL,
+    action TEXT NOT NULL,
+    source_ip TEXT,
+    diff JSONB NOT NULL,
+    created_at TIMESTAMP NOT NULL
+);
+
+CREATE INDEX fcm_schedule_audit_schedule_id_idx ON fcm_schedule_audit (schedule_id, created_at);
@@ -0,0 +1,2 @@
+DROP TRIGGER fcm_schedule_changed ON fcm_schedule;
+DROP FUNCTION notify_fcm_schedule_changed();
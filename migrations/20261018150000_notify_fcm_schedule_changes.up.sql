@@ -0,0 +1,10 @@
+CREATE FUNCTION notify_fcm_schedule_changed() RETURNS TRIGGER AS $$
+BEGIN
+    PERFORM pg_notify('fcm_schedule_changed', TG_OP);
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER fcm_schedule_changed
+    AFTER INSERT OR UPDATE OF next_execution, paused OR DELETE ON fcm_schedule
+    FOR EACH STATEMENT EXECUTE FUNCTION notify_fcm_schedule_changed();
@@ -0,0 +1,2 @@
+DROP TABLE monitor_snapshot;
+DROP TABLE monitor;
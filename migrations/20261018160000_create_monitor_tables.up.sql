@@ -0,0 +1,35 @@
+CREATE TABLE monitor (
+    id SERIAL PRIMARY KEY,
+    name TEXT NOT NULL,
+    fb_user_id TEXT NOT NULL,
+    fb_project_id TEXT NOT NULL,
+    url TEXT NOT NULL,
+    selector TEXT,
+    cron_pattern TEXT,
+    rrule TEXT,
+    sensitivity DOUBLE PRECISION NOT NULL DEFAULT 0,
+    push_token TEXT,
+    webhook_url TEXT,
+    paused BOOLEAN NOT NULL DEFAULT FALSE,
+    last_error TEXT,
+    last_checked_at TIMESTAMP,
+    next_check TIMESTAMP NOT NULL,
+    created_at TIMESTAMP NOT NULL,
+    updated_at TIMESTAMP NOT NULL
+);
+
+CREATE INDEX monitor_fb_user_id_idx ON monitor (fb_user_id);
+CREATE INDEX monitor_next_check_idx ON monitor (next_check) WHERE NOT paused;
+
+CREATE TABLE monitor_snapshot (
+    id BIGSERIAL PRIMARY KEY,
+    monitor_id INTEGER NOT NULL REFERENCES monitor (id) ON DELETE CASCADE,
+    content TEXT NOT NULL,
+    added INTEGER NOT NULL,
+    removed INTEGER NOT NULL,
+    summary TEXT,
+    notified BOOLEAN NOT NULL,
+    created_at TIMESTAMP NOT NULL
+);
+
+CREATE INDEX monitor_snapshot_monitor_id_idx ON monitor_snapshot (monitor_id, id);
@@ -0,0 +1,9 @@
+DROP TRIGGER fcm_delivery_snoozed ON fcm_delivery;
+DROP INDEX fcm_delivery_snoozed_until_idx;
+DROP INDEX fcm_delivery_schedule_created_at_idx;
+
+ALTER TABLE fcm_delivery
+    DROP COLUMN resend_of,
+    DROP COLUMN acked_at,
+    DROP COLUMN snoozed_until,
+    DROP COLUMN snoozes;
@@ -0,0 +1,14 @@
+ALTER TABLE fcm_delivery
+    ADD COLUMN resend_of BIGINT REFERENCES fcm_delivery(id) ON DELETE CASCADE,
+    ADD COLUMN acked_at TIMESTAMP,
+    ADD COLUMN snoozed_until TIMESTAMP,
+    ADD COLUMN snoozes INTEGER NOT NULL DEFAULT 0;
+
+CREATE INDEX fcm_delivery_schedule_created_at_idx ON fcm_delivery (schedule_id, created_at);
+CREATE INDEX fcm_delivery_snoozed_until_idx ON fcm_delivery (snoozed_until) WHERE snoozed_until IS NOT NULL;
+
+-- wake the workers when a snooze is set, clearing it when resending doesn't need to
+CREATE TRIGGER fcm_delivery_snoozed
+    AFTER UPDATE OF snoozed_until ON fcm_delivery
+    FOR EACH ROW WHEN (NEW.snoozed_until IS NOT NULL)
+    EXECUTE FUNCTION notify_fcm_schedule_changed();
@@ -0,0 +1,7 @@
+DROP INDEX fcm_delivery_next_escalation_at_idx;
+
+ALTER TABLE fcm_delivery
+    DROP COLUMN escalation_step,
+    DROP COLUMN next_escalation_at;
+
+ALTER TABLE fcm_schedule DROP COLUMN escalation;
@@ -0,0 +1,7 @@
+ALTER TABLE fcm_schedule ADD COLUMN escalation JSONB;
+
+ALTER TABLE fcm_delivery
+    ADD COLUMN escalation_step INTEGER NOT NULL DEFAULT 0,
+    ADD COLUMN next_escalation_at TIMESTAMP;
+
+CREATE INDEX fcm_delivery_next_escalation_at_idx ON fcm_delivery (next_escalation_at) WHERE next_escalation_at IS NOT NULL;
@@ -0,0 +1,38 @@
+DROP TRIGGER fcm_schedule_changed ON fcm_schedule;
+DROP TRIGGER fcm_delivery_snoozed ON fcm_delivery;
+
+ALTER TABLE fcm_schedule
+    ALTER COLUMN last_execution TYPE TIMESTAMP USING last_execution AT TIME ZONE 'UTC',
+    ALTER COLUMN next_execution TYPE TIMESTAMP USING next_execution AT TIME ZONE 'UTC',
+    ALTER COLUMN created_at TYPE TIMESTAMP USING created_at AT TIME ZONE 'UTC',
+    ALTER COLUMN updated_at TYPE TIMESTAMP USING updated_at AT TIME ZONE 'UTC';
+
+ALTER TABLE fcm_calendar_feed
+    ALTER COLUMN created_at TYPE TIMESTAMP USING created_at AT TIME ZONE 'UTC';
+
+ALTER TABLE fcm_delivery
+    ALTER COLUMN created_at TYPE TIMESTAMP USING created_at AT TIME ZONE 'UTC',
+    ALTER COLUMN acked_at TYPE TIMESTAMP USING acked_at AT TIME ZONE 'UTC',
+    ALTER COLUMN snoozed_until TYPE TIMESTAMP USING snoozed_until AT TIME ZONE 'UTC',
+    ALTER COLUMN next_escalation_at TYPE TIMESTAMP USING next_escalation_at AT TIME ZONE 'UTC';
+
+ALTER TABLE fcm_schedule_audit
+    ALTER COLUMN created_at TYPE TIMESTAMP USING created_at AT TIME ZONE 'UTC';
+
+ALTER TABLE monitor
+    ALTER COLUMN last_checked_at TYPE TIMESTAMP USING last_checked_at AT TIME ZONE 'UTC',
+    ALTER COLUMN next_check TYPE TIMESTAMP USING next_check AT TIME ZONE 'UTC',
+    ALTER COLUMN created_at TYPE TIMESTAMP USING created_at AT TIME ZONE 'UTC',
+    ALTER COLUMN updated_at TYPE TIMESTAMP USING updated_at AT TIME ZONE 'UTC';
+
+ALTER TABLE monitor_snapshot
+    ALTER COLUMN created_at TYPE TIMESTAMP USING created_at AT TIME ZONE 'UTC';
+
+CREATE TRIGGER fcm_schedule_changed
+    AFTER INSERT OR UPDATE OF next_execution, paused OR DELETE ON fcm_schedule
+    FOR EACH STATEMENT EXECUTE FUNCTION notify_fcm_schedule_changed();
+
+CREATE TRIGGER fcm_delivery_snoozed
+    AFTER UPDATE OF snoozed_until ON fcm_delivery
+    FOR EACH ROW WHEN (NEW.snoozed_until IS NOT NULL)
+    EXECUTE FUNCTION notify_fcm_schedule_changed();
@@ -0,0 +1,3 @@
+DROP INDEX fcm_schedule_audit_created_at_idx;
+DROP INDEX fcm_schedule_audit_schedule_id_idx;
+CREATE INDEX fcm_schedule_audit_schedule_id_idx ON fcm_schedule_audit (schedule_id, created_at);
@@ -0,0 +1,4 @@
+-- audit pages are read by id and old entries are deleted by age
+DROP INDEX fcm_schedule_audit_schedule_id_idx;
+CREATE INDEX fcm_schedule_audit_schedule_id_idx ON fcm_schedule_audit (schedule_id, id);
+CREATE INDEX fcm_schedule_audit_created_at_idx ON fcm_schedule_audit (created_at);
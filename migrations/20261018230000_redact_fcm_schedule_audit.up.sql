@@ -0,0 +1,7 @@
+-- push tokens, webhook URLs and data source headers are no longer kept in the audit trail
+UPDATE fcm_schedule_audit
+SET diff = diff
+    || CASE WHEN diff ? 'push_token' THEN '{"push_token": {"changed": true}}'::jsonb ELSE '{}'::jsonb END
+    || CASE WHEN diff ? 'escalation' THEN '{"escalation": {"changed": true}}'::jsonb ELSE '{}'::jsonb END
+    || CASE WHEN diff ? 'data_source' THEN '{"data_source": {"changed": true}}'::jsonb ELSE '{}'::jsonb END
+WHERE diff ?| ARRAY['push_token', 'escalation', 'data_source'];
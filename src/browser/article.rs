@@ -0,0 +1,42 @@
+use super::model::Article;
+use dom_smoothie::{Config, Readability, TextMode};
+use url::Url;
+
+/// Extract the main content of a rendered page, `url` resolves relative links and images
+pub fn extract(html: &str, url: &str) -> Result<Article, String> {
+    let config = Config {
+        text_mode: TextMode::Formatted,
+        ..Default::default()
+    };
+
+    let mut readability = match Readability::new(html, Some(url), Some(config)) {
+        Ok(r) => r,
+        Err(e) => return Err(format!("Failed to read the page: {}", e)),
+    };
+
+    let article = match readability.parse() {
+        Ok(a) => a,
+        Err(e) => return Err(format!("Failed to find an article on the page: {}", e)),
+    };
+
+    let text = article.text_content.trim().to_string();
+
+    Ok(Article {
+        url: url.to_string(),
+        title: article.title,
+        byline: article.byline,
+        published_time: article.published_time,
+        site_name: article.site_name,
+        lead_image: article.image.map(|image| {
+            match Url::parse(url).and_then(|base| base.join(&image)) {
+                Ok(u) => u.to_string(),
+                Err(_) => image,
+            }
+        }),
+        excerpt: article.excerpt,
+        language: article.lang,
+        word_count: text.split_whitespace().count(),
+        html: article.content.to_string(),
+        text,
+    })
+}
@@ -0,0 +1,69 @@
+use serde_json::Value;
+use std::time::Duration;
+use thirtyfour::{error::WebDriverResult, WebDriver};
+
+const DEFAULT_TIMEOUT_MS: u64 = 10_000;
+const MAX_TIMEOUT_MS: u64 = 60_000;
+/// extra time given to the driver to report a script timeout before the session is given up
+const DEADLINE_GRACE: Duration = Duration::from_secs(5);
+
+/// Caller supplied script
+#[derive(Debug, Clone)]
+pub struct Script {
+    body: String,
+    args: Vec<Value>,
+    is_async: bool,
+    timeout: Duration,
+}
+
+impl Script {
+    /// Run `body` with `args` for up to `timeout_ms` (10 seconds when not set)
+    pub fn new(
+        body: String,
+        args: Option<Vec<Value>>,
+        is_async: Option<bool>,
+        timeout_ms: Option<u64>,
+    ) -> Result<Self, String> {
+        let timeout_ms = timeout_ms.unwrap_or(DEFAULT_TIMEOUT_MS);
+        if !(1..=MAX_TIMEOUT_MS).contains(&timeout_ms) {
+            return Err(format!(
+                "Invalid timeout: must be between 1 and {} ms",
+                MAX_TIMEOUT_MS
+            ));
+        }
+
+        Ok(Script {
+            body,
+            args: args.unwrap_or_default(),
+            is_async: is_async.unwrap_or(false),
+            timeout: Duration::from_millis(timeout_ms),
+        })
+    }
+
+    /// How long to wait for the driver before treating the session as hung, a busy page can keep
+    /// it from enforcing the script timeout
+    pub fn deadline(&self) -> Duration {
+        self.timeout + DEADLINE_GRACE
+    }
+
+    /// Run the script on the page, the session's script timeout is restored afterwards
+    pub async fn run(&self, driver: &WebDriver) -> WebDriverResult<Value> {
+        let previous = driver.get_timeouts().await?;
+        driver.set_script_timeout(self.timeout).await?;
+
+        let result = if self.is_async {
+            driver
+                .execute_async(self.body.as_str(), self.args.clone())
+                .await
+        } else {
+            driver.execute(self.body.as_str(), self.args.clone()).await
+        };
+
+        // a script error is more useful to the caller than a failed restore
+        let restored = driver.update_timeouts(previous).await;
+        let value = result?.json().clone();
+        restored?;
+
+        Ok(value)
+    }
+}
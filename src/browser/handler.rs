@@ -1,16 +1,62 @@
-use super::model::Image;
-use crate::utils::{verify_apikey, ApiTags, JsonError, JsonSuccess, ResponseObject};
+use super::{
+    article,
+    evaluate::Script,
+    links::{self, LinkFilter},
+    markdown, metadata,
+    model::{
+        Article, Device, Evaluate, Image, ImageFormat, Link, LinkStyle, Metadata, Orientation,
+        PageSize, PoolStats,
+    },
+    pool::{is_dead_session, PoolError, PooledSession, SessionPool},
+    print::{print_pdf, PrintOptions},
+    screenshot::{Capture, Viewport},
+    wait::{Wait, WaitError},
+};
+use crate::metrics;
+use crate::utils::{
+    client_ip, resolve_public_url, verify_admin_apikey, verify_apikey, ApiTags, JsonError,
+    JsonSuccess, ResponseObject,
+};
 use base64::{engine::general_purpose, Engine as _};
-use poem::{Request, Result};
-use poem_openapi::{param::Query, OpenApi};
-use std::sync::Arc;
+use poem::{http::header::ACCEPT, Request, Result};
+use poem_openapi::types::{ParseFromJSON, ToJSON};
+use poem_openapi::{
+    param::Query,
+    payload::{Attachment, AttachmentType, Binary, Json},
+    ApiResponse, OpenApi, ResponseContent,
+};
+use prometheus::HistogramTimer;
+use serde_json::Value;
 use thirtyfour::prelude::*;
-use tokio::sync::{Mutex, MutexGuard};
-use tracing::error;
+use tracing::{error, info, warn};
+use url::Url;
+
+#[derive(ApiResponse)]
+pub enum PdfResponse {
+    #[oai(status = 200, content_type = "application/pdf")]
+    Ok(Attachment<Vec<u8>>),
+}
+
+#[derive(ResponseContent)]
+pub enum ScreenshotContent {
+    Json(Json<ResponseObject<String>>),
+    #[oai(content_type = "image/png")]
+    Png(Binary<Vec<u8>>),
+    #[oai(content_type = "image/jpeg")]
+    Jpeg(Binary<Vec<u8>>),
+    #[oai(content_type = "image/webp")]
+    Webp(Binary<Vec<u8>>),
+}
+
+#[derive(ApiResponse)]
+pub enum ScreenshotResponse {
+    #[oai(status = 200)]
+    Ok(ScreenshotContent),
+}
 
 #[derive(Clone)]
 pub struct Selenium {
-    driver: Arc<Mutex<WebDriver>>,
+    pool: SessionPool,
 }
 
 #[OpenApi(
@@ -20,8 +66,8 @@ pub struct Selenium {
 )]
 impl Selenium {
     // create new instance
-    pub fn new(driver: Arc<Mutex<WebDriver>>) -> Self {
-        Selenium { driver }
+    pub fn new(pool: SessionPool) -> Self {
+        Selenium { pool }
     }
 
     /// get rendered html
@@ -31,13 +77,18 @@ impl Selenium {
         req: &Request,
         /// url of the page to render
         url: Query<String>,
-        /// delay in milliseconds wait for the page to be fully loaded
-        delay: Query<u64>,
+        /// wait until the page is ready: `selector:<css>`, `xpath:<expression>`, `text:<text>`,
+        /// `network_idle:<ms>`, `ready_state:<interactive|complete>` or `script:<js function body>`
+        /// returning a truthy value, which needs an admin scoped API Key (default
+        /// ready_state:complete)
+        wait_for: Query<Option<String>>,
+        /// milliseconds to wait for the condition before failing (default 10000, max 60000)
+        wait_timeout: Query<Option<u64>>,
+        /// extra delay in milliseconds after the page is ready (max 30000)
+        delay: Query<Option<u64>>,
         /// whether to try to bypass paywall
         bypass_paywall: Query<bool>,
     ) -> Result<JsonSuccess<String>, JsonError<String>> {
-        let driver = self.driver.lock().await;
-
         match verify_apikey(req).await {
             Ok(_) => (),
             Err(e) => {
@@ -45,12 +96,30 @@ impl Selenium {
             }
         }
 
+        let wait = match Wait::new(wait_for.0.as_deref(), wait_timeout.0, delay.0) {
+            Ok(w) => w,
+            Err(e) => {
+                return Err(ResponseObject::bad_request(e));
+            }
+        };
+        verify_wait(req, &wait).await?;
+
+        let (mut driver, _hold) = match self.checkout().await {
+            Ok(d) => d,
+            Err(e) => {
+                return Err(pool_error(e));
+            }
+        };
+
         let mut url = url.0;
         if bypass_paywall.0 {
             url = format!("https://12ft.io/{}", url);
         }
 
-        let driver = match self.setup_driver(&driver, url.as_str()).await {
+        let driver = match self
+            .setup_driver(&mut driver, url.as_str(), None, &wait)
+            .await
+        {
             Ok(d) => d,
             Err(e) => {
                 error!(url=?*url, error=?e, "Failed to setup driver");
@@ -59,7 +128,15 @@ impl Selenium {
                 ));
             }
         };
-        tokio::time::sleep(tokio::time::Duration::from_millis(delay.0)).await;
+
+        match wait.until_ready(driver).await {
+            Ok(_) => (),
+            Err(e) => {
+                error!(url=?*url, error=?e, "Page did not become ready");
+                let _ = self.cleanup_driver(driver, url.as_str()).await;
+                return Err(wait_error(e));
+            }
+        }
 
         let html = match driver.source().await {
             Ok(h) => h,
@@ -91,13 +168,18 @@ impl Selenium {
         req: &Request,
         /// url of the page to render
         url: Query<String>,
-        /// delay in milliseconds wait for the page to be fully loaded
-        delay: Query<u64>,
+        /// wait until the page is ready: `selector:<css>`, `xpath:<expression>`, `text:<text>`,
+        /// `network_idle:<ms>`, `ready_state:<interactive|complete>` or `script:<js function body>`
+        /// returning a truthy value, which needs an admin scoped API Key (default
+        /// ready_state:complete)
+        wait_for: Query<Option<String>>,
+        /// milliseconds to wait for the condition before failing (default 10000, max 60000)
+        wait_timeout: Query<Option<u64>>,
+        /// extra delay in milliseconds after the page is ready (max 30000)
+        delay: Query<Option<u64>>,
         /// whether to try to bypass paywall
         bypass_paywall: Query<bool>,
     ) -> Result<JsonSuccess<String>, JsonError<String>> {
-        let driver = self.driver.lock().await;
-
         match verify_apikey(req).await {
             Ok(_) => (),
             Err(e) => {
@@ -105,12 +187,30 @@ impl Selenium {
             }
         }
 
+        let wait = match Wait::new(wait_for.0.as_deref(), wait_timeout.0, delay.0) {
+            Ok(w) => w,
+            Err(e) => {
+                return Err(ResponseObject::bad_request(e));
+            }
+        };
+        verify_wait(req, &wait).await?;
+
+        let (mut driver, _hold) = match self.checkout().await {
+            Ok(d) => d,
+            Err(e) => {
+                return Err(pool_error(e));
+            }
+        };
+
         let mut url = url.0;
         if bypass_paywall.0 {
             url = format!("https://12ft.io/api/proxy?ref=&q={}", url);
         }
 
-        let driver = match self.setup_driver(&driver, url.as_str()).await {
+        let driver = match self
+            .setup_driver(&mut driver, url.as_str(), None, &wait)
+            .await
+        {
             Ok(d) => d,
             Err(e) => {
                 error!(url=?*url, error=?e, "Failed to setup driver");
@@ -119,7 +219,15 @@ impl Selenium {
                 ));
             }
         };
-        tokio::time::sleep(tokio::time::Duration::from_millis(delay.0)).await;
+
+        match wait.until_ready(driver).await {
+            Ok(_) => (),
+            Err(e) => {
+                error!(url=?*url, error=?e, "Page did not become ready");
+                let _ = self.cleanup_driver(driver, url.as_str()).await;
+                return Err(wait_error(e));
+            }
+        }
 
         let body = match driver.find(By::Tag("body")).await {
             Ok(h) => h,
@@ -154,24 +262,288 @@ impl Selenium {
         Ok(ResponseObject::ok(text))
     }
 
-    /// get screenshot of the rendered page
+    /// get the main article of the rendered page without navigation, banners and footers
+    #[oai(
+        path = "/article/",
+        method = "get",
+        operation_id = "browser::get_article"
+    )]
+    async fn get_article(
+        &self,
+        req: &Request,
+        /// url of the page to render
+        url: Query<String>,
+        /// wait until the page is ready: `selector:<css>`, `xpath:<expression>`, `text:<text>`,
+        /// `network_idle:<ms>`, `ready_state:<interactive|complete>` or `script:<js function body>`
+        /// returning a truthy value, which needs an admin scoped API Key (default
+        /// ready_state:complete)
+        wait_for: Query<Option<String>>,
+        /// milliseconds to wait for the condition before failing (default 10000, max 60000)
+        wait_timeout: Query<Option<u64>>,
+        /// extra delay in milliseconds after the page is ready (max 30000)
+        delay: Query<Option<u64>>,
+        /// whether to try to bypass paywall
+        bypass_paywall: Query<bool>,
+    ) -> Result<JsonSuccess<Article>, JsonError<String>> {
+        match verify_apikey(req).await {
+            Ok(_) => (),
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        }
+
+        let wait = match Wait::new(wait_for.0.as_deref(), wait_timeout.0, delay.0) {
+            Ok(w) => w,
+            Err(e) => {
+                return Err(ResponseObject::bad_request(e));
+            }
+        };
+        verify_wait(req, &wait).await?;
+
+        let (mut driver, _hold) = match self.checkout().await {
+            Ok(d) => d,
+            Err(e) => {
+                return Err(pool_error(e));
+            }
+        };
+
+        // links and images are resolved against the original url, not the paywall proxy
+        let page_url = url.0.clone();
+        let mut url = url.0;
+        if bypass_paywall.0 {
+            url = format!("https://12ft.io/api/proxy?ref=&q={}", url);
+        }
+
+        let driver = match self
+            .setup_driver(&mut driver, url.as_str(), None, &wait)
+            .await
+        {
+            Ok(d) => d,
+            Err(e) => {
+                error!(url=?*url, error=?e, "Failed to setup driver");
+                return Err(ResponseObject::internal_server_error(
+                    "Failed to setup driver",
+                ));
+            }
+        };
+
+        match wait.until_ready(driver).await {
+            Ok(_) => (),
+            Err(e) => {
+                error!(url=?*url, error=?e, "Page did not become ready");
+                let _ = self.cleanup_driver(driver, url.as_str()).await;
+                return Err(wait_error(e));
+            }
+        }
+
+        let html = match driver.source().await {
+            Ok(h) => h,
+            Err(e) => {
+                error!(url=?*url, error=?e, "Failed to get page source");
+                return Err(ResponseObject::internal_server_error(
+                    "Failed to get page source",
+                ));
+            }
+        };
+
+        match self.cleanup_driver(driver, url.as_str()).await {
+            Ok(_) => (),
+            Err(e) => {
+                error!(url=?*url, error=?e, "Failed to cleanup driver");
+                return Err(ResponseObject::internal_server_error(
+                    "Failed to cleanup driver",
+                ));
+            }
+        }
+
+        // parsing large pages is CPU bound
+        let article = tokio::task::spawn_blocking(move || article::extract(&html, &page_url)).await;
+
+        match article {
+            Ok(Ok(article)) => Ok(ResponseObject::ok(article)),
+            Ok(Err(e)) => {
+                error!(url=?*url, error=?e, "Failed to extract the article");
+                Err(ResponseObject::not_found(e))
+            }
+            Err(e) => {
+                error!(url=?*url, error=?e, "Failed to extract the article");
+                Err(ResponseObject::internal_server_error(
+                    "Failed to extract the article",
+                ))
+            }
+        }
+    }
+
+    /// get the rendered page, or its main article, as Markdown
+    #[oai(
+        path = "/markdown/",
+        method = "get",
+        operation_id = "browser::get_markdown"
+    )]
+    #[allow(clippy::too_many_arguments)]
+    async fn get_markdown(
+        &self,
+        req: &Request,
+        /// url of the page to render
+        url: Query<String>,
+        /// wait until the page is ready: `selector:<css>`, `xpath:<expression>`, `text:<text>`,
+        /// `network_idle:<ms>`, `ready_state:<interactive|complete>` or `script:<js function body>`
+        /// returning a truthy value, which needs an admin scoped API Key (default
+        /// ready_state:complete)
+        wait_for: Query<Option<String>>,
+        /// milliseconds to wait for the condition before failing (default 10000, max 60000)
+        wait_timeout: Query<Option<u64>>,
+        /// extra delay in milliseconds after the page is ready (max 30000)
+        delay: Query<Option<u64>>,
+        /// whether to try to bypass paywall
+        bypass_paywall: Query<bool>,
+        /// convert only the main article instead of the whole page (default false)
+        main_content: Query<Option<bool>>,
+        /// how links are written (default inline)
+        link_style: Query<Option<LinkStyle>>,
+        /// leave images out (default false)
+        strip_images: Query<Option<bool>>,
+    ) -> Result<JsonSuccess<String>, JsonError<String>> {
+        match verify_apikey(req).await {
+            Ok(_) => (),
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        }
+
+        let wait = match Wait::new(wait_for.0.as_deref(), wait_timeout.0, delay.0) {
+            Ok(w) => w,
+            Err(e) => {
+                return Err(ResponseObject::bad_request(e));
+            }
+        };
+        verify_wait(req, &wait).await?;
+
+        let (mut driver, _hold) = match self.checkout().await {
+            Ok(d) => d,
+            Err(e) => {
+                return Err(pool_error(e));
+            }
+        };
+
+        let page_url = url.0.clone();
+        let mut url = url.0;
+        if bypass_paywall.0 {
+            url = format!("https://12ft.io/api/proxy?ref=&q={}", url);
+        }
+
+        let driver = match self
+            .setup_driver(&mut driver, url.as_str(), None, &wait)
+            .await
+        {
+            Ok(d) => d,
+            Err(e) => {
+                error!(url=?*url, error=?e, "Failed to setup driver");
+                return Err(ResponseObject::internal_server_error(
+                    "Failed to setup driver",
+                ));
+            }
+        };
+
+        match wait.until_ready(driver).await {
+            Ok(_) => (),
+            Err(e) => {
+                error!(url=?*url, error=?e, "Page did not become ready");
+                let _ = self.cleanup_driver(driver, url.as_str()).await;
+                return Err(wait_error(e));
+            }
+        }
+
+        let html = match markdown::absolute_html(driver).await {
+            Ok(h) => h,
+            Err(e) => {
+                error!(url=?*url, error=?e, "Failed to get page source");
+                let _ = self.cleanup_driver(driver, url.as_str()).await;
+                return Err(ResponseObject::internal_server_error(
+                    "Failed to get page source",
+                ));
+            }
+        };
+
+        match self.cleanup_driver(driver, url.as_str()).await {
+            Ok(_) => (),
+            Err(e) => {
+                error!(url=?*url, error=?e, "Failed to cleanup driver");
+                return Err(ResponseObject::internal_server_error(
+                    "Failed to cleanup driver",
+                ));
+            }
+        }
+
+        let main_content = main_content.0.unwrap_or(false);
+        let link_style = link_style.0.unwrap_or_default();
+        let strip_images = strip_images.0.unwrap_or(false);
+        let converted = tokio::task::spawn_blocking(move || {
+            if !main_content {
+                return markdown::convert(&html, link_style, strip_images);
+            }
+            let article = article::extract(&html, &page_url)?;
+            let content = markdown::convert(&article.html, link_style, strip_images)?;
+            Ok(format!("# {}\n\n{}", article.title, content))
+        })
+        .await;
+
+        match converted {
+            Ok(Ok(markdown)) => Ok(ResponseObject::ok(markdown)),
+            Ok(Err(e)) => {
+                error!(url=?*url, error=?e, "Failed to convert the page to Markdown");
+                Err(ResponseObject::not_found(e))
+            }
+            Err(e) => {
+                error!(url=?*url, error=?e, "Failed to convert the page to Markdown");
+                Err(ResponseObject::internal_server_error(
+                    "Failed to convert the page to Markdown",
+                ))
+            }
+        }
+    }
+
+    /// get screenshot of the rendered page, as an image when the Accept header asks for one and
+    /// as a data URI otherwise
     #[oai(
         path = "/screenshot/",
         method = "get",
         operation_id = "browser::get_screenshot"
     )]
+    #[allow(clippy::too_many_arguments)]
     async fn get_screenshot(
         &self,
         req: &Request,
         /// url of the page to render
         url: Query<String>,
-        /// delay in milliseconds wait for the page to be fully loaded
-        delay: Query<u64>,
+        /// wait until the page is ready: `selector:<css>`, `xpath:<expression>`, `text:<text>`,
+        /// `network_idle:<ms>`, `ready_state:<interactive|complete>` or `script:<js function body>`
+        /// returning a truthy value, which needs an admin scoped API Key (default
+        /// ready_state:complete)
+        wait_for: Query<Option<String>>,
+        /// milliseconds to wait for the condition before failing (default 10000, max 60000)
+        wait_timeout: Query<Option<u64>>,
+        /// extra delay in milliseconds after the page is ready (max 30000)
+        delay: Query<Option<u64>>,
         /// whether to try to bypass paywall
         bypass_paywall: Query<bool>,
-    ) -> Result<JsonSuccess<String>, JsonError<Option<String>>> {
-        let driver = self.driver.lock().await;
-
+        /// capture the whole page instead of the viewport (default false)
+        full_page: Query<Option<bool>>,
+        /// capture only the first element matching the CSS selector
+        selector: Query<Option<String>>,
+        /// mobile device to emulate
+        device: Query<Option<Device>>,
+        /// viewport width in CSS pixels, overrides the device's
+        width: Query<Option<u32>>,
+        /// viewport height in CSS pixels, overrides the device's
+        height: Query<Option<u32>>,
+        /// device pixel ratio between 0.5 and 4, overrides the device's
+        device_scale_factor: Query<Option<f64>>,
+        /// image format (default png)
+        format: Query<Option<ImageFormat>>,
+        /// jpeg or webp quality between 0 and 100
+        quality: Query<Option<u8>>,
+    ) -> Result<ScreenshotResponse, JsonError<Option<String>>> {
         match verify_apikey(req).await {
             Ok(_) => (),
             Err(e) => {
@@ -179,12 +551,44 @@ impl Selenium {
             }
         }
 
+        let wait = match Wait::new(wait_for.0.as_deref(), wait_timeout.0, delay.0) {
+            Ok(w) => w,
+            Err(e) => {
+                return Err(ResponseObject::bad_request(e));
+            }
+        };
+        verify_wait(req, &wait).await?;
+
+        let viewport = match Viewport::new(device.0, width.0, height.0, device_scale_factor.0) {
+            Ok(v) => v,
+            Err(e) => {
+                return Err(ResponseObject::bad_request(e));
+            }
+        };
+
+        let capture = match Capture::new(format.0, quality.0, full_page.0, selector.0) {
+            Ok(c) => c,
+            Err(e) => {
+                return Err(ResponseObject::bad_request(e));
+            }
+        };
+
+        let (mut driver, _hold) = match self.checkout().await {
+            Ok(d) => d,
+            Err(e) => {
+                return Err(pool_error(e));
+            }
+        };
+
         let mut url = url.0;
         if bypass_paywall.0 {
             url = format!("https://12ft.io/api/proxy?ref=&q={}", url);
         }
 
-        let driver = match self.setup_driver(&driver, url.as_str()).await {
+        let driver = match self
+            .setup_driver(&mut driver, url.as_str(), viewport.as_ref(), &wait)
+            .await
+        {
             Ok(d) => d,
             Err(e) => {
                 error!(url=?*url, error=?e, "Failed to setup driver");
@@ -193,18 +597,31 @@ impl Selenium {
                 ));
             }
         };
-        tokio::time::sleep(tokio::time::Duration::from_millis(delay.0)).await;
 
-        let screenshot = match driver.screenshot_as_png().await {
+        match wait.until_ready(driver).await {
+            Ok(_) => (),
+            Err(e) => {
+                error!(url=?*url, error=?e, "Page did not become ready");
+                let _ = self.cleanup_driver(driver, url.as_str()).await;
+                return Err(wait_error(e));
+            }
+        }
+
+        let screenshot = match capture.screenshot(driver).await {
             Ok(h) => h,
+            Err(WebDriverError::NoSuchElement(e)) => {
+                let _ = self.cleanup_driver(driver, url.as_str()).await;
+                return Err(ResponseObject::not_found(e.value.message));
+            }
             Err(e) => {
                 error!(url=?*url, error=?e, "Failed to get the screenshot of the page");
+                discard_if_dead(driver, &e);
+                let _ = self.cleanup_driver(driver, url.as_str()).await;
                 return Err(ResponseObject::internal_server_error(
                     "Failed to get the screenshot of the page",
                 ));
             }
         };
-        let b64 = general_purpose::STANDARD.encode(screenshot);
 
         match self.cleanup_driver(driver, url.as_str()).await {
             Ok(_) => (),
@@ -216,7 +633,18 @@ impl Selenium {
             }
         }
 
-        Ok(ResponseObject::ok(format!("data:image/png;base64,{}", b64)))
+        let content = match (accepts_image(req), capture.format) {
+            (true, ImageFormat::Png) => ScreenshotContent::Png(Binary(screenshot)),
+            (true, ImageFormat::Jpeg) => ScreenshotContent::Jpeg(Binary(screenshot)),
+            (true, ImageFormat::Webp) => ScreenshotContent::Webp(Binary(screenshot)),
+            (false, _) => ScreenshotContent::Json(ResponseObject::json(format!(
+                "data:{};base64,{}",
+                capture.content_type(),
+                general_purpose::STANDARD.encode(screenshot)
+            ))),
+        };
+
+        Ok(ScreenshotResponse::Ok(content))
     }
 
     /// get list of images in the rendered page
@@ -230,13 +658,18 @@ impl Selenium {
         req: &Request,
         /// url of the page to render
         url: Query<String>,
-        /// delay in milliseconds wait for the page to be fully loaded
-        delay: Query<u64>,
+        /// wait until the page is ready: `selector:<css>`, `xpath:<expression>`, `text:<text>`,
+        /// `network_idle:<ms>`, `ready_state:<interactive|complete>` or `script:<js function body>`
+        /// returning a truthy value, which needs an admin scoped API Key (default
+        /// ready_state:complete)
+        wait_for: Query<Option<String>>,
+        /// milliseconds to wait for the condition before failing (default 10000, max 60000)
+        wait_timeout: Query<Option<u64>>,
+        /// extra delay in milliseconds after the page is ready (max 30000)
+        delay: Query<Option<u64>>,
         /// whether to try to bypass paywall
         bypass_paywall: Query<bool>,
     ) -> Result<JsonSuccess<Vec<Image>>, JsonError<String>> {
-        let driver = self.driver.lock().await;
-
         match verify_apikey(req).await {
             Ok(_) => (),
             Err(e) => {
@@ -244,12 +677,30 @@ impl Selenium {
             }
         }
 
+        let wait = match Wait::new(wait_for.0.as_deref(), wait_timeout.0, delay.0) {
+            Ok(w) => w,
+            Err(e) => {
+                return Err(ResponseObject::bad_request(e));
+            }
+        };
+        verify_wait(req, &wait).await?;
+
+        let (mut driver, _hold) = match self.checkout().await {
+            Ok(d) => d,
+            Err(e) => {
+                return Err(pool_error(e));
+            }
+        };
+
         let mut url = url.0;
         if bypass_paywall.0 {
             url = format!("https://12ft.io/api/proxy?ref=&q={}", url);
         }
 
-        let driver = match self.setup_driver(&driver, url.as_str()).await {
+        let driver = match self
+            .setup_driver(&mut driver, url.as_str(), None, &wait)
+            .await
+        {
             Ok(d) => d,
             Err(e) => {
                 error!(url=?*url, error=?e, "Failed to setup driver");
@@ -258,7 +709,15 @@ impl Selenium {
                 ));
             }
         };
-        tokio::time::sleep(tokio::time::Duration::from_millis(delay.0)).await;
+
+        match wait.until_ready(driver).await {
+            Ok(_) => (),
+            Err(e) => {
+                error!(url=?*url, error=?e, "Page did not become ready");
+                let _ = self.cleanup_driver(driver, url.as_str()).await;
+                return Err(wait_error(e));
+            }
+        }
 
         let images = match driver.find_all(By::Tag("img")).await {
             Ok(h) => h,
@@ -332,52 +791,567 @@ impl Selenium {
         Ok(ResponseObject::ok(images_vec))
     }
 
-    async fn setup_driver<'a>(
-        &'a self,
-        driver: &'a MutexGuard<'_, WebDriver>,
-        url: &str,
-    ) -> Result<&MutexGuard<'_, WebDriver>, String> {
-        let tab = match driver.new_tab().await {
-            Ok(t) => t,
-            Err(e) => {
-                error!(url=?*url, error=?e, "Failed to create new tab");
-                return Err("Failed to create new tab".to_string());
-            }
-        };
-
-        match driver.switch_to_window(tab).await {
+    /// get list of links in the rendered page
+    #[oai(path = "/links/", method = "get", operation_id = "browser::get_links")]
+    #[allow(clippy::too_many_arguments)]
+    async fn get_links(
+        &self,
+        req: &Request,
+        /// url of the page to render
+        url: Query<String>,
+        /// wait until the page is ready: `selector:<css>`, `xpath:<expression>`, `text:<text>`,
+        /// `network_idle:<ms>`, `ready_state:<interactive|complete>` or `script:<js function body>`
+        /// returning a truthy value, which needs an admin scoped API Key (default
+        /// ready_state:complete)
+        wait_for: Query<Option<String>>,
+        /// milliseconds to wait for the condition before failing (default 10000, max 60000)
+        wait_timeout: Query<Option<u64>>,
+        /// extra delay in milliseconds after the page is ready (max 30000)
+        delay: Query<Option<u64>>,
+        /// whether to try to bypass paywall
+        bypass_paywall: Query<bool>,
+        /// only return links to this domain or its subdomains
+        domain: Query<Option<String>>,
+        /// only return links whose URL matches this regex
+        pattern: Query<Option<String>>,
+        /// only return links inside the elements matching this CSS selector
+        selector: Query<Option<String>>,
+    ) -> Result<JsonSuccess<Vec<Link>>, JsonError<String>> {
+        match verify_apikey(req).await {
             Ok(_) => (),
             Err(e) => {
-                error!(url=?*url, error=?e, "Failed to switch to new tab");
-                self.cleanup_driver(driver, url).await?;
-                return Err("Failed to switch to new tab".to_string());
+                return Err(ResponseObject::unauthorized(e));
             }
         }
 
-        match driver.goto(url.to_string()).await {
-            Ok(_) => (),
+        let wait = match Wait::new(wait_for.0.as_deref(), wait_timeout.0, delay.0) {
+            Ok(w) => w,
             Err(e) => {
-                error!(url=?*url, error=?e, "Failed to navigate to URL");
-                self.cleanup_driver(driver, url).await?;
-                return Err("Failed to navigate to URL".to_string());
+                return Err(ResponseObject::bad_request(e));
             }
-        }
+        };
+        verify_wait(req, &wait).await?;
+
+        let filter = match LinkFilter::new(domain.0.as_deref(), pattern.0.as_deref()) {
+            Ok(f) => f,
+            Err(e) => {
+                return Err(ResponseObject::bad_request(e));
+            }
+        };
+
+        let (mut driver, _hold) = match self.checkout().await {
+            Ok(d) => d,
+            Err(e) => {
+                return Err(pool_error(e));
+            }
+        };
+
+        // links are classified against the original url, not the paywall proxy
+        let page_url = url.0.clone();
+        let mut url = url.0;
+        if bypass_paywall.0 {
+            url = format!("https://12ft.io/api/proxy?ref=&q={}", url);
+        }
+
+        let driver = match self
+            .setup_driver(&mut driver, url.as_str(), None, &wait)
+            .await
+        {
+            Ok(d) => d,
+            Err(e) => {
+                error!(url=?*url, error=?e, "Failed to setup driver");
+                return Err(ResponseObject::internal_server_error(
+                    "Failed to setup driver",
+                ));
+            }
+        };
+
+        match wait.until_ready(driver).await {
+            Ok(_) => (),
+            Err(e) => {
+                error!(url=?*url, error=?e, "Page did not become ready");
+                let _ = self.cleanup_driver(driver, url.as_str()).await;
+                return Err(wait_error(e));
+            }
+        }
+
+        let raw = match links::read(driver, selector.0.as_deref()).await {
+            Ok(l) => l,
+            Err(WebDriverError::JavascriptError(e)) => {
+                let _ = self.cleanup_driver(driver, url.as_str()).await;
+                return Err(ResponseObject::bad_request(format!(
+                    "Invalid selector: {}",
+                    e.value.message
+                )));
+            }
+            Err(e) => {
+                error!(url=?*url, error=?e, "Failed to get the links of the page");
+                let _ = self.cleanup_driver(driver, url.as_str()).await;
+                return Err(ResponseObject::internal_server_error(
+                    "Failed to get the links of the page",
+                ));
+            }
+        };
+
+        match self.cleanup_driver(driver, url.as_str()).await {
+            Ok(_) => (),
+            Err(e) => {
+                error!(url=?*url, error=?e, "Failed to cleanup driver");
+                return Err(ResponseObject::internal_server_error(
+                    "Failed to cleanup driver",
+                ));
+            }
+        }
+
+        Ok(ResponseObject::ok(links::collect(raw, &page_url, &filter)))
+    }
+
+    /// get the metadata of the rendered page for link previews
+    #[oai(
+        path = "/metadata/",
+        method = "get",
+        operation_id = "browser::get_metadata"
+    )]
+    async fn get_metadata(
+        &self,
+        req: &Request,
+        /// url of the page to render
+        url: Query<String>,
+        /// wait until the page is ready: `selector:<css>`, `xpath:<expression>`, `text:<text>`,
+        /// `network_idle:<ms>`, `ready_state:<interactive|complete>` or `script:<js function body>`
+        /// returning a truthy value, which needs an admin scoped API Key (default
+        /// ready_state:complete)
+        wait_for: Query<Option<String>>,
+        /// milliseconds to wait for the condition before failing (default 10000, max 60000)
+        wait_timeout: Query<Option<u64>>,
+        /// extra delay in milliseconds after the page is ready (max 30000)
+        delay: Query<Option<u64>>,
+        /// whether to try to bypass paywall
+        bypass_paywall: Query<bool>,
+    ) -> Result<JsonSuccess<Metadata>, JsonError<String>> {
+        match verify_apikey(req).await {
+            Ok(_) => (),
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        }
+
+        let wait = match Wait::new(wait_for.0.as_deref(), wait_timeout.0, delay.0) {
+            Ok(w) => w,
+            Err(e) => {
+                return Err(ResponseObject::bad_request(e));
+            }
+        };
+        verify_wait(req, &wait).await?;
+
+        let (mut driver, _hold) = match self.checkout().await {
+            Ok(d) => d,
+            Err(e) => {
+                return Err(pool_error(e));
+            }
+        };
+
+        let page_url = url.0.clone();
+        let mut url = url.0;
+        if bypass_paywall.0 {
+            url = format!("https://12ft.io/api/proxy?ref=&q={}", url);
+        }
+
+        let driver = match self
+            .setup_driver(&mut driver, url.as_str(), None, &wait)
+            .await
+        {
+            Ok(d) => d,
+            Err(e) => {
+                error!(url=?*url, error=?e, "Failed to setup driver");
+                return Err(ResponseObject::internal_server_error(
+                    "Failed to setup driver",
+                ));
+            }
+        };
+
+        match wait.until_ready(driver).await {
+            Ok(_) => (),
+            Err(e) => {
+                error!(url=?*url, error=?e, "Page did not become ready");
+                let _ = self.cleanup_driver(driver, url.as_str()).await;
+                return Err(wait_error(e));
+            }
+        }
+
+        let raw = match metadata::read(driver).await {
+            Ok(m) => m,
+            Err(e) => {
+                error!(url=?*url, error=?e, "Failed to get the metadata of the page");
+                let _ = self.cleanup_driver(driver, url.as_str()).await;
+                return Err(ResponseObject::internal_server_error(
+                    "Failed to get the metadata of the page",
+                ));
+            }
+        };
+
+        match self.cleanup_driver(driver, url.as_str()).await {
+            Ok(_) => (),
+            Err(e) => {
+                error!(url=?*url, error=?e, "Failed to cleanup driver");
+                return Err(ResponseObject::internal_server_error(
+                    "Failed to cleanup driver",
+                ));
+            }
+        }
+
+        Ok(ResponseObject::ok(metadata::parse(raw, &page_url)))
+    }
+
+    /// get the rendered page as a PDF
+    #[oai(path = "/pdf/", method = "get", operation_id = "browser::get_pdf")]
+    #[allow(clippy::too_many_arguments)]
+    async fn get_pdf(
+        &self,
+        req: &Request,
+        /// url of the page to render
+        url: Query<String>,
+        /// wait until the page is ready: `selector:<css>`, `xpath:<expression>`, `text:<text>`,
+        /// `network_idle:<ms>`, `ready_state:<interactive|complete>` or `script:<js function body>`
+        /// returning a truthy value, which needs an admin scoped API Key (default
+        /// ready_state:complete)
+        wait_for: Query<Option<String>>,
+        /// milliseconds to wait for the condition before failing (default 10000, max 60000)
+        wait_timeout: Query<Option<u64>>,
+        /// extra delay in milliseconds after the page is ready (max 30000)
+        delay: Query<Option<u64>>,
+        /// whether to try to bypass paywall
+        bypass_paywall: Query<bool>,
+        /// paper size (default a4)
+        page_size: Query<Option<PageSize>>,
+        /// page orientation (default portrait)
+        orientation: Query<Option<Orientation>>,
+        /// margins in cm as `all`, `vertical,horizontal` or `top,right,bottom,left` (default 1)
+        margins: Query<Option<String>>,
+        /// scale of the page between 0.1 and 2 (default 1)
+        scale: Query<Option<f64>>,
+        /// whether to print background graphics (default false)
+        background: Query<Option<bool>>,
+        /// pages to print, e.g. `1-3,5` (default all)
+        page_ranges: Query<Option<String>>,
+    ) -> Result<PdfResponse, JsonError<String>> {
+        match verify_apikey(req).await {
+            Ok(_) => (),
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        }
+
+        let wait = match Wait::new(wait_for.0.as_deref(), wait_timeout.0, delay.0) {
+            Ok(w) => w,
+            Err(e) => {
+                return Err(ResponseObject::bad_request(e));
+            }
+        };
+        verify_wait(req, &wait).await?;
+
+        let options = match PrintOptions::new(
+            page_size.0,
+            orientation.0,
+            margins.0.as_deref(),
+            scale.0,
+            background.0,
+            page_ranges.0.as_deref(),
+        ) {
+            Ok(o) => o,
+            Err(e) => {
+                return Err(ResponseObject::bad_request(e));
+            }
+        };
+
+        let (mut driver, _hold) = match self.checkout().await {
+            Ok(d) => d,
+            Err(e) => {
+                return Err(pool_error(e));
+            }
+        };
+
+        // name the file after the site
+        let filename = match Url::parse(&url.0) {
+            Ok(u) => format!("{}.pdf", u.host_str().unwrap_or("page")),
+            Err(_) => "page.pdf".to_string(),
+        };
+
+        let mut url = url.0;
+        if bypass_paywall.0 {
+            url = format!("https://12ft.io/api/proxy?ref=&q={}", url);
+        }
+
+        let driver = match self
+            .setup_driver(&mut driver, url.as_str(), None, &wait)
+            .await
+        {
+            Ok(d) => d,
+            Err(e) => {
+                error!(url=?*url, error=?e, "Failed to setup driver");
+                return Err(ResponseObject::internal_server_error(
+                    "Failed to setup driver",
+                ));
+            }
+        };
+
+        match wait.until_ready(driver).await {
+            Ok(_) => (),
+            Err(e) => {
+                error!(url=?*url, error=?e, "Page did not become ready");
+                let _ = self.cleanup_driver(driver, url.as_str()).await;
+                return Err(wait_error(e));
+            }
+        }
+
+        let pdf = match print_pdf(driver, &options).await {
+            Ok(p) => p,
+            Err(e) => {
+                error!(url=?*url, error=?e, "Failed to print the page");
+                let _ = self.cleanup_driver(driver, url.as_str()).await;
+                return Err(ResponseObject::internal_server_error(
+                    "Failed to print the page",
+                ));
+            }
+        };
 
-        // Wait for page to be loaded.
-        let _ = driver.find(By::Tag("img")).await;
+        match self.cleanup_driver(driver, url.as_str()).await {
+            Ok(_) => (),
+            Err(e) => {
+                error!(url=?*url, error=?e, "Failed to cleanup driver");
+                return Err(ResponseObject::internal_server_error(
+                    "Failed to cleanup driver",
+                ));
+            }
+        }
+
+        Ok(PdfResponse::Ok(
+            Attachment::new(pdf)
+                .attachment_type(AttachmentType::Attachment)
+                .filename(filename),
+        ))
+    }
+
+    /// run JavaScript on the rendered page and get its result (admin scoped API Key)
+    #[oai(
+        path = "/evaluate/",
+        method = "post",
+        operation_id = "browser::evaluate"
+    )]
+    async fn evaluate(
+        &self,
+        req: &Request,
+        payload: Json<Evaluate>,
+    ) -> Result<JsonSuccess<Value>, JsonError<String>> {
+        match verify_admin_apikey(req).await {
+            Ok(_) => (),
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        }
+
+        let payload = payload.0;
+        let wait = match Wait::new(
+            payload.wait_for.as_deref(),
+            payload.wait_timeout,
+            payload.delay,
+        ) {
+            Ok(w) => w,
+            Err(e) => {
+                return Err(ResponseObject::bad_request(e));
+            }
+        };
+
+        let script = match Script::new(
+            payload.script,
+            payload.args,
+            payload.is_async,
+            payload.timeout,
+        ) {
+            Ok(s) => s,
+            Err(e) => {
+                return Err(ResponseObject::bad_request(e));
+            }
+        };
+
+        let (mut driver, _hold) = match self.checkout().await {
+            Ok(d) => d,
+            Err(e) => {
+                return Err(pool_error(e));
+            }
+        };
+
+        let mut url = payload.url;
+        if payload.bypass_paywall.unwrap_or(false) {
+            url = format!("https://12ft.io/api/proxy?ref=&q={}", url);
+        }
+        info!(url=?*url, client_ip=?client_ip(req), "Evaluating script");
+
+        let driver = match self
+            .setup_driver(&mut driver, url.as_str(), None, &wait)
+            .await
+        {
+            Ok(d) => d,
+            Err(e) => {
+                error!(url=?*url, error=?e, "Failed to setup driver");
+                return Err(ResponseObject::internal_server_error(
+                    "Failed to setup driver",
+                ));
+            }
+        };
+
+        match wait.until_ready(driver).await {
+            Ok(_) => (),
+            Err(e) => {
+                error!(url=?*url, error=?e, "Page did not become ready");
+                let _ = self.cleanup_driver(driver, url.as_str()).await;
+                return Err(wait_error(e));
+            }
+        }
+
+        let value = match tokio::time::timeout(script.deadline(), script.run(driver)).await {
+            Ok(Ok(v)) => v,
+            Ok(Err(WebDriverError::JavascriptError(e))) => {
+                let _ = self.cleanup_driver(driver, url.as_str()).await;
+                return Err(ResponseObject::bad_request(format!(
+                    "Script failed: {}",
+                    e.value.message
+                )));
+            }
+            Ok(Err(WebDriverError::ScriptTimeout(_))) => {
+                let _ = self.cleanup_driver(driver, url.as_str()).await;
+                return Err(ResponseObject::gateway_timeout("Script timed out"));
+            }
+            Ok(Err(e)) => {
+                error!(url=?*url, error=?e, "Failed to evaluate script");
+                let _ = self.cleanup_driver(driver, url.as_str()).await;
+                return Err(ResponseObject::internal_server_error(
+                    "Failed to evaluate script",
+                ));
+            }
+            Err(_) => {
+                // the page is still busy with the script, the session can't be reused
+                warn!(url=?*url, "Script did not return, discarding the session");
+                driver.discard();
+                return Err(ResponseObject::gateway_timeout("Script timed out"));
+            }
+        };
+
+        match self.cleanup_driver(driver, url.as_str()).await {
+            Ok(_) => (),
+            Err(e) => {
+                error!(url=?*url, error=?e, "Failed to cleanup driver");
+                return Err(ResponseObject::internal_server_error(
+                    "Failed to cleanup driver",
+                ));
+            }
+        }
+
+        Ok(ResponseObject::ok(value))
+    }
+
+    async fn checkout(&self) -> Result<(PooledSession, HistogramTimer), PoolError> {
+        let wait = metrics::BROWSER_DRIVER_WAIT.start_timer();
+        let session = self.pool.checkout().await;
+        wait.observe_duration();
+
+        Ok((session?, metrics::BROWSER_DRIVER_HOLD.start_timer()))
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        self.pool.stats()
+    }
+
+    async fn setup_driver<'a>(
+        &'a self,
+        driver: &'a mut PooledSession,
+        url: &str,
+        viewport: Option<&Viewport>,
+        wait: &Wait,
+    ) -> Result<&'a PooledSession, String> {
+        let tab = match driver.new_tab().await {
+            Ok(t) => t,
+            Err(e) if is_dead_session(&e) => {
+                // the driver restarted or the session timed out, start over with a new session
+                warn!(url=?*url, error=?e, "Browser session is dead, recreating it");
+                match driver.renew().await {
+                    Ok(_) => (),
+                    Err(e) => {
+                        error!(url=?*url, error=?e, "Failed to recreate browser session");
+                        return Err("Failed to recreate browser session".to_string());
+                    }
+                }
+
+                match driver.new_tab().await {
+                    Ok(t) => t,
+                    Err(e) => {
+                        error!(url=?*url, error=?e, "Failed to create new tab");
+                        discard_if_dead(driver, &e);
+                        return Err("Failed to create new tab".to_string());
+                    }
+                }
+            }
+            Err(e) => {
+                error!(url=?*url, error=?e, "Failed to create new tab");
+                return Err("Failed to create new tab".to_string());
+            }
+        };
+
+        match driver.switch_to_window(tab).await {
+            Ok(_) => (),
+            Err(e) => {
+                error!(url=?*url, error=?e, "Failed to switch to new tab");
+                discard_if_dead(driver, &e);
+                self.cleanup_driver(driver, url).await?;
+                return Err("Failed to switch to new tab".to_string());
+            }
+        }
+
+        if let Some(viewport) = viewport {
+            match viewport.apply(driver).await {
+                Ok(_) => (),
+                Err(e) => {
+                    error!(url=?*url, error=?e, "Failed to set the viewport");
+                    discard_if_dead(driver, &e);
+                    self.cleanup_driver(driver, url).await?;
+                    return Err("Failed to set the viewport".to_string());
+                }
+            }
+        }
+
+        match wait.prepare(driver).await {
+            Ok(_) => (),
+            Err(e) => {
+                error!(url=?*url, error=?e, "Failed to prepare the wait condition");
+                discard_if_dead(driver, &e);
+                self.cleanup_driver(driver, url).await?;
+                return Err("Failed to prepare the wait condition".to_string());
+            }
+        }
+
+        match driver.goto(url.to_string()).await {
+            Ok(_) => (),
+            Err(e) => {
+                error!(url=?*url, error=?e, "Failed to navigate to URL");
+                discard_if_dead(driver, &e);
+                self.cleanup_driver(driver, url).await?;
+                return Err("Failed to navigate to URL".to_string());
+            }
+        }
 
         Ok(driver)
     }
 
     async fn cleanup_driver<'a>(
         &'a self,
-        driver: &'a MutexGuard<'_, WebDriver>,
+        driver: &'a PooledSession,
         url: &str,
     ) -> Result<(), String> {
         match driver.close_window().await {
             Ok(_) => (),
             Err(e) => {
                 error!(url=?*url, error=?e, "Failed to close tab");
+                discard_if_dead(driver, &e);
                 return Err("Failed to close tab".to_string());
             }
         }
@@ -386,6 +1360,7 @@ impl Selenium {
             Ok(w) => w,
             Err(e) => {
                 error!(url=?*url, error=?e, "Failed to get windows");
+                discard_if_dead(driver, &e);
                 return Err("Failed to get windows".to_string());
             }
         };
@@ -401,20 +1376,90 @@ impl Selenium {
         let handle = handle.clone();
 
         match driver.switch_to_window(handle).await {
-            Ok(_) => {
-                Ok(())
-            }
+            Ok(_) => Ok(()),
             Err(e) => {
                 error!(url=?*url, error=?e, "Failed to switch to window");
+                discard_if_dead(driver, &e);
                 Err("Failed to switch to window".to_string())
             }
         }
     }
 
+    /// Text of the rendered page, or of the elements matching `selector` joined by new lines
+    pub async fn render_text(&self, url: &str, selector: Option<&str>) -> Result<String, String> {
+        let (mut driver, _hold) = match self.checkout().await {
+            Ok(d) => d,
+            Err(e) => {
+                return Err(e.to_string());
+            }
+        };
+
+        let wait = Wait::default();
+        let driver = self.setup_driver(&mut driver, url, None, &wait).await?;
+
+        match wait.until_ready(driver).await {
+            Ok(_) => (),
+            Err(e) => {
+                error!(url=?url, error=?e, "Page did not become ready");
+                let _ = self.cleanup_driver(driver, url).await;
+                return Err(e.to_string());
+            }
+        }
+
+        // pages are rendered for callers without an API key, they may not redirect to private hosts
+        let landed = match driver.current_url().await {
+            Ok(landed) => resolve_public_url(landed.as_str()).await.map(|_| ()),
+            Err(e) => Err(format!("Failed to get the url of the page: {}", e)),
+        };
+        if let Err(e) = landed {
+            let _ = self.cleanup_driver(driver, url).await;
+            return Err(e);
+        }
+
+        let elements = match selector {
+            Some(selector) => driver.find_all(By::Css(selector)).await,
+            None => driver.find_all(By::Tag("body")).await,
+        };
+
+        let text = match elements {
+            Ok(elements) => {
+                let mut texts = Vec::with_capacity(elements.len());
+                for element in elements {
+                    match element.text().await {
+                        Ok(text) => texts.push(text),
+                        Err(e) => {
+                            error!(url=?url, error=?e, "Failed to get the text of the element");
+                        }
+                    }
+                }
+                Ok(texts.join("\n"))
+            }
+            Err(e) => {
+                error!(url=?url, error=?e, "Failed to find the elements of the page");
+                Err("Failed to find the elements of the page".to_string())
+            }
+        };
+
+        self.cleanup_driver(driver, url).await?;
+
+        text
+    }
+
     pub async fn health(&self) -> anyhow::Result<(), anyhow::Error> {
-        let driver = self.driver.lock().await;
+        // don't queue behind requests, every session being busy means they are working
+        let mut driver = match self.pool.try_checkout().await {
+            Some(Ok(d)) => d,
+            Some(Err(e)) => {
+                error!(error=?e, "Failed to checkout browser session");
+                return Err(anyhow::anyhow!("{}", e));
+            }
+            None => return Ok(()),
+        };
 
-        let driver = match self.setup_driver(&driver, "https://example.com").await {
+        let driver = match self
+            .setup_driver(&mut driver, "https://example.com", None, &Wait::default())
+            .await
+        {
             Ok(d) => d,
             Err(e) => {
                 error!(error=?e, "Failed to setup driver");
@@ -441,3 +1486,50 @@ impl Selenium {
         Ok(())
     }
 }
+
+/// Pool exhaustion is temporary, tell the client to retry instead of failing the request
+fn pool_error<T: ParseFromJSON + ToJSON + Send + Sync>(error: PoolError) -> JsonError<T> {
+    error!(error=?error, "Failed to checkout browser session");
+    match error {
+        PoolError::Session(_) => ResponseObject::internal_server_error(error),
+        _ => ResponseObject::service_unavailable(error),
+    }
+}
+
+/// Don't return a session to the pool once the driver no longer knows it
+fn discard_if_dead(driver: &PooledSession, error: &WebDriverError) {
+    if is_dead_session(error) {
+        driver.discard();
+    }
+}
+
+/// Conditions running caller supplied JavaScript are limited to admin scoped API Keys
+async fn verify_wait<T: ParseFromJSON + ToJSON + Send + Sync>(
+    req: &Request,
+    wait: &Wait,
+) -> Result<(), JsonError<T>> {
+    if wait.runs_script() {
+        if let Err(e) = verify_admin_apikey(req).await {
+            return Err(ResponseObject::unauthorized(e));
+        }
+    }
+
+    Ok(())
+}
+
+fn wait_error<T: ParseFromJSON + ToJSON + Send + Sync>(error: WaitError) -> JsonError<T> {
+    match error {
+        WaitError::Timeout(_) => ResponseObject::gateway_timeout(error),
+        WaitError::Script(_) => ResponseObject::bad_request(error),
+        WaitError::Failed(_) => ResponseObject::internal_server_error(error),
+    }
+}
+
+/// Whether the client asked for the image itself rather than the JSON envelope
+fn accepts_image(req: &Request) -> bool {
+    req.headers()
+        .get(ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .map(|accept| accept.contains("image/"))
+        .unwrap_or(false)
+}
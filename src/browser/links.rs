@@ -0,0 +1,244 @@
+use super::model::Link;
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::HashMap;
+use thirtyfour::{error::WebDriverResult, WebDriver};
+use url::{form_urlencoded, Url};
+
+/// anchors inside the elements matching `arguments[0]`, in document order
+const LINKS_SCRIPT: &str = r#"
+const scopes = arguments[0] ? document.querySelectorAll(arguments[0]) : [document];
+const anchors = new Set();
+for (const scope of scopes) {
+    for (const anchor of scope.querySelectorAll('a[href]')) anchors.add(anchor);
+}
+return [...anchors].map((anchor) => ({
+    href: anchor.href,
+    text: (anchor.innerText || anchor.textContent || '').trim(),
+    rel: anchor.getAttribute('rel'),
+    target: anchor.getAttribute('target'),
+}));
+"#;
+
+/// query parameters only used to track the click
+const TRACKING_PARAMS: [&str; 10] = [
+    "fbclid", "gclid", "dclid", "gbraid", "wbraid", "msclkid", "yclid", "igshid", "mc_cid",
+    "mc_eid",
+];
+
+/// Anchor as read from the page
+#[derive(Debug, Deserialize)]
+pub struct RawLink {
+    href: String,
+    text: String,
+    rel: Option<String>,
+    target: Option<String>,
+}
+
+/// Which links to keep
+#[derive(Debug)]
+pub struct LinkFilter {
+    domain: Option<String>,
+    pattern: Option<Regex>,
+}
+
+impl LinkFilter {
+    /// Keep links to `domain` or its subdomains whose URL matches `pattern`
+    pub fn new(domain: Option<&str>, pattern: Option<&str>) -> Result<Self, String> {
+        let domain = domain
+            .map(|d| d.trim().trim_start_matches("www.").to_lowercase())
+            .filter(|d| !d.is_empty());
+        let pattern = match pattern {
+            Some(p) => match Regex::new(p) {
+                Ok(r) => Some(r),
+                Err(e) => return Err(format!("Invalid pattern: {}", e)),
+            },
+            None => None,
+        };
+
+        Ok(LinkFilter { domain, pattern })
+    }
+
+    fn matches(&self, url: &Url) -> bool {
+        if let Some(domain) = &self.domain {
+            let host = site(url);
+            if host != *domain && !host.ends_with(&format!(".{}", domain)) {
+                return false;
+            }
+        }
+        match &self.pattern {
+            Some(pattern) => pattern.is_match(url.as_str()),
+            None => true,
+        }
+    }
+}
+
+/// Read the anchors on the page, limited to the elements matching `selector`
+pub async fn read(driver: &WebDriver, selector: Option<&str>) -> WebDriverResult<Vec<RawLink>> {
+    let result = driver.execute(LINKS_SCRIPT, vec![json!(selector)]).await?;
+    result.convert()
+}
+
+/// Normalize, filter and dedupe the anchors of `page_url`, non HTTP links are left out
+pub fn collect(raw: Vec<RawLink>, page_url: &str, filter: &LinkFilter) -> Vec<Link> {
+    let page_site = Url::parse(page_url).ok().map(|u| site(&u));
+    let mut links: Vec<Link> = Vec::new();
+    let mut seen: HashMap<String, usize> = HashMap::new();
+
+    for raw in raw {
+        let url = match normalize(&raw.href) {
+            Some(u) => u,
+            None => continue,
+        };
+        if !filter.matches(&url) {
+            continue;
+        }
+
+        // the same link often appears as an image and as a title, keep the first with text
+        if let Some(&index) = seen.get(url.as_str()) {
+            if links[index].text.is_empty() {
+                links[index].text = raw.text;
+            }
+            continue;
+        }
+
+        seen.insert(url.to_string(), links.len());
+        links.push(Link {
+            internal: page_site.as_deref() == Some(site(&url).as_str()),
+            url: url.to_string(),
+            text: raw.text,
+            rel: raw.rel.filter(|r| !r.is_empty()),
+            target: raw.target.filter(|t| !t.is_empty()),
+        });
+    }
+
+    links
+}
+
+/// Drop the fragment and tracking parameters of an absolute HTTP URL
+fn normalize(href: &str) -> Option<Url> {
+    let mut url = match Url::parse(href) {
+        Ok(u) if u.scheme() == "http" || u.scheme() == "https" => u,
+        _ => return None,
+    };
+    url.set_fragment(None);
+
+    // the kept parameters are copied as written, re-serializing them would change their encoding
+    let query = url.query().unwrap_or_default().to_string();
+    let params: Vec<&str> = query
+        .split('&')
+        .filter(
+            |pair| match form_urlencoded::parse(pair.as_bytes()).next() {
+                Some((key, _)) => {
+                    let key = key.to_lowercase();
+                    !key.starts_with("utm_") && !TRACKING_PARAMS.contains(&key.as_str())
+                }
+                None => false,
+            },
+        )
+        .collect();
+
+    if params.is_empty() {
+        url.set_query(None);
+    } else {
+        url.set_query(Some(&params.join("&")));
+    }
+
+    Some(url)
+}
+
+/// Host of the URL without `www.`
+fn site(url: &Url) -> String {
+    url.host_str()
+        .unwrap_or_default()
+        .trim_start_matches("www.")
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn raw(href: &str, text: &str) -> RawLink {
+        RawLink {
+            href: href.to_string(),
+            text: text.to_string(),
+            rel: None,
+            target: None,
+        }
+    }
+
+    fn normalized(href: &str) -> Option<String> {
+        normalize(href).map(|u| u.to_string())
+    }
+
+    #[test]
+    fn normalize_drops_tracking() {
+        assert_eq!(
+            normalized("https://example.com/a?utm_source=x&id=1&FBCLID=2#top").as_deref(),
+            Some("https://example.com/a?id=1")
+        );
+        assert_eq!(
+            normalized("https://example.com/a?utm_medium=x&gclid=1").as_deref(),
+            Some("https://example.com/a")
+        );
+        assert_eq!(normalized("mailto:a@example.com"), None);
+        assert_eq!(normalized("javascript:void(0)"), None);
+    }
+
+    #[test]
+    fn normalize_keeps_parameter_encoding() {
+        assert_eq!(
+            normalized("https://example.com/search?q=a%20b&utm_source=x&tag=c+d").as_deref(),
+            Some("https://example.com/search?q=a%20b&tag=c+d")
+        );
+        assert_eq!(
+            normalized("https://example.com/search?q=a%20b").as_deref(),
+            Some("https://example.com/search?q=a%20b")
+        );
+    }
+
+    #[test]
+    fn collect_dedupes_keeping_the_first_text() {
+        let filter = LinkFilter::new(None, None).unwrap();
+        let links = collect(
+            vec![
+                raw("https://example.com/post#comments", ""),
+                raw("https://example.com/post", "Post"),
+                raw("https://example.com/post?utm_source=x", "Again"),
+                raw("https://other.com/", "Other"),
+            ],
+            "https://www.example.com/",
+            &filter,
+        );
+
+        assert_eq!(links.len(), 2);
+        assert_eq!(links[0].url, "https://example.com/post");
+        assert_eq!(links[0].text, "Post");
+        assert!(links[0].internal);
+        assert_eq!(links[1].url, "https://other.com/");
+        assert!(!links[1].internal);
+    }
+
+    #[test]
+    fn filter_matches_subdomains() {
+        let filter = LinkFilter::new(Some(" www.Example.com "), None).unwrap();
+        let matches = |href: &str| filter.matches(&Url::parse(href).unwrap());
+
+        assert!(matches("https://example.com/"));
+        assert!(matches("https://www.example.com/"));
+        assert!(matches("https://blog.example.com/"));
+        assert!(!matches("https://notexample.com/"));
+        assert!(!matches("https://example.com.evil.com/"));
+    }
+
+    #[test]
+    fn filter_matches_pattern() {
+        let filter = LinkFilter::new(None, Some(r"/posts/\d+")).unwrap();
+        assert!(filter.matches(&Url::parse("https://example.com/posts/12").unwrap()));
+        assert!(!filter.matches(&Url::parse("https://example.com/about").unwrap()));
+
+        assert!(LinkFilter::new(None, Some("(")).is_err());
+    }
+}
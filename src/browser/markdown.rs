@@ -0,0 +1,59 @@
+use super::model::LinkStyle;
+use htmd::{
+    options::{self, Options},
+    HtmlToMarkdown,
+};
+use thirtyfour::{error::WebDriverResult, WebDriver};
+
+/// copy of the document with links and images pointing to absolute URLs
+const ABSOLUTE_HTML_SCRIPT: &str = r#"
+const root = document.documentElement.cloneNode(true);
+for (const element of root.querySelectorAll('[href], [src]')) {
+    for (const attribute of ['href', 'src']) {
+        const value = element.getAttribute(attribute);
+        if (value === null) continue;
+        try {
+            element.setAttribute(attribute, new URL(value, document.baseURI).href);
+        } catch (e) {}
+    }
+}
+return root.outerHTML;
+"#;
+
+/// elements that never carry readable content
+const SKIPPED_TAGS: [&str; 8] = [
+    "head", "script", "style", "noscript", "template", "svg", "canvas", "iframe",
+];
+
+/// Get the rendered DOM with relative links and images resolved against the page
+pub async fn absolute_html(driver: &WebDriver) -> WebDriverResult<String> {
+    let result = driver.execute(ABSOLUTE_HTML_SCRIPT, vec![]).await?;
+    result.convert()
+}
+
+/// Convert HTML to CommonMark, tables are written the GitHub way
+pub fn convert(html: &str, link_style: LinkStyle, strip_images: bool) -> Result<String, String> {
+    let mut skipped = SKIPPED_TAGS.to_vec();
+    if strip_images {
+        skipped.extend(["img", "picture"]);
+    }
+
+    let converter = HtmlToMarkdown::builder()
+        .options(Options {
+            link_style: match link_style {
+                LinkStyle::Inline => options::LinkStyle::Inlined,
+                LinkStyle::Referenced => options::LinkStyle::Referenced,
+            },
+            bullet_list_marker: options::BulletListMarker::Dash,
+            ul_bullet_spacing: 1,
+            ol_number_spacing: 1,
+            ..Default::default()
+        })
+        .skip_tags(skipped)
+        .build();
+
+    match converter.convert(html) {
+        Ok(markdown) => Ok(markdown),
+        Err(e) => Err(format!("Failed to convert the page to Markdown: {}", e)),
+    }
+}
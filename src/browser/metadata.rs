@@ -0,0 +1,329 @@
+use super::model::{Feed, FeedFormat, Metadata, OpenGraph, TwitterCard};
+use serde::Deserialize;
+use serde_json::Value;
+use thirtyfour::{error::WebDriverResult, WebDriver};
+use url::Url;
+
+/// raw metadata of the document, link hrefs are already absolute
+const METADATA_SCRIPT: &str = r#"
+const attribute = (selector, name) => {
+    const element = document.querySelector(selector);
+    return element ? element.getAttribute(name) : null;
+};
+const link = (selector) => {
+    const element = document.querySelector(selector);
+    return element ? element.href : null;
+};
+return {
+    base: document.baseURI,
+    title: document.title,
+    description: attribute('meta[name="description" i]', 'content'),
+    canonical: link('link[rel~="canonical" i][href]'),
+    language: document.documentElement.getAttribute('lang'),
+    meta: [...document.querySelectorAll('meta[content]')]
+        .map((m) => [(m.getAttribute('property') || m.getAttribute('name') || '').toLowerCase(), m.getAttribute('content')])
+        .filter(([key]) => key.startsWith('og:') || key.startsWith('twitter:')),
+    json_ld: [...document.querySelectorAll('script[type="application/ld+json" i]')].map((s) => s.textContent),
+    icons: [...document.querySelectorAll('link[rel~="icon" i][href], link[rel="apple-touch-icon" i][href], link[rel="apple-touch-icon-precomposed" i][href]')]
+        .map((l) => ({ href: l.href, rel: l.getAttribute('rel'), sizes: l.getAttribute('sizes'), type: l.getAttribute('type') })),
+    feeds: [...document.querySelectorAll('link[rel~="alternate" i][href][type]')]
+        .map((l) => ({ href: l.href, title: l.getAttribute('title'), type: l.getAttribute('type') })),
+};
+"#;
+
+/// size assumed for touch icons that don't declare one, Apple's default
+const TOUCH_ICON_SIZE: u32 = 180;
+/// size assumed for favicons that don't declare one
+const FAVICON_SIZE: u32 = 16;
+/// size used for scalable icons
+const SCALABLE_ICON_SIZE: u32 = 1024;
+
+/// Metadata as read from the page
+#[derive(Debug, Deserialize)]
+pub struct RawMetadata {
+    base: String,
+    title: Option<String>,
+    description: Option<String>,
+    canonical: Option<String>,
+    language: Option<String>,
+    meta: Vec<(String, Option<String>)>,
+    json_ld: Vec<String>,
+    icons: Vec<RawIcon>,
+    feeds: Vec<RawFeed>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RawIcon {
+    href: String,
+    rel: Option<String>,
+    sizes: Option<String>,
+    #[serde(rename = "type")]
+    kind: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RawFeed {
+    href: String,
+    title: Option<String>,
+    #[serde(rename = "type")]
+    kind: String,
+}
+
+/// Read the metadata of the page
+pub async fn read(driver: &WebDriver) -> WebDriverResult<RawMetadata> {
+    let result = driver.execute(METADATA_SCRIPT, vec![]).await?;
+    result.convert()
+}
+
+/// Type the metadata of `page_url`, URLs in meta tags are resolved against the page
+pub fn parse(raw: RawMetadata, page_url: &str) -> Metadata {
+    let base = Url::parse(&raw.base).ok();
+    let tag = |key: &str| {
+        raw.meta
+            .iter()
+            .find(|(k, v)| k == key && v.as_deref().is_some_and(|v| !v.trim().is_empty()))
+            .and_then(|(_, v)| v.as_deref())
+            .map(|v| v.trim().to_string())
+    };
+    let url_tag = |key: &str| tag(key).map(|v| absolute(base.as_ref(), v));
+
+    let open_graph = OpenGraph {
+        title: tag("og:title"),
+        description: tag("og:description"),
+        kind: tag("og:type"),
+        url: url_tag("og:url"),
+        image: url_tag("og:image").or_else(|| url_tag("og:image:url")),
+        image_alt: tag("og:image:alt"),
+        site_name: tag("og:site_name"),
+        locale: tag("og:locale"),
+    };
+
+    let twitter = TwitterCard {
+        card: tag("twitter:card"),
+        title: tag("twitter:title"),
+        description: tag("twitter:description"),
+        image: url_tag("twitter:image").or_else(|| url_tag("twitter:image:src")),
+        site: tag("twitter:site"),
+        creator: tag("twitter:creator"),
+    };
+
+    // blocks with syntax errors are common and left out
+    let json_ld = raw
+        .json_ld
+        .iter()
+        .filter_map(|block| serde_json::from_str::<Value>(block.trim()).ok())
+        .collect();
+
+    let icon = raw
+        .icons
+        .iter()
+        .max_by_key(|icon| icon_size(icon))
+        .map(|icon| icon.href.clone())
+        .or_else(|| {
+            base.as_ref()
+                .and_then(|b| b.join("/favicon.ico").ok())
+                .map(|u| u.to_string())
+        });
+
+    let feeds = raw
+        .feeds
+        .into_iter()
+        .filter_map(|feed| {
+            let format = match feed.kind.trim().to_lowercase().as_str() {
+                "application/rss+xml" => FeedFormat::Rss,
+                "application/atom+xml" => FeedFormat::Atom,
+                _ => return None,
+            };
+            Some(Feed {
+                url: feed.href,
+                title: feed.title.filter(|t| !t.trim().is_empty()),
+                format,
+            })
+        })
+        .collect();
+
+    Metadata {
+        url: page_url.to_string(),
+        title: raw.title.filter(|t| !t.trim().is_empty()),
+        description: raw.description.filter(|d| !d.trim().is_empty()),
+        canonical_url: raw.canonical,
+        language: raw.language.filter(|l| !l.trim().is_empty()),
+        open_graph,
+        twitter,
+        json_ld,
+        icon,
+        feeds,
+    }
+}
+
+/// Largest declared dimension of the icon, or a guess based on its kind
+fn icon_size(icon: &RawIcon) -> u32 {
+    let sizes = icon.sizes.as_deref().unwrap_or_default().to_lowercase();
+    if sizes.split_whitespace().any(|s| s == "any") || icon.kind.as_deref() == Some("image/svg+xml")
+    {
+        return SCALABLE_ICON_SIZE;
+    }
+
+    let declared = sizes
+        .split_whitespace()
+        .filter_map(|s| s.split_once('x'))
+        .filter_map(|(w, h)| Some(w.parse::<u32>().ok()?.max(h.parse::<u32>().ok()?)))
+        .max();
+
+    match declared {
+        Some(size) => size,
+        None if icon
+            .rel
+            .as_deref()
+            .is_some_and(|r| r.to_lowercase().contains("apple-touch-icon")) =>
+        {
+            TOUCH_ICON_SIZE
+        }
+        None => FAVICON_SIZE,
+    }
+}
+
+fn absolute(base: Option<&Url>, value: String) -> String {
+    match base.map(|b| b.join(&value)) {
+        Some(Ok(u)) => u.to_string(),
+        _ => value,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn raw() -> RawMetadata {
+        RawMetadata {
+            base: "https://example.com/blog/post".to_string(),
+            title: Some("Post".to_string()),
+            description: Some(" ".to_string()),
+            canonical: None,
+            language: Some("en".to_string()),
+            meta: vec![],
+            json_ld: vec![],
+            icons: vec![],
+            feeds: vec![],
+        }
+    }
+
+    fn icon(href: &str, rel: &str, sizes: Option<&str>, kind: Option<&str>) -> RawIcon {
+        RawIcon {
+            href: href.to_string(),
+            rel: Some(rel.to_string()),
+            sizes: sizes.map(str::to_string),
+            kind: kind.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn icon_sizes() {
+        assert_eq!(icon_size(&icon("a", "icon", Some("any"), None)), 1024);
+        assert_eq!(
+            icon_size(&icon("a", "icon", None, Some("image/svg+xml"))),
+            1024
+        );
+        assert_eq!(icon_size(&icon("a", "apple-touch-icon", None, None)), 180);
+        assert_eq!(icon_size(&icon("a", "icon", None, None)), 16);
+        assert_eq!(icon_size(&icon("a", "icon", Some("16x16 32X48"), None)), 48);
+        assert_eq!(
+            icon_size(&icon("a", "apple-touch-icon", Some("bad"), None)),
+            180
+        );
+    }
+
+    #[test]
+    fn largest_icon_is_kept() {
+        let mut raw = raw();
+        raw.icons = vec![
+            icon("https://example.com/16.png", "icon", Some("16x16"), None),
+            icon(
+                "https://example.com/touch.png",
+                "apple-touch-icon",
+                None,
+                None,
+            ),
+            icon("https://example.com/64.png", "icon", Some("64x64"), None),
+        ];
+        let metadata = parse(raw, "https://example.com/blog/post");
+        assert_eq!(
+            metadata.icon.as_deref(),
+            Some("https://example.com/touch.png")
+        );
+    }
+
+    #[test]
+    fn favicon_fallback() {
+        let metadata = parse(raw(), "https://example.com/blog/post");
+        assert_eq!(
+            metadata.icon.as_deref(),
+            Some("https://example.com/favicon.ico")
+        );
+    }
+
+    #[test]
+    fn tags_are_trimmed_and_resolved() {
+        let mut raw = raw();
+        raw.meta = vec![
+            ("og:title".to_string(), Some("  ".to_string())),
+            ("og:title".to_string(), Some(" Title ".to_string())),
+            ("og:image".to_string(), Some("/cover.png".to_string())),
+            (
+                "twitter:image:src".to_string(),
+                Some("card.png".to_string()),
+            ),
+        ];
+        let metadata = parse(raw, "https://example.com/blog/post");
+
+        assert_eq!(metadata.open_graph.title.as_deref(), Some("Title"));
+        assert_eq!(
+            metadata.open_graph.image.as_deref(),
+            Some("https://example.com/cover.png")
+        );
+        assert_eq!(
+            metadata.twitter.image.as_deref(),
+            Some("https://example.com/blog/card.png")
+        );
+        assert_eq!(metadata.title.as_deref(), Some("Post"));
+        assert_eq!(metadata.description, None);
+    }
+
+    #[test]
+    fn invalid_json_ld_is_skipped() {
+        let mut raw = raw();
+        raw.json_ld = vec![
+            r#" {"@type": "Article"} "#.to_string(),
+            r#"{"@type": "Article",}"#.to_string(),
+            "".to_string(),
+        ];
+        let metadata = parse(raw, "https://example.com/blog/post");
+        assert_eq!(
+            metadata.json_ld,
+            vec![serde_json::json!({"@type": "Article"})]
+        );
+    }
+
+    #[test]
+    fn only_rss_and_atom_feeds() {
+        let feed = |href: &str, kind: &str| RawFeed {
+            href: href.to_string(),
+            title: Some(String::new()),
+            kind: kind.to_string(),
+        };
+        let mut raw = raw();
+        raw.feeds = vec![
+            feed("https://example.com/rss", " Application/RSS+XML "),
+            feed("https://example.com/atom", "application/atom+xml"),
+            feed("https://example.com/fr", "text/html"),
+            feed("https://example.com/feed.json", "application/feed+json"),
+        ];
+        let metadata = parse(raw, "https://example.com/blog/post");
+
+        assert_eq!(metadata.feeds.len(), 2);
+        assert!(matches!(metadata.feeds[0].format, FeedFormat::Rss));
+        assert!(matches!(metadata.feeds[1].format, FeedFormat::Atom));
+        assert_eq!(metadata.feeds[1].url, "https://example.com/atom");
+        assert_eq!(metadata.feeds[0].title, None);
+    }
+}
@@ -1,28 +1,33 @@
-use crate::utils;
-use std::sync::Arc;
-use thirtyfour::{ChromiumLikeCapabilities, DesiredCapabilities, WebDriver};
-use tokio::sync::Mutex;
+use crate::supervisor::Supervisor;
+use std::time::Duration;
 
+mod article;
+mod evaluate;
 pub mod handler;
-mod model;
+mod links;
+mod markdown;
+mod metadata;
+pub mod model;
+pub mod pool;
+mod print;
+mod screenshot;
+mod wait;
 
-pub async fn selenium() -> (handler::Selenium, WebDriver) {
-    let mut caps = DesiredCapabilities::chrome();
-    caps.set_headless().unwrap();
-    caps.set_ignore_certificate_errors().unwrap();
-    caps.set_no_sandbox().unwrap();
-    caps.set_disable_gpu().unwrap();
-    caps.set_disable_dev_shm_usage().unwrap();
+use pool::{SessionPool, POOL_CONFIG};
 
-    let web_driver = match WebDriver::new(utils::CHROME_DRIVER_ENDPOINT.as_str(), caps).await {
-        Ok(d) => d,
-        Err(e) => {
-            panic!("Failed to create session: {:?}", e)
-        }
-    };
+/// the reaper wakes every 30 seconds, opening a session can take a while on a loaded driver
+const REAPER_STALL_TIMEOUT: Duration = Duration::from_secs(300);
 
-    let driver = Arc::new(Mutex::new(web_driver.clone()));
-    let selenium_api = handler::Selenium::new(driver);
+pub async fn selenium(supervisor: &Supervisor) -> (handler::Selenium, SessionPool) {
+    let pool = SessionPool::new(&POOL_CONFIG).await;
 
-    (selenium_api, web_driver)
+    let reaper = pool.clone();
+    supervisor.spawn("browser::pool", Some(REAPER_STALL_TIMEOUT), move |handle| {
+        let pool = reaper.clone();
+        async move { pool.run_reaper(handle).await }
+    });
+
+    let selenium_api = handler::Selenium::new(pool.clone());
+
+    (selenium_api, pool)
 }
@@ -1,5 +1,6 @@
-use poem_openapi::Object;
+use poem_openapi::{Enum, Object};
 use serde::Serialize;
+use serde_json::Value;
 
 /// Image on the page
 #[derive(Debug, Object, Clone, Serialize)]
@@ -15,3 +16,241 @@ pub struct Image {
     /// Size of the image
     pub size: f64,
 }
+
+/// State of the WebDriver session pool
+#[derive(Debug, Object, Clone, Serialize)]
+pub struct PoolStats {
+    /// Sessions currently open
+    pub open: usize,
+    /// Open sessions waiting for a request
+    pub idle: usize,
+    /// Sessions serving a request
+    pub in_use: usize,
+    /// Requests queued for a session
+    pub waiting: usize,
+    /// Sessions kept open even when idle
+    pub min_size: usize,
+    /// Maximum number of concurrent sessions
+    pub max_size: usize,
+    /// Sessions handed out since startup
+    pub checkouts: u64,
+    /// Requests that timed out waiting for a session
+    pub timeouts: u64,
+    /// Requests rejected because the queue was full
+    pub rejected: u64,
+    /// Sessions closed after going idle or reaching their maximum uses
+    pub retired: u64,
+}
+
+/// Paper size of a printed page
+#[derive(Debug, Enum, Clone, Copy)]
+#[oai(rename_all = "snake_case")]
+pub enum PageSize {
+    A3,
+    A4,
+    A5,
+    Letter,
+    Legal,
+    Tabloid,
+}
+
+/// Orientation of a printed page
+#[derive(Debug, Enum, Clone, Copy)]
+#[oai(rename_all = "snake_case")]
+pub enum Orientation {
+    Portrait,
+    Landscape,
+}
+
+/// Encoding of a screenshot
+#[derive(Debug, Enum, Clone, Copy, PartialEq)]
+#[oai(rename_all = "snake_case")]
+pub enum ImageFormat {
+    Png,
+    Jpeg,
+    Webp,
+}
+
+/// Mobile device to emulate
+#[derive(Debug, Enum, Clone, Copy)]
+#[oai(rename_all = "snake_case")]
+pub enum Device {
+    Iphone15,
+    Pixel7,
+    Ipad,
+}
+
+/// Main content of a page with its metadata
+#[derive(Debug, Object, Clone, Serialize)]
+pub struct Article {
+    /// URL of the page
+    pub url: String,
+    /// Title of the article
+    pub title: String,
+    /// Author of the article
+    pub byline: Option<String>,
+    /// When the article was published, as found in the page
+    pub published_time: Option<String>,
+    /// Name of the site
+    pub site_name: Option<String>,
+    /// URL of the lead image
+    pub lead_image: Option<String>,
+    /// Short description of the article
+    pub excerpt: Option<String>,
+    /// Language of the page
+    pub language: Option<String>,
+    /// Number of words in the text
+    pub word_count: usize,
+    /// Main content as clean HTML
+    pub html: String,
+    /// Main content as plain text
+    pub text: String,
+}
+
+/// How links are written in Markdown
+#[derive(Debug, Enum, Clone, Copy, Default)]
+#[oai(rename_all = "snake_case")]
+pub enum LinkStyle {
+    /// `[text](url)`
+    #[default]
+    Inline,
+    /// `[text][1]` with the URLs listed at the end
+    Referenced,
+}
+
+/// Link on the page
+#[derive(Debug, Object, Clone, Serialize)]
+pub struct Link {
+    /// Absolute URL of the link without tracking parameters
+    pub url: String,
+    /// Text of the link
+    pub text: String,
+    /// `rel` attribute of the link
+    pub rel: Option<String>,
+    /// `target` attribute of the link
+    pub target: Option<String>,
+    /// Whether the link points to the same site as the page
+    pub internal: bool,
+}
+
+/// Structured metadata of a page for link previews
+#[derive(Debug, Object, Clone, Serialize)]
+pub struct Metadata {
+    /// URL of the page
+    pub url: String,
+    /// Title of the document
+    pub title: Option<String>,
+    /// Meta description
+    pub description: Option<String>,
+    /// Canonical URL
+    pub canonical_url: Option<String>,
+    /// Language of the document
+    pub language: Option<String>,
+    /// OpenGraph tags
+    pub open_graph: OpenGraph,
+    /// Twitter card tags
+    pub twitter: TwitterCard,
+    /// Parsed JSON-LD blocks
+    pub json_ld: Vec<Value>,
+    /// URL of the largest favicon or touch icon
+    pub icon: Option<String>,
+    /// RSS and Atom feeds
+    pub feeds: Vec<Feed>,
+}
+
+/// OpenGraph tags of a page
+#[derive(Debug, Object, Clone, Serialize, Default)]
+pub struct OpenGraph {
+    /// `og:title`
+    pub title: Option<String>,
+    /// `og:description`
+    pub description: Option<String>,
+    /// `og:type`
+    #[oai(rename = "type")]
+    #[serde(rename = "type")]
+    pub kind: Option<String>,
+    /// `og:url`
+    pub url: Option<String>,
+    /// `og:image`
+    pub image: Option<String>,
+    /// `og:image:alt`
+    pub image_alt: Option<String>,
+    /// `og:site_name`
+    pub site_name: Option<String>,
+    /// `og:locale`
+    pub locale: Option<String>,
+}
+
+/// Twitter card tags of a page
+#[derive(Debug, Object, Clone, Serialize, Default)]
+pub struct TwitterCard {
+    /// `twitter:card`
+    pub card: Option<String>,
+    /// `twitter:title`
+    pub title: Option<String>,
+    /// `twitter:description`
+    pub description: Option<String>,
+    /// `twitter:image`
+    pub image: Option<String>,
+    /// `twitter:site`
+    pub site: Option<String>,
+    /// `twitter:creator`
+    pub creator: Option<String>,
+}
+
+/// Feed linked from a page
+#[derive(Debug, Object, Clone, Serialize)]
+pub struct Feed {
+    /// URL of the feed
+    pub url: String,
+    /// Title of the feed
+    pub title: Option<String>,
+    /// Format of the feed
+    pub format: FeedFormat,
+}
+
+/// Format of a feed
+#[derive(Debug, Enum, Clone, Copy, Serialize)]
+#[oai(rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum FeedFormat {
+    Rss,
+    Atom,
+}
+
+/// JavaScript to run on a rendered page
+#[derive(Debug, Object, Clone)]
+pub struct Evaluate {
+    /// url of the page to render
+    pub url: String,
+
+    #[oai(validator(min_length = 1, max_length = 65536))]
+    /// body of a function whose JSON serializable return value is the result
+    /// e.g. return document.querySelectorAll('article').length;
+    /// async scripts pass the result to the callback `arguments[arguments.length - 1]` instead
+    pub script: String,
+
+    /// values passed to the script as `arguments[0]`, `arguments[1]`, ...
+    pub args: Option<Vec<Value>>,
+
+    #[oai(rename = "async")]
+    /// whether to run the script with execute_async (default false)
+    pub is_async: Option<bool>,
+
+    /// milliseconds the script may run (default 10000, max 60000)
+    pub timeout: Option<u64>,
+
+    /// wait until the page is ready: `selector:<css>`, `xpath:<expression>`, `text:<text>`,
+    /// `network_idle:<ms>`, `ready_state:<interactive|complete>` or `script:<js function body>`
+    /// returning a truthy value (default ready_state:complete)
+    pub wait_for: Option<String>,
+
+    /// milliseconds to wait for the condition before failing (default 10000, max 60000)
+    pub wait_timeout: Option<u64>,
+
+    /// extra delay in milliseconds after the page is ready (max 30000)
+    pub delay: Option<u64>,
+
+    /// whether to try to bypass paywall (default false)
+    pub bypass_paywall: Option<bool>,
+}
@@ -0,0 +1,442 @@
+use super::model::PoolStats;
+use crate::{
+    supervisor::WorkerHandle,
+    utils::{self, env_or},
+};
+use lazy_static::lazy_static;
+use std::{
+    fmt,
+    ops::Deref,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+use thirtyfour::{
+    error::{WebDriverError, WebDriverResult},
+    ChromiumLikeCapabilities, DesiredCapabilities, WebDriver,
+};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tracing::{error, info, warn};
+
+/// how often idle sessions are closed, swept for leaked tabs and the pool is topped up to its
+/// minimum size
+const REAP_INTERVAL: Duration = Duration::from_secs(30);
+
+lazy_static! {
+    pub static ref POOL_CONFIG: PoolConfig = PoolConfig::from_env();
+}
+
+/// Size and lifetime of the WebDriver sessions, configured through environment variables
+pub struct PoolConfig {
+    /// sessions kept open even when idle (BROWSER_POOL_MIN_SIZE)
+    pub min_size: usize,
+    /// maximum number of concurrent sessions (BROWSER_POOL_MAX_SIZE)
+    pub max_size: usize,
+    /// idle sessions above the minimum are closed after this long (BROWSER_POOL_IDLE_TIMEOUT_SECONDS)
+    pub idle_timeout: Duration,
+    /// a session is replaced after serving this many requests (BROWSER_POOL_MAX_USES)
+    pub max_uses: u32,
+    /// how long a request waits for a free session (BROWSER_POOL_QUEUE_TIMEOUT_SECONDS)
+    pub queue_timeout: Duration,
+    /// requests waiting for a session beyond this are rejected (BROWSER_POOL_MAX_QUEUE)
+    pub max_queue: usize,
+}
+
+impl PoolConfig {
+    fn from_env() -> Self {
+        let max_size = env_or("BROWSER_POOL_MAX_SIZE", 4).max(1);
+
+        PoolConfig {
+            min_size: env_or("BROWSER_POOL_MIN_SIZE", 1).clamp(1, max_size),
+            max_size,
+            idle_timeout: Duration::from_secs(env_or("BROWSER_POOL_IDLE_TIMEOUT_SECONDS", 300)),
+            max_uses: env_or("BROWSER_POOL_MAX_USES", 100).max(1),
+            queue_timeout: Duration::from_secs(env_or("BROWSER_POOL_QUEUE_TIMEOUT_SECONDS", 30)),
+            max_queue: env_or("BROWSER_POOL_MAX_QUEUE", 16),
+        }
+    }
+}
+
+/// Reasons a session could not be checked out
+#[derive(Debug)]
+pub enum PoolError {
+    /// too many requests are already waiting for a session
+    Saturated,
+    /// no session became free within the queue timeout
+    Timeout,
+    /// a new session could not be started
+    Session(String),
+}
+
+impl fmt::Display for PoolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PoolError::Saturated => write!(f, "Browser is busy, too many requests are queued"),
+            PoolError::Timeout => write!(f, "Browser is busy, timed out waiting for a session"),
+            PoolError::Session(e) => write!(f, "Failed to create browser session: {}", e),
+        }
+    }
+}
+
+struct Session {
+    driver: WebDriver,
+    uses: u32,
+    idle_since: Instant,
+}
+
+impl Session {
+    fn new(driver: WebDriver) -> Self {
+        Session {
+            driver,
+            uses: 0,
+            idle_since: Instant::now(),
+        }
+    }
+}
+
+struct Inner {
+    config: &'static PoolConfig,
+    /// one permit per session that may be in use, tokio hands them out in FIFO order
+    permits: Arc<Semaphore>,
+    idle: Mutex<Vec<Session>>,
+    open: AtomicUsize,
+    waiting: AtomicUsize,
+    checkouts: AtomicU64,
+    timeouts: AtomicU64,
+    rejected: AtomicU64,
+    retired: AtomicU64,
+    closed: AtomicBool,
+}
+
+/// Pool of WebDriver sessions shared by the browser endpoints
+#[derive(Clone)]
+pub struct SessionPool {
+    inner: Arc<Inner>,
+}
+
+/// Session checked out of the pool, returned to it when dropped
+pub struct PooledSession {
+    session: Option<Session>,
+    pool: SessionPool,
+    broken: AtomicBool,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl SessionPool {
+    /// Create the pool and open its minimum number of sessions, the reaper keeps retrying when the
+    /// driver is not reachable yet
+    pub async fn new(config: &'static PoolConfig) -> Self {
+        let pool = SessionPool {
+            inner: Arc::new(Inner {
+                config,
+                permits: Arc::new(Semaphore::new(config.max_size)),
+                idle: Mutex::new(Vec::with_capacity(config.max_size)),
+                open: AtomicUsize::new(0),
+                waiting: AtomicUsize::new(0),
+                checkouts: AtomicU64::new(0),
+                timeouts: AtomicU64::new(0),
+                rejected: AtomicU64::new(0),
+                retired: AtomicU64::new(0),
+                closed: AtomicBool::new(false),
+            }),
+        };
+
+        for _ in 0..config.min_size {
+            match pool.connect().await {
+                Ok(driver) => pool.release(Session::new(driver)),
+                Err(e) => {
+                    warn!(error=?e, "Browser is not reachable, starting without sessions");
+                    break;
+                }
+            }
+        }
+
+        pool
+    }
+
+    /// Wait for a free session, queueing behind earlier requests
+    pub async fn checkout(&self) -> Result<PooledSession, PoolError> {
+        let config = self.inner.config;
+
+        if self.inner.permits.available_permits() == 0
+            && self.inner.waiting.load(Ordering::SeqCst) >= config.max_queue
+        {
+            self.inner.rejected.fetch_add(1, Ordering::Relaxed);
+            return Err(PoolError::Saturated);
+        }
+
+        self.inner.waiting.fetch_add(1, Ordering::SeqCst);
+        let permit = tokio::time::timeout(
+            config.queue_timeout,
+            self.inner.permits.clone().acquire_owned(),
+        )
+        .await;
+        self.inner.waiting.fetch_sub(1, Ordering::SeqCst);
+
+        match permit {
+            Ok(Ok(permit)) => self.session(permit).await,
+            Ok(Err(_)) => Err(PoolError::Session("Browser pool is closed".to_string())),
+            Err(_) => {
+                self.inner.timeouts.fetch_add(1, Ordering::Relaxed);
+                Err(PoolError::Timeout)
+            }
+        }
+    }
+
+    /// Check out a session only if one is free right now
+    pub async fn try_checkout(&self) -> Option<Result<PooledSession, PoolError>> {
+        match self.inner.permits.clone().try_acquire_owned() {
+            Ok(permit) => Some(self.session(permit).await),
+            Err(_) => None,
+        }
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        let config = self.inner.config;
+        let idle = self.inner.idle.lock().unwrap().len();
+
+        PoolStats {
+            open: self.inner.open.load(Ordering::SeqCst),
+            idle,
+            in_use: config.max_size - self.inner.permits.available_permits(),
+            waiting: self.inner.waiting.load(Ordering::SeqCst),
+            min_size: config.min_size,
+            max_size: config.max_size,
+            checkouts: self.inner.checkouts.load(Ordering::Relaxed),
+            timeouts: self.inner.timeouts.load(Ordering::Relaxed),
+            rejected: self.inner.rejected.load(Ordering::Relaxed),
+            retired: self.inner.retired.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Close sessions idle for longer than the idle timeout, close tabs leaked by failed requests
+    /// and reopen up to the minimum size
+    pub async fn run_reaper(&self, worker: WorkerHandle) {
+        while !worker.is_cancelled() {
+            self.reap();
+            self.sweep().await;
+
+            while self.inner.open.load(Ordering::SeqCst) < self.inner.config.min_size {
+                // hold a permit while connecting so the pool never exceeds its maximum size
+                let _permit = match self.inner.permits.clone().try_acquire_owned() {
+                    Ok(permit) => permit,
+                    Err(_) => break,
+                };
+
+                match self.connect().await {
+                    Ok(driver) => self.release(Session::new(driver)),
+                    Err(e) => {
+                        error!(error=?e, "Failed to top up the browser pool");
+                        worker.error(&e);
+                        break;
+                    }
+                }
+            }
+
+            worker.tick();
+            tokio::select! {
+                _ = tokio::time::sleep(REAP_INTERVAL) => {}
+                _ = worker.cancelled() => {}
+            }
+        }
+    }
+
+    /// Quit every idle session, sessions in use are quit when they are returned
+    pub async fn close(&self) {
+        self.inner.closed.store(true, Ordering::SeqCst);
+        self.inner.permits.close();
+
+        let sessions: Vec<Session> = self.inner.idle.lock().unwrap().drain(..).collect();
+        for session in sessions {
+            self.inner.open.fetch_sub(1, Ordering::SeqCst);
+            if let Err(e) = session.driver.quit().await {
+                warn!(error=?e, "Failed to quit browser session");
+            }
+        }
+    }
+
+    async fn session(&self, permit: OwnedSemaphorePermit) -> Result<PooledSession, PoolError> {
+        let idle = self.inner.idle.lock().unwrap().pop();
+
+        let mut session = match idle {
+            Some(session) => session,
+            None => Session::new(self.connect().await.map_err(PoolError::Session)?),
+        };
+        session.uses += 1;
+        self.inner.checkouts.fetch_add(1, Ordering::Relaxed);
+
+        Ok(PooledSession {
+            session: Some(session),
+            pool: self.clone(),
+            broken: AtomicBool::new(false),
+            _permit: permit,
+        })
+    }
+
+    async fn connect(&self) -> Result<WebDriver, String> {
+        let mut caps = DesiredCapabilities::chrome();
+        caps.set_headless().unwrap();
+        caps.set_ignore_certificate_errors().unwrap();
+        caps.set_no_sandbox().unwrap();
+        caps.set_disable_gpu().unwrap();
+        caps.set_disable_dev_shm_usage().unwrap();
+
+        match WebDriver::new(utils::CHROME_DRIVER_ENDPOINT.as_str(), caps).await {
+            Ok(driver) => {
+                self.inner.open.fetch_add(1, Ordering::SeqCst);
+                info!("Opened browser session");
+                Ok(driver)
+            }
+            Err(e) => Err(format!("{:?}", e)),
+        }
+    }
+
+    fn release(&self, mut session: Session) {
+        if self.inner.closed.load(Ordering::SeqCst) || session.uses >= self.inner.config.max_uses {
+            self.retire(session.driver);
+            return;
+        }
+
+        session.idle_since = Instant::now();
+        self.inner.idle.lock().unwrap().push(session);
+    }
+
+    fn reap(&self) {
+        let config = self.inner.config;
+        let mut expired = Vec::new();
+
+        {
+            let mut idle = self.inner.idle.lock().unwrap();
+            let mut open = self.inner.open.load(Ordering::SeqCst);
+            let mut index = 0;
+            while index < idle.len() {
+                if open > config.min_size && idle[index].idle_since.elapsed() >= config.idle_timeout
+                {
+                    expired.push(idle.remove(index));
+                    open -= 1;
+                } else {
+                    index += 1;
+                }
+            }
+        }
+
+        for session in expired {
+            self.retire(session.driver);
+        }
+    }
+
+    /// Close every tab but the first of the idle sessions, tabs leak when a request fails to clean
+    /// up after itself, sessions that don't respond are replaced
+    async fn sweep(&self) {
+        let idle = self.inner.idle.lock().unwrap().len();
+
+        for _ in 0..idle {
+            // hold a permit so requests don't count on the session while it is swept
+            let _permit = match self.inner.permits.clone().try_acquire_owned() {
+                Ok(permit) => permit,
+                Err(_) => break,
+            };
+            let session = {
+                let mut idle = self.inner.idle.lock().unwrap();
+                match idle.is_empty() {
+                    true => break,
+                    false => idle.remove(0),
+                }
+            };
+
+            match close_leaked_tabs(&session.driver).await {
+                // keep idle_since, sweeping doesn't count as a use
+                Ok(_) => self.inner.idle.lock().unwrap().push(session),
+                Err(e) => {
+                    warn!(error=?e, "Browser session is not responding, replacing it");
+                    self.retire(session.driver);
+                }
+            }
+        }
+    }
+
+    fn retire(&self, driver: WebDriver) {
+        self.inner.open.fetch_sub(1, Ordering::SeqCst);
+        self.inner.retired.fetch_add(1, Ordering::Relaxed);
+
+        tokio::spawn(async move {
+            if let Err(e) = driver.quit().await {
+                warn!(error=?e, "Failed to quit browser session");
+            }
+        });
+    }
+}
+
+impl PooledSession {
+    /// Close the session instead of returning it to the pool
+    pub fn discard(&self) {
+        self.broken.store(true, Ordering::SeqCst);
+    }
+
+    /// Replace a dead session with a new one
+    pub async fn renew(&mut self) -> Result<(), PoolError> {
+        let driver = self.pool.connect().await.map_err(PoolError::Session)?;
+
+        let mut session = Session::new(driver);
+        session.uses = 1;
+        if let Some(dead) = self.session.replace(session) {
+            self.pool.retire(dead.driver);
+        }
+        self.broken.store(false, Ordering::SeqCst);
+
+        Ok(())
+    }
+}
+
+impl Deref for PooledSession {
+    type Target = WebDriver;
+
+    fn deref(&self) -> &WebDriver {
+        &self.session.as_ref().unwrap().driver
+    }
+}
+
+impl Drop for PooledSession {
+    fn drop(&mut self) {
+        // runs before the permit is released, so the next request finds the session idle
+        if let Some(session) = self.session.take() {
+            match self.broken.load(Ordering::SeqCst) {
+                true => self.pool.retire(session.driver),
+                false => self.pool.release(session),
+            }
+        }
+    }
+}
+
+/// Whether the error means the session is gone, the driver restarted or the session timed out
+pub fn is_dead_session(error: &WebDriverError) -> bool {
+    matches!(
+        error,
+        WebDriverError::InvalidSessionId(_)
+            | WebDriverError::SessionNotCreated(_)
+            | WebDriverError::HttpError(_)
+            | WebDriverError::CommandSendError(_)
+            | WebDriverError::CommandRecvError(_)
+    )
+}
+
+async fn close_leaked_tabs(driver: &WebDriver) -> WebDriverResult<()> {
+    let windows = driver.windows().await?;
+    let (first, leaked) = match windows.split_first() {
+        Some(windows) => windows,
+        None => return Ok(()),
+    };
+
+    for window in leaked {
+        driver.switch_to_window(window.clone()).await?;
+        driver.close_window().await?;
+    }
+    if !leaked.is_empty() {
+        info!(tabs = leaked.len(), "Closed leaked browser tabs");
+        driver.switch_to_window(first.clone()).await?;
+    }
+
+    Ok(())
+}
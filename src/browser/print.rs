@@ -0,0 +1,209 @@
+use super::model::{Orientation, PageSize};
+use base64::{engine::general_purpose, Engine as _};
+use serde_json::{json, Value};
+use thirtyfour::{
+    common::command::{Command, ExtensionCommand},
+    error::{WebDriverError, WebDriverResult},
+    RequestMethod, WebDriver,
+};
+
+/// WebDriver Print Page command (https://w3c.github.io/webdriver/#print-page)
+#[derive(Debug)]
+struct PrintPage {
+    parameters: Value,
+}
+
+impl ExtensionCommand for PrintPage {
+    fn parameters_json(&self) -> Option<Value> {
+        Some(self.parameters.clone())
+    }
+
+    fn method(&self) -> RequestMethod {
+        RequestMethod::Post
+    }
+
+    fn endpoint(&self) -> String {
+        "/print".to_string()
+    }
+}
+
+/// Layout of the printed document, lengths are in centimetres
+#[derive(Debug)]
+pub struct PrintOptions {
+    page_size: PageSize,
+    orientation: Orientation,
+    /// top, right, bottom and left margins
+    margins: [f64; 4],
+    scale: f64,
+    background: bool,
+    page_ranges: Vec<String>,
+}
+
+impl PrintOptions {
+    pub fn new(
+        page_size: Option<PageSize>,
+        orientation: Option<Orientation>,
+        margins: Option<&str>,
+        scale: Option<f64>,
+        background: Option<bool>,
+        page_ranges: Option<&str>,
+    ) -> Result<Self, String> {
+        let scale = scale.unwrap_or(1.0);
+        if !(0.1..=2.0).contains(&scale) {
+            return Err("Invalid scale: must be between 0.1 and 2".to_string());
+        }
+
+        Ok(PrintOptions {
+            page_size: page_size.unwrap_or(PageSize::A4),
+            orientation: orientation.unwrap_or(Orientation::Portrait),
+            margins: match margins {
+                Some(margins) => parse_margins(margins)?,
+                None => [1.0; 4],
+            },
+            scale,
+            background: background.unwrap_or(false),
+            page_ranges: match page_ranges {
+                Some(page_ranges) => parse_page_ranges(page_ranges)?,
+                None => vec![],
+            },
+        })
+    }
+
+    fn parameters(&self) -> Value {
+        let (width, height) = match self.page_size {
+            PageSize::A3 => (29.7, 42.0),
+            PageSize::A4 => (21.0, 29.7),
+            PageSize::A5 => (14.8, 21.0),
+            PageSize::Letter => (21.59, 27.94),
+            PageSize::Legal => (21.59, 35.56),
+            PageSize::Tabloid => (27.94, 43.18),
+        };
+        let [top, right, bottom, left] = self.margins;
+
+        json!({
+            "orientation": match self.orientation {
+                Orientation::Portrait => "portrait",
+                Orientation::Landscape => "landscape",
+            },
+            "scale": self.scale,
+            "background": self.background,
+            "page": { "width": width, "height": height },
+            "margin": { "top": top, "right": right, "bottom": bottom, "left": left },
+            "pageRanges": self.page_ranges,
+        })
+    }
+}
+
+/// Print the current page to a PDF
+pub async fn print_pdf(driver: &WebDriver, options: &PrintOptions) -> WebDriverResult<Vec<u8>> {
+    let command = PrintPage {
+        parameters: options.parameters(),
+    };
+    let pdf: String = driver
+        .cmd(Command::ExtensionCommand(Box::new(command)))
+        .await?
+        .value()?;
+
+    general_purpose::STANDARD
+        .decode(pdf)
+        .map_err(|e| WebDriverError::Json(format!("Failed to decode the PDF: {}", e)))
+}
+
+/// CSS like shorthand: `all`, `vertical,horizontal` or `top,right,bottom,left`
+fn parse_margins(margins: &str) -> Result<[f64; 4], String> {
+    let values: Vec<f64> = match margins
+        .split(',')
+        .map(|margin| margin.trim().parse::<f64>())
+        .collect()
+    {
+        Ok(values) => values,
+        Err(_) => return Err(format!("Invalid margins: {}", margins)),
+    };
+
+    if values.iter().any(|margin| !(0.0..=10.0).contains(margin)) {
+        return Err("Invalid margins: must be between 0 and 10 cm".to_string());
+    }
+
+    match values[..] {
+        [all] => Ok([all; 4]),
+        [vertical, horizontal] => Ok([vertical, horizontal, vertical, horizontal]),
+        [top, right, bottom, left] => Ok([top, right, bottom, left]),
+        _ => Err(format!("Invalid margins: {}", margins)),
+    }
+}
+
+/// Comma separated pages or ranges, e.g. `1-3,5`
+fn parse_page_ranges(page_ranges: &str) -> Result<Vec<String>, String> {
+    page_ranges
+        .split(',')
+        .map(|range| {
+            let range = range.trim();
+            let valid = match range.split_once('-') {
+                Some((start, end)) => match (start.parse::<u32>(), end.parse::<u32>()) {
+                    (Ok(start), Ok(end)) => start >= 1 && start <= end,
+                    _ => false,
+                },
+                None => range.parse::<u32>().map(|page| page >= 1).unwrap_or(false),
+            };
+
+            match valid {
+                true => Ok(range.to_string()),
+                false => Err(format!("Invalid page range: {}", range)),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn margins_shorthand() {
+        assert_eq!(parse_margins("2"), Ok([2.0; 4]));
+        assert_eq!(parse_margins("1, 0.5"), Ok([1.0, 0.5, 1.0, 0.5]));
+        assert_eq!(parse_margins("1,2,3,4"), Ok([1.0, 2.0, 3.0, 4.0]));
+        assert!(parse_margins("1,2,3").is_err());
+        assert!(parse_margins("1,2,3,4,5").is_err());
+    }
+
+    #[test]
+    fn margins_out_of_range() {
+        assert!(parse_margins("0").is_ok());
+        assert!(parse_margins("10").is_ok());
+        assert!(parse_margins("10.5").is_err());
+        assert!(parse_margins("-1").is_err());
+        assert!(parse_margins("NaN").is_err());
+        assert!(parse_margins("inf").is_err());
+        assert!(parse_margins("one").is_err());
+        assert!(parse_margins("").is_err());
+    }
+
+    #[test]
+    fn page_ranges() {
+        assert_eq!(
+            parse_page_ranges("1-3, 5"),
+            Ok(vec!["1-3".to_string(), "5".to_string()])
+        );
+        assert_eq!(parse_page_ranges("2-2"), Ok(vec!["2-2".to_string()]));
+        assert!(parse_page_ranges("3-1").is_err());
+        assert!(parse_page_ranges("0").is_err());
+        assert!(parse_page_ranges("0-2").is_err());
+        assert!(parse_page_ranges("1-").is_err());
+        assert!(parse_page_ranges("1,,2").is_err());
+        assert!(parse_page_ranges("a").is_err());
+    }
+
+    #[test]
+    fn options_defaults_and_scale() {
+        let options = PrintOptions::new(None, None, None, None, None, None).unwrap();
+        assert_eq!(options.margins, [1.0; 4]);
+        assert_eq!(options.scale, 1.0);
+        assert!(options.page_ranges.is_empty());
+
+        assert!(PrintOptions::new(None, None, None, Some(0.05), None, None).is_err());
+        assert!(PrintOptions::new(None, None, None, Some(2.5), None, None).is_err());
+        assert!(PrintOptions::new(None, None, Some("11"), None, None, None).is_err());
+        assert!(PrintOptions::new(None, None, None, None, None, Some("3-1")).is_err());
+    }
+}
@@ -0,0 +1,299 @@
+use super::model::{Device, ImageFormat};
+use base64::{engine::general_purpose, Engine as _};
+use serde_json::{json, Value};
+use thirtyfour::{
+    error::{WebDriverError, WebDriverErrorInfo, WebDriverResult},
+    extensions::cdp::ChromeDevTools,
+    WebDriver,
+};
+
+/// Size and device the page is rendered for
+#[derive(Debug)]
+pub struct Viewport {
+    width: u32,
+    height: u32,
+    device_scale_factor: f64,
+    mobile: bool,
+    user_agent: Option<&'static str>,
+}
+
+impl Viewport {
+    /// Emulate `device`, with `width`, `height` and `device_scale_factor` overriding its metrics,
+    /// `None` keeps the browser's default viewport
+    pub fn new(
+        device: Option<Device>,
+        width: Option<u32>,
+        height: Option<u32>,
+        device_scale_factor: Option<f64>,
+    ) -> Result<Option<Self>, String> {
+        let mut viewport = match device {
+            Some(Device::Iphone15) => Viewport {
+                width: 393,
+                height: 852,
+                device_scale_factor: 3.0,
+                mobile: true,
+                user_agent: Some("Mozilla/5.0 (iPhone; CPU iPhone OS 17_0 like Mac OS X) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/17.0 Mobile/15E148 Safari/604.1"),
+            },
+            Some(Device::Pixel7) => Viewport {
+                width: 412,
+                height: 915,
+                device_scale_factor: 2.625,
+                mobile: true,
+                user_agent: Some("Mozilla/5.0 (Linux; Android 14; Pixel 7) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Mobile Safari/537.36"),
+            },
+            Some(Device::Ipad) => Viewport {
+                width: 820,
+                height: 1180,
+                device_scale_factor: 2.0,
+                mobile: true,
+                user_agent: Some("Mozilla/5.0 (iPad; CPU OS 17_0 like Mac OS X) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/17.0 Mobile/15E148 Safari/604.1"),
+            },
+            None if width.is_none() && height.is_none() && device_scale_factor.is_none() => {
+                return Ok(None)
+            }
+            None => Viewport {
+                width: 1280,
+                height: 800,
+                device_scale_factor: 1.0,
+                mobile: false,
+                user_agent: None,
+            },
+        };
+
+        if let Some(width) = width {
+            viewport.width = width;
+        }
+        if let Some(height) = height {
+            viewport.height = height;
+        }
+        if let Some(device_scale_factor) = device_scale_factor {
+            viewport.device_scale_factor = device_scale_factor;
+        }
+
+        if !(100..=3840).contains(&viewport.width) || !(100..=3840).contains(&viewport.height) {
+            return Err(
+                "Invalid viewport: width and height must be between 100 and 3840".to_string(),
+            );
+        }
+        if !(0.5..=4.0).contains(&viewport.device_scale_factor) {
+            return Err(
+                "Invalid viewport: device_scale_factor must be between 0.5 and 4".to_string(),
+            );
+        }
+
+        Ok(Some(viewport))
+    }
+
+    /// Apply to the current tab, before navigating so the page is laid out for it
+    pub async fn apply(&self, driver: &WebDriver) -> WebDriverResult<()> {
+        let dev_tools = ChromeDevTools::new(driver.handle.clone());
+
+        dev_tools
+            .execute_cdp_with_params(
+                "Emulation.setDeviceMetricsOverride",
+                json!({
+                    "width": self.width,
+                    "height": self.height,
+                    "deviceScaleFactor": self.device_scale_factor,
+                    "mobile": self.mobile,
+                }),
+            )
+            .await?;
+        dev_tools
+            .execute_cdp_with_params(
+                "Emulation.setTouchEmulationEnabled",
+                json!({ "enabled": self.mobile }),
+            )
+            .await?;
+        if let Some(user_agent) = self.user_agent {
+            dev_tools
+                .execute_cdp_with_params(
+                    "Emulation.setUserAgentOverride",
+                    json!({ "userAgent": user_agent }),
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// What part of the page is captured and how it is encoded
+#[derive(Debug)]
+pub struct Capture {
+    pub format: ImageFormat,
+    quality: Option<u8>,
+    full_page: bool,
+    selector: Option<String>,
+}
+
+impl Capture {
+    pub fn new(
+        format: Option<ImageFormat>,
+        quality: Option<u8>,
+        full_page: Option<bool>,
+        selector: Option<String>,
+    ) -> Result<Self, String> {
+        let format = format.unwrap_or(ImageFormat::Png);
+
+        if let Some(quality) = quality {
+            if format == ImageFormat::Png {
+                return Err("Invalid quality: only jpeg and webp support a quality".to_string());
+            }
+            if quality > 100 {
+                return Err("Invalid quality: must be between 0 and 100".to_string());
+            }
+        }
+
+        Ok(Capture {
+            format,
+            quality,
+            full_page: full_page.unwrap_or(false),
+            selector,
+        })
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self.format {
+            ImageFormat::Png => "image/png",
+            ImageFormat::Jpeg => "image/jpeg",
+            ImageFormat::Webp => "image/webp",
+        }
+    }
+
+    /// Screenshot of the viewport, the whole page or the element matching the selector
+    pub async fn screenshot(&self, driver: &WebDriver) -> WebDriverResult<Vec<u8>> {
+        let dev_tools = ChromeDevTools::new(driver.handle.clone());
+
+        let mut params = json!({
+            "format": match self.format {
+                ImageFormat::Png => "png",
+                ImageFormat::Jpeg => "jpeg",
+                ImageFormat::Webp => "webp",
+            },
+        });
+        if let Some(quality) = self.quality {
+            params["quality"] = json!(quality);
+        }
+
+        let clip = match (&self.selector, self.full_page) {
+            (Some(selector), _) => Some(element_clip(driver, selector).await?),
+            (None, true) => {
+                let metrics = dev_tools.execute_cdp("Page.getLayoutMetrics").await?;
+                let size = match metrics.get("cssContentSize") {
+                    Some(size) => size,
+                    None => &metrics["contentSize"],
+                };
+                Some(json!({
+                    "x": 0,
+                    "y": 0,
+                    "width": size["width"],
+                    "height": size["height"],
+                    "scale": 1,
+                }))
+            }
+            (None, false) => None,
+        };
+        if let Some(clip) = clip {
+            params["clip"] = clip;
+            params["captureBeyondViewport"] = json!(true);
+        }
+
+        let screenshot = dev_tools
+            .execute_cdp_with_params("Page.captureScreenshot", params)
+            .await?;
+        let data = screenshot["data"].as_str().unwrap_or_default();
+
+        general_purpose::STANDARD
+            .decode(data)
+            .map_err(|e| WebDriverError::Json(format!("Failed to decode the screenshot: {}", e)))
+    }
+}
+
+/// Area of the element in page coordinates
+async fn element_clip(driver: &WebDriver, selector: &str) -> WebDriverResult<Value> {
+    let rect = driver
+        .execute(
+            "const element = document.querySelector(arguments[0]);
+            if (!element) return null;
+            const rect = element.getBoundingClientRect();
+            return [rect.left + window.scrollX, rect.top + window.scrollY, rect.width, rect.height];",
+            vec![json!(selector)],
+        )
+        .await?;
+
+    match rect.json().as_array() {
+        Some(rect) if rect.len() == 4 => Ok(json!({
+            "x": rect[0],
+            "y": rect[1],
+            "width": rect[2],
+            "height": rect[3],
+            "scale": 1,
+        })),
+        _ => Err(WebDriverError::NoSuchElement(WebDriverErrorInfo::new(
+            format!("No element matches {}", selector),
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_viewport() {
+        assert!(Viewport::new(None, None, None, None).unwrap().is_none());
+
+        let viewport = Viewport::new(None, Some(800), None, None).unwrap().unwrap();
+        assert_eq!((viewport.width, viewport.height), (800, 800));
+        assert_eq!(viewport.device_scale_factor, 1.0);
+        assert!(!viewport.mobile);
+        assert!(viewport.user_agent.is_none());
+    }
+
+    #[test]
+    fn device_metrics_are_overridden() {
+        let viewport = Viewport::new(Some(Device::Pixel7), None, None, None)
+            .unwrap()
+            .unwrap();
+        assert_eq!((viewport.width, viewport.height), (412, 915));
+        assert_eq!(viewport.device_scale_factor, 2.625);
+        assert!(viewport.mobile);
+
+        let viewport = Viewport::new(Some(Device::Iphone15), Some(430), Some(932), Some(2.0))
+            .unwrap()
+            .unwrap();
+        assert_eq!((viewport.width, viewport.height), (430, 932));
+        assert_eq!(viewport.device_scale_factor, 2.0);
+        assert!(viewport.mobile);
+        assert!(viewport.user_agent.unwrap().contains("iPhone"));
+    }
+
+    #[test]
+    fn viewport_bounds() {
+        assert!(Viewport::new(None, Some(100), Some(3840), None).is_ok());
+        assert!(Viewport::new(None, Some(99), None, None).is_err());
+        assert!(Viewport::new(None, None, Some(3841), None).is_err());
+        assert!(Viewport::new(Some(Device::Ipad), Some(5000), None, None).is_err());
+
+        assert!(Viewport::new(None, None, None, Some(0.5)).is_ok());
+        assert!(Viewport::new(None, None, None, Some(4.0)).is_ok());
+        assert!(Viewport::new(None, None, None, Some(0.4)).is_err());
+        assert!(Viewport::new(None, None, None, Some(4.5)).is_err());
+        assert!(Viewport::new(None, None, None, Some(f64::NAN)).is_err());
+    }
+
+    #[test]
+    fn capture_quality() {
+        let capture = Capture::new(None, None, None, None).unwrap();
+        assert!(capture.format == ImageFormat::Png);
+        assert_eq!(capture.content_type(), "image/png");
+        assert!(!capture.full_page);
+
+        assert!(Capture::new(None, Some(80), None, None).is_err());
+        assert!(Capture::new(Some(ImageFormat::Png), Some(80), None, None).is_err());
+        assert!(Capture::new(Some(ImageFormat::Jpeg), Some(80), None, None).is_ok());
+        assert!(Capture::new(Some(ImageFormat::Webp), Some(100), None, None).is_ok());
+        assert!(Capture::new(Some(ImageFormat::Jpeg), Some(101), None, None).is_err());
+    }
+}
@@ -0,0 +1,279 @@
+use serde_json::{json, Value};
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+use thirtyfour::{
+    error::{WebDriverError, WebDriverResult},
+    extensions::cdp::ChromeDevTools,
+    WebDriver,
+};
+use tokio::time::sleep;
+
+/// how often a condition is checked
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+const DEFAULT_TIMEOUT_MS: u64 = 10_000;
+const MAX_TIMEOUT_MS: u64 = 60_000;
+const MAX_NETWORK_IDLE_MS: u64 = 10_000;
+const MAX_DELAY_MS: u64 = 30_000;
+
+/// counts fetch and XHR requests, installed before the page's own scripts run so requests started
+/// while loading are counted too
+const IN_FLIGHT_COUNTER: &str = r#"
+if (!window.__toolkitInFlight) {
+    const state = { count: 0 };
+    window.__toolkitInFlight = state;
+    const fetch = window.fetch;
+    window.fetch = function (...args) {
+        state.count++;
+        return fetch.apply(this, args).finally(() => state.count--);
+    };
+    const send = XMLHttpRequest.prototype.send;
+    XMLHttpRequest.prototype.send = function (...args) {
+        state.count++;
+        this.addEventListener('loadend', () => state.count--);
+        return send.apply(this, args);
+    };
+}
+"#;
+
+/// in flight requests and the number of finished resources
+const IN_FLIGHT_SCRIPT: &str =
+    "return [window.__toolkitInFlight.count, performance.getEntriesByType('resource').length];";
+
+/// Condition a page has to meet before it is read
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition {
+    /// an element matches the CSS selector
+    Selector(String),
+    /// an element matches the XPath expression
+    XPath(String),
+    /// the body contains the text
+    Text(String),
+    /// no requests were in flight for the number of milliseconds
+    NetworkIdle(u64),
+    /// document.readyState reached `interactive` or `complete`
+    ReadyState(String),
+    /// the JavaScript function body returns a truthy value
+    Script(String),
+}
+
+/// How long to wait for a page before reading it
+#[derive(Debug, Clone)]
+pub struct Wait {
+    condition: Condition,
+    timeout: Duration,
+    delay: Duration,
+}
+
+/// Reasons a page did not become ready
+#[derive(Debug)]
+pub enum WaitError {
+    /// the condition wasn't met within the timeout
+    Timeout(String),
+    /// the condition script threw
+    Script(String),
+    /// the condition could not be checked
+    Failed(String),
+}
+
+impl fmt::Display for WaitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WaitError::Timeout(e) | WaitError::Script(e) | WaitError::Failed(e) => {
+                write!(f, "{}", e)
+            }
+        }
+    }
+}
+
+impl Default for Wait {
+    fn default() -> Self {
+        Wait {
+            condition: Condition::ReadyState("complete".to_string()),
+            timeout: Duration::from_millis(DEFAULT_TIMEOUT_MS),
+            delay: Duration::ZERO,
+        }
+    }
+}
+
+impl Condition {
+    /// Parse `kind:argument`, e.g. `selector:#content` or `network_idle:500`
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let (kind, argument) = match spec.split_once(':') {
+            Some((kind, argument)) if !argument.trim().is_empty() => (kind.trim(), argument),
+            _ => return Err(format!("Invalid wait condition: {}", spec)),
+        };
+
+        match kind {
+            "selector" => Ok(Condition::Selector(argument.to_string())),
+            "xpath" => Ok(Condition::XPath(argument.to_string())),
+            "text" => Ok(Condition::Text(argument.to_string())),
+            "network_idle" => match argument.trim().parse::<u64>() {
+                Ok(ms) if (1..=MAX_NETWORK_IDLE_MS).contains(&ms) => Ok(Condition::NetworkIdle(ms)),
+                _ => Err(format!(
+                    "Invalid wait condition: network_idle must be between 1 and {} ms",
+                    MAX_NETWORK_IDLE_MS
+                )),
+            },
+            "ready_state" => match argument.trim() {
+                "interactive" | "complete" => {
+                    Ok(Condition::ReadyState(argument.trim().to_string()))
+                }
+                _ => Err(
+                    "Invalid wait condition: ready_state must be interactive or complete"
+                        .to_string(),
+                ),
+            },
+            "script" => Ok(Condition::Script(argument.to_string())),
+            _ => Err(format!("Invalid wait condition: unknown kind {}", kind)),
+        }
+    }
+
+    /// Script returning whether the condition is met, and its arguments
+    fn script(&self) -> (String, Vec<Value>) {
+        match self {
+            Condition::Selector(selector) => (
+                "return document.querySelector(arguments[0]) !== null;".to_string(),
+                vec![json!(selector)],
+            ),
+            Condition::XPath(xpath) => (
+                "return document.evaluate(arguments[0], document, null, XPathResult.FIRST_ORDERED_NODE_TYPE, null).singleNodeValue !== null;".to_string(),
+                vec![json!(xpath)],
+            ),
+            Condition::Text(text) => (
+                "return !!document.body && document.body.innerText.includes(arguments[0]);".to_string(),
+                vec![json!(text)],
+            ),
+            Condition::ReadyState(state) => (
+                "return document.readyState === 'complete' || document.readyState === arguments[0];".to_string(),
+                vec![json!(state)],
+            ),
+            Condition::Script(script) => (script.clone(), vec![]),
+            // the counter is installed again in case the page was loaded without it
+            Condition::NetworkIdle(_) => {
+                (format!("{}\n{}", IN_FLIGHT_COUNTER, IN_FLIGHT_SCRIPT), vec![])
+            }
+        }
+    }
+}
+
+impl Wait {
+    /// Wait for `wait_for` (ready_state:complete when not set) for up to `timeout_ms`, then
+    /// `delay_ms` more
+    pub fn new(
+        wait_for: Option<&str>,
+        timeout_ms: Option<u64>,
+        delay_ms: Option<u64>,
+    ) -> Result<Self, String> {
+        let mut wait = Wait::default();
+
+        if let Some(wait_for) = wait_for {
+            wait.condition = Condition::parse(wait_for)?;
+        }
+        if let Some(timeout_ms) = timeout_ms {
+            if !(1..=MAX_TIMEOUT_MS).contains(&timeout_ms) {
+                return Err(format!(
+                    "Invalid wait timeout: must be between 1 and {} ms",
+                    MAX_TIMEOUT_MS
+                ));
+            }
+            wait.timeout = Duration::from_millis(timeout_ms);
+        }
+        if let Some(delay_ms) = delay_ms {
+            if delay_ms > MAX_DELAY_MS {
+                return Err(format!(
+                    "Invalid delay: must be at most {} ms",
+                    MAX_DELAY_MS
+                ));
+            }
+            wait.delay = Duration::from_millis(delay_ms);
+        }
+
+        Ok(wait)
+    }
+
+    /// Whether the condition runs a caller supplied script, which needs an admin scoped API Key
+    pub fn runs_script(&self) -> bool {
+        matches!(self.condition, Condition::Script(_))
+    }
+
+    /// Prepare the current tab before navigating, network_idle counts requests from the start of
+    /// the page load only when its counter is in place before the page's own scripts run
+    pub async fn prepare(&self, driver: &WebDriver) -> WebDriverResult<()> {
+        if let Condition::NetworkIdle(_) = self.condition {
+            ChromeDevTools::new(driver.handle.clone())
+                .execute_cdp_with_params(
+                    "Page.addScriptToEvaluateOnNewDocument",
+                    json!({ "source": IN_FLIGHT_COUNTER }),
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Poll the condition until it is met or the timeout passes
+    pub async fn until_ready(&self, driver: &WebDriver) -> Result<(), WaitError> {
+        let started = Instant::now();
+        let (script, args) = self.condition.script();
+        // in flight requests and resource count of the last check, and since when they are unchanged
+        let mut network: Option<(Value, Instant)> = None;
+
+        loop {
+            let result = match driver.execute(script.as_str(), args.clone()).await {
+                Ok(r) => r.json().clone(),
+                Err(WebDriverError::JavascriptError(e)) => {
+                    return Err(WaitError::Script(format!(
+                        "Wait condition failed: {}",
+                        e.value.message
+                    )));
+                }
+                Err(e) => {
+                    return Err(WaitError::Failed(format!(
+                        "Failed to check wait condition: {}",
+                        e
+                    )));
+                }
+            };
+
+            let ready = match &self.condition {
+                Condition::NetworkIdle(idle_ms) => {
+                    let in_flight = result.get(0).and_then(Value::as_u64).unwrap_or(0);
+                    let since = match network.take() {
+                        Some((last, since)) if last == result => since,
+                        _ => Instant::now(),
+                    };
+                    network = Some((result, since));
+                    in_flight == 0 && since.elapsed() >= Duration::from_millis(*idle_ms)
+                }
+                _ => is_truthy(&result),
+            };
+
+            if ready {
+                break;
+            }
+            if started.elapsed() >= self.timeout {
+                return Err(WaitError::Timeout(format!(
+                    "Timed out after {} ms waiting for {:?}",
+                    self.timeout.as_millis(),
+                    self.condition
+                )));
+            }
+            sleep(POLL_INTERVAL).await;
+        }
+
+        sleep(self.delay).await;
+        Ok(())
+    }
+}
+
+fn is_truthy(value: &Value) -> bool {
+    match value {
+        Value::Null => false,
+        Value::Bool(b) => *b,
+        Value::Number(n) => n.as_f64().map(|n| n != 0.0).unwrap_or(false),
+        Value::String(s) => !s.is_empty(),
+        Value::Array(_) | Value::Object(_) => true,
+    }
+}
@@ -0,0 +1,352 @@
+use super::audit;
+use super::data_source::{resolve_payload, SourceCache};
+use super::handler::update_paused;
+use super::model::{FCMSchedule, ProjectStats, ScheduleAudit};
+use super::worker::{next_delivery_id, record_delivery, send_message, with_occurrence_id};
+use crate::utils::{
+    client_ip, verify_admin_apikey, ApiTags, JsonError, JsonSuccess, ResponseObject,
+};
+use chrono::{Duration, Utc};
+use gcp_auth::AuthenticationManager;
+use poem::{web::Data, Request};
+use poem_openapi::{
+    param::{Path, Query},
+    OpenApi,
+};
+use sqlx::postgres::PgPool;
+use std::{collections::HashMap, sync::Arc};
+use tokio::sync::Notify;
+
+pub struct FirebaseMessagingAdmin {
+    auth_managers: Arc<HashMap<String, AuthenticationManager>>,
+    wake: Arc<Notify>,
+}
+
+#[OpenApi(
+    prefix_path = "/admin/fcm/",
+    request_header(name = "API-Key", ty = "String", description = "Admin API Key"),
+    tag = "ApiTags::Admin"
+)]
+impl FirebaseMessagingAdmin {
+    // create new instance
+    pub fn new(
+        auth_managers: Arc<HashMap<String, AuthenticationManager>>,
+        wake: Arc<Notify>,
+    ) -> Self {
+        Self {
+            auth_managers,
+            wake,
+        }
+    }
+
+    // search schedules across users and projects
+    #[oai(
+        path = "/",
+        method = "get",
+        operation_id = "admin::fcm::search_schedules"
+    )]
+    #[allow(clippy::too_many_arguments)]
+    async fn search_schedules(
+        &self,
+        req: &Request,
+        pool: Data<&PgPool>,
+        /// firebase user id of the owner
+        fb_user_id: Query<Option<String>>,
+        /// firebase project id
+        fb_project_id: Query<Option<String>>,
+        /// part of the schedule name (case insensitive)
+        name: Query<Option<String>>,
+        /// whether the schedule is paused
+        paused: Query<Option<bool>>,
+        /// maximum number of schedules to return (default 100)
+        limit: Query<Option<i64>>,
+        /// number of schedules to skip
+        offset: Query<Option<i64>>,
+    ) -> Result<JsonSuccess<Vec<FCMSchedule>>, JsonError<String>> {
+        verify_admin_apikey(req)
+            .await
+            .map_err(ResponseObject::unauthorized)?;
+
+        let schedules = sqlx::query_as!(
+            FCMSchedule,
+            "SELECT * FROM fcm_schedule
+            WHERE ($1::TEXT IS NULL OR fb_user_id = $1)
+            AND ($2::TEXT IS NULL OR fb_project_id = $2)
+            AND ($3::TEXT IS NULL OR name ILIKE '%' || $3 || '%')
+            AND ($4::BOOLEAN IS NULL OR paused = $4)
+            ORDER BY id
+            LIMIT $5 OFFSET $6",
+            fb_user_id.0,
+            fb_project_id.0,
+            name.0,
+            paused.0,
+            limit.0.unwrap_or(100).clamp(1, 1000),
+            offset.0.unwrap_or(0).max(0)
+        )
+        .fetch_all(pool.0)
+        .await;
+
+        match schedules {
+            Ok(schedules) => Ok(ResponseObject::ok(schedules)),
+            Err(e) => Err(ResponseObject::internal_server_error(e)),
+        }
+    }
+
+    // send statistics per firebase project
+    #[oai(
+        path = "/stats",
+        method = "get",
+        operation_id = "admin::fcm::get_stats"
+    )]
+    async fn get_stats(
+        &self,
+        req: &Request,
+        pool: Data<&PgPool>,
+        /// number of days to aggregate sends over (default 1)
+        days: Query<Option<i64>>,
+    ) -> Result<JsonSuccess<Vec<ProjectStats>>, JsonError<String>> {
+        verify_admin_apikey(req)
+            .await
+            .map_err(ResponseObject::unauthorized)?;
+
+        let since = Utc::now() - Duration::days(days.0.unwrap_or(1).clamp(1, 365));
+
+        let stats = sqlx::query_as!(
+            ProjectStats,
+            r#"WITH projects AS (
+                SELECT fb_project_id FROM fcm_schedule
+                UNION SELECT fb_project_id FROM fcm_delivery WHERE created_at > $1
+            )
+            SELECT
+                p.fb_project_id AS "fb_project_id!",
+                (SELECT COUNT(*) FROM fcm_schedule s WHERE s.fb_project_id = p.fb_project_id) AS "schedules!",
+                (SELECT COUNT(*) FROM fcm_schedule s WHERE s.fb_project_id = p.fb_project_id AND s.paused) AS "paused_schedules!",
+                (SELECT COUNT(*) FROM fcm_delivery d WHERE d.fb_project_id = p.fb_project_id AND d.created_at > $1 AND d.status = 'sent') AS "sent!",
+                (SELECT COUNT(*) FROM fcm_delivery d WHERE d.fb_project_id = p.fb_project_id AND d.created_at > $1 AND d.status = 'failed') AS "failed!",
+                (SELECT COUNT(*) FROM fcm_delivery d WHERE d.fb_project_id = p.fb_project_id AND d.created_at > $1 AND d.status = 'throttled') AS "throttled!",
+                (SELECT MAX(d.created_at) FROM fcm_delivery d WHERE d.fb_project_id = p.fb_project_id AND d.status = 'sent') AS last_sent_at
+            FROM projects p
+            ORDER BY p.fb_project_id"#,
+            since
+        )
+        .fetch_all(pool.0)
+        .await;
+
+        match stats {
+            Ok(stats) => Ok(ResponseObject::ok(stats)),
+            Err(e) => Err(ResponseObject::internal_server_error(e)),
+        }
+    }
+
+    // force pause any schedule
+    #[oai(
+        path = "/:id/pause",
+        method = "post",
+        operation_id = "admin::fcm::pause_schedule"
+    )]
+    async fn pause_schedule(
+        &self,
+        req: &Request,
+        pool: Data<&PgPool>,
+        id: Path<i32>,
+    ) -> Result<JsonSuccess<FCMSchedule>, JsonError<String>> {
+        verify_admin_apikey(req)
+            .await
+            .map_err(ResponseObject::unauthorized)?;
+
+        let before = self.find_schedule(pool.0, id.0).await?;
+        let schedule = update_paused(pool.0, before, true, audit::ADMIN, client_ip(req)).await?;
+
+        Ok(ResponseObject::ok(schedule))
+    }
+
+    // resume any paused schedule
+    #[oai(
+        path = "/:id/resume",
+        method = "post",
+        operation_id = "admin::fcm::resume_schedule"
+    )]
+    async fn resume_schedule(
+        &self,
+        req: &Request,
+        pool: Data<&PgPool>,
+        id: Path<i32>,
+    ) -> Result<JsonSuccess<FCMSchedule>, JsonError<String>> {
+        verify_admin_apikey(req)
+            .await
+            .map_err(ResponseObject::unauthorized)?;
+
+        let before = self.find_schedule(pool.0, id.0).await?;
+        let schedule = update_paused(pool.0, before, false, audit::ADMIN, client_ip(req)).await?;
+        self.wake.notify_one();
+
+        Ok(ResponseObject::ok(schedule))
+    }
+
+    // delete any schedule
+    #[oai(
+        path = "/:id",
+        method = "delete",
+        operation_id = "admin::fcm::delete_schedule"
+    )]
+    async fn delete_schedule(
+        &self,
+        req: &Request,
+        pool: Data<&PgPool>,
+        id: Path<i32>,
+    ) -> Result<JsonSuccess<FCMSchedule>, JsonError<String>> {
+        verify_admin_apikey(req)
+            .await
+            .map_err(ResponseObject::unauthorized)?;
+
+        let schedule = self.find_schedule(pool.0, id.0).await?;
+
+        let result = sqlx::query!("DELETE FROM fcm_schedule WHERE id = $1", id.0)
+            .execute(pool.0)
+            .await;
+
+        if let Err(e) = result {
+            return Err(ResponseObject::internal_server_error(e));
+        }
+
+        audit::record(
+            pool.0,
+            audit::ADMIN,
+            "deleted",
+            client_ip(req),
+            Some(&schedule),
+            None,
+        )
+        .await;
+
+        Ok(ResponseObject::ok(schedule))
+    }
+
+    // send the FCM of a schedule right now, without changing its next execution
+    #[oai(
+        path = "/:id/resend",
+        method = "post",
+        operation_id = "admin::fcm::resend_schedule"
+    )]
+    async fn resend_schedule(
+        &self,
+        req: &Request,
+        pool: Data<&PgPool>,
+        id: Path<i32>,
+    ) -> Result<JsonSuccess<FCMSchedule>, JsonError<String>> {
+        verify_admin_apikey(req)
+            .await
+            .map_err(ResponseObject::unauthorized)?;
+
+        let schedule = self.find_schedule(pool.0, id.0).await?;
+
+        let auth_manager = match self.auth_managers.get(&schedule.fb_project_id) {
+            Some(auth_manager) => auth_manager,
+            None => {
+                return Err(ResponseObject::bad_request(
+                    "No service account found for the project",
+                ));
+            }
+        };
+
+        let delivery_id = match next_delivery_id(pool.0).await {
+            Ok(id) => id,
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+
+        let payload = resolve_payload(&schedule, &mut SourceCache::default()).await;
+        let message = FCMSchedule {
+            payload: with_occurrence_id(payload, delivery_id),
+            ..schedule.clone()
+        };
+        let result = send_message(auth_manager, &message).await;
+
+        audit::record(
+            pool.0,
+            audit::ADMIN,
+            "resent",
+            client_ip(req),
+            Some(&schedule),
+            Some(&schedule),
+        )
+        .await;
+
+        match result {
+            Ok(_) => {
+                record_delivery(pool.0, delivery_id, None, 0, &schedule, "sent", None).await;
+                Ok(ResponseObject::ok(schedule))
+            }
+            Err(e) => {
+                record_delivery(
+                    pool.0,
+                    delivery_id,
+                    None,
+                    0,
+                    &schedule,
+                    "failed",
+                    Some(e.to_string()),
+                )
+                .await;
+                Err(ResponseObject::internal_server_error(e))
+            }
+        }
+    }
+
+    // history of changes of any schedule
+    #[oai(
+        path = "/:id/audit",
+        method = "get",
+        operation_id = "admin::fcm::get_schedule_audit"
+    )]
+    async fn get_schedule_audit(
+        &self,
+        req: &Request,
+        pool: Data<&PgPool>,
+        id: Path<i32>,
+        /// maximum number of entries to return (default 100, max 1000)
+        limit: Query<Option<i64>>,
+        /// only return entries older than this entry id, for the next page
+        before_id: Query<Option<i64>>,
+    ) -> Result<JsonSuccess<Vec<ScheduleAudit>>, JsonError<String>> {
+        verify_admin_apikey(req)
+            .await
+            .map_err(ResponseObject::unauthorized)?;
+
+        let entries = sqlx::query_as!(
+            ScheduleAudit,
+            "SELECT * FROM fcm_schedule_audit
+            WHERE schedule_id = $1 AND ($2::BIGINT IS NULL OR id < $2)
+            ORDER BY id DESC
+            LIMIT $3",
+            id.0,
+            before_id.0,
+            limit.0.unwrap_or(100).clamp(1, 1000)
+        )
+        .fetch_all(pool.0)
+        .await;
+
+        match entries {
+            Ok(entries) => Ok(ResponseObject::ok(entries)),
+            Err(e) => Err(ResponseObject::internal_server_error(e)),
+        }
+    }
+}
+
+impl FirebaseMessagingAdmin {
+    async fn find_schedule(
+        &self,
+        pool: &PgPool,
+        id: i32,
+    ) -> Result<FCMSchedule, JsonError<String>> {
+        let schedule = sqlx::query_as!(FCMSchedule, "SELECT * FROM fcm_schedule WHERE id = $1", id)
+            .fetch_one(pool)
+            .await;
+
+        match schedule {
+            Ok(schedule) => Ok(schedule),
+            Err(_) => Err(ResponseObject::not_found("Schedule not found")),
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use super::model::FCMSchedule;
+use crate::{supervisor::WorkerHandle, utils::env_or};
+use chrono::{Duration, Utc};
+use lazy_static::lazy_static;
+use serde_json::{json, Map, Value};
+use sqlx::postgres::PgPool;
+use tokio::time::sleep;
+use tracing::{debug, error};
+
+pub const WORKER: &str = "worker";
+pub const ADMIN: &str = "admin";
+
+/// fields holding push tokens, webhook URLs or data source headers, only whether they changed is
+/// recorded
+const REDACTED_FIELDS: [&str; 3] = ["push_token", "escalation", "data_source"];
+
+/// how often old audit entries are deleted
+const RETENTION_INTERVAL: std::time::Duration = std::time::Duration::from_secs(3600);
+
+lazy_static! {
+    /// days audit entries are kept for (FCM_AUDIT_RETENTION_DAYS)
+    static ref RETENTION_DAYS: i64 = env_or("FCM_AUDIT_RETENTION_DAYS", 90);
+}
+
+pub fn user(fb_user_id: &str) -> String {
+    format!("user:{}", fb_user_id)
+}
+
+/// Write an audit row for a schedule, failures are logged and never fail the caller
+pub async fn record(
+    pool: &PgPool,
+    actor: &str,
+    action: &str,
+    source_ip: Option<String>,
+    before: Option<&FCMSchedule>,
+    after: Option<&FCMSchedule>,
+) {
+    let schedule = match after.or(before) {
+        Some(schedule) => schedule,
+        None => return,
+    };
+
+    let result = sqlx::query!(
+        "INSERT INTO fcm_schedule_audit (schedule_id, fb_user_id, actor, action, source_ip, diff, created_at) VALUES ($1, $2, $3, $4, $5, $6, $7)",
+        schedule.id,
+        schedule.fb_user_id,
+        actor,
+        action,
+        source_ip,
+        diff(before, after),
+        Utc::now()
+    )
+    .execute(pool)
+    .await;
+
+    if let Err(e) = result {
+        error!(schedule_id = schedule.id, action, error = ?e, "Failed to record audit entry");
+    }
+}
+
+/// Delete audit entries older than the retention period, once an hour
+pub async fn run_retention(pool: &PgPool, worker: WorkerHandle) {
+    while !worker.is_cancelled() {
+        let result = sqlx::query!(
+            "DELETE FROM fcm_schedule_audit WHERE created_at < $1",
+            Utc::now() - Duration::days(*RETENTION_DAYS)
+        )
+        .execute(pool)
+        .await;
+
+        match result {
+            Ok(result) => {
+                debug!(
+                    deleted = result.rows_affected(),
+                    "Deleted old audit entries"
+                );
+                worker.tick();
+            }
+            Err(e) => {
+                error!(error=?e, "Error deleting old audit entries");
+                worker.error(&e.to_string());
+            }
+        }
+
+        tokio::select! {
+            _ = sleep(RETENTION_INTERVAL) => {}
+            _ = worker.cancelled() => {}
+        }
+    }
+}
+
+fn diff(before: Option<&FCMSchedule>, after: Option<&FCMSchedule>) -> Value {
+    let before = to_map(before);
+    let after = to_map(after);
+
+    let mut diff = Map::new();
+    for key in before.keys().chain(after.keys()) {
+        let old = before.get(key).unwrap_or(&Value::Null);
+        let new = after.get(key).unwrap_or(&Value::Null);
+        if old == new || diff.contains_key(key) {
+            continue;
+        }
+        let change = match REDACTED_FIELDS.contains(&key.as_str()) {
+            true => json!({ "changed": true }),
+            false => json!({ "before": old, "after": new }),
+        };
+        diff.insert(key.to_owned(), change);
+    }
+
+    Value::Object(diff)
+}
+
+fn to_map(schedule: Option<&FCMSchedule>) -> Map<String, Value> {
+    match schedule.map(serde_json::to_value) {
+        Some(Ok(Value::Object(map))) => map,
+        _ => Map::new(),
+    }
+}
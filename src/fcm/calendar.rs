@@ -0,0 +1,213 @@
+use super::{model::FCMSchedule, schedule::Recurrence};
+use chrono::{DateTime, Duration, Utc};
+use tracing::warn;
+
+/// how far ahead the calendar feed renders occurrences
+const HORIZON_DAYS: i64 = 30;
+/// upper bound of occurrences rendered per schedule
+const MAX_EVENTS_PER_SCHEDULE: u16 = 100;
+
+// https://datatracker.ietf.org/doc/html/rfc5545
+pub fn render_calendar(schedules: &[FCMSchedule], now: DateTime<Utc>) -> String {
+    let until = now + Duration::days(HORIZON_DAYS);
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//ToolKit//FCM Schedules//EN".to_string(),
+        "CALSCALE:GREGORIAN".to_string(),
+        "METHOD:PUBLISH".to_string(),
+        "X-WR-CALNAME:Reminders".to_string(),
+    ];
+
+    for schedule in schedules {
+        if schedule.paused {
+            continue;
+        }
+
+        let recurrence = match Recurrence::for_schedule(
+            schedule.recurrence.as_ref(),
+            schedule.cron_pattern.as_deref(),
+            schedule.rrule.as_deref(),
+        ) {
+            Ok(recurrence) => recurrence,
+            Err(e) => {
+                warn!(schedule_id = schedule.id, error = ?e, "Skipping schedule with invalid recurrence");
+                continue;
+            }
+        };
+
+        let occurrences = match recurrence.upcoming(now, MAX_EVENTS_PER_SCHEDULE) {
+            Ok(occurrences) => occurrences,
+            Err(e) => {
+                warn!(schedule_id = schedule.id, error = ?e, "Failed to compute occurrences");
+                continue;
+            }
+        };
+
+        let description = schedule
+            .payload
+            .get("body")
+            .and_then(|body| body.as_str())
+            .map(escape_text);
+
+        for occurrence in occurrences.into_iter().take_while(|o| *o <= until) {
+            lines.push("BEGIN:VEVENT".to_string());
+            lines.push(format!(
+                "UID:fcm-{}-{}@toolkit",
+                schedule.id,
+                occurrence.timestamp()
+            ));
+            lines.push(format!("DTSTAMP:{}", format_datetime(now)));
+            lines.push(format!("DTSTART:{}", format_datetime(occurrence)));
+            lines.push(format!("SUMMARY:{}", escape_text(&schedule.name)));
+            if let Some(description) = &description {
+                lines.push(format!("DESCRIPTION:{}", description));
+            }
+            lines.push("END:VEVENT".to_string());
+        }
+    }
+
+    lines.push("END:VCALENDAR".to_string());
+
+    lines
+        .iter()
+        .map(|line| fold_line(line))
+        .collect::<Vec<_>>()
+        .join("")
+}
+
+fn format_datetime(datetime: DateTime<Utc>) -> String {
+    datetime.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+fn escape_text(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+        .replace('\r', "")
+}
+
+// content lines must not be longer than 75 octets, continuation lines start with a space
+fn fold_line(line: &str) -> String {
+    let mut folded = String::with_capacity(line.len() + 8);
+    let mut octets = 0;
+    for c in line.chars() {
+        if octets + c.len_utf8() > 75 {
+            folded.push_str("\r\n ");
+            octets = 1;
+        }
+        folded.push(c);
+        octets += c.len_utf8();
+    }
+    folded.push_str("\r\n");
+    folded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn at(time: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(time)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    fn schedule(name: &str, cron_pattern: &str, body: &str) -> FCMSchedule {
+        FCMSchedule {
+            id: 7,
+            name: name.to_string(),
+            fb_user_id: "user".to_string(),
+            push_token: "token".to_string(),
+            fb_project_id: "project".to_string(),
+            cron_pattern: Some(cron_pattern.to_string()),
+            rrule: None,
+            recurrence: None,
+            payload: json!({"title": "Title", "body": body}),
+            data_source: None,
+            escalation: None,
+            paused: false,
+            last_execution: at("2024-01-01T00:00:00Z"),
+            next_execution: at("2024-01-01T00:00:00Z"),
+            created_at: at("2024-01-01T00:00:00Z"),
+            updated_at: at("2024-01-01T00:00:00Z"),
+        }
+    }
+
+    /// content lines with their continuation lines joined back
+    fn unfold(calendar: &str) -> Vec<String> {
+        calendar
+            .replace("\r\n ", "")
+            .split("\r\n")
+            .filter(|line| !line.is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn renders_events() {
+        let now = at("2024-01-01T10:30:00Z");
+        let calendar = render_calendar(&[schedule("Daily", "0 12 * * *", "Hello")], now);
+        let lines = unfold(&calendar);
+
+        assert_eq!(lines.first().unwrap(), "BEGIN:VCALENDAR");
+        assert_eq!(lines.last().unwrap(), "END:VCALENDAR");
+        // the horizon ends after 30 days
+        assert_eq!(lines.iter().filter(|l| *l == "BEGIN:VEVENT").count(), 30);
+        assert!(lines.contains(&"DTSTART:20240101T120000Z".to_string()));
+        assert!(lines.contains(&format!(
+            "UID:fcm-7-{}@toolkit",
+            at("2024-01-01T12:00:00Z").timestamp()
+        )));
+        assert!(lines.contains(&"DTSTAMP:20240101T103000Z".to_string()));
+        assert!(lines.contains(&"DESCRIPTION:Hello".to_string()));
+    }
+
+    #[test]
+    fn escapes_text() {
+        let calendar = render_calendar(
+            &[schedule("Tea, biscuits; cake", "0 12 * * *", "a\\b\r\nc")],
+            at("2024-01-01T10:30:00Z"),
+        );
+        let lines = unfold(&calendar);
+
+        assert!(lines.contains(&r"SUMMARY:Tea\, biscuits\; cake".to_string()));
+        assert!(lines.contains(&r"DESCRIPTION:a\\b\nc".to_string()));
+    }
+
+    #[test]
+    fn folds_long_lines_at_75_octets() {
+        let name = "日本語のリマインダー".repeat(6);
+        let calendar = render_calendar(
+            &[schedule(&name, "0 12 * * *", "")],
+            at("2024-01-01T10:30:00Z"),
+        );
+
+        for line in calendar.split("\r\n") {
+            assert!(line.len() <= 75, "{} octets: {}", line.len(), line);
+        }
+        assert!(calendar.contains("\r\n "));
+        assert!(unfold(&calendar).contains(&format!("SUMMARY:{}", name)));
+    }
+
+    #[test]
+    fn caps_events_per_schedule() {
+        let mut paused = schedule("Paused", "0 12 * * *", "");
+        paused.paused = true;
+        let invalid = schedule("Invalid", "not a cron", "");
+
+        let calendar = render_calendar(
+            &[schedule("Every minute", "* * * * *", ""), paused, invalid],
+            at("2024-01-01T10:30:00Z"),
+        );
+        let lines = unfold(&calendar);
+
+        assert_eq!(
+            lines.iter().filter(|l| *l == "BEGIN:VEVENT").count(),
+            MAX_EVENTS_PER_SCHEDULE as usize
+        );
+        assert!(!lines.contains(&"SUMMARY:Paused".to_string()));
+    }
+}
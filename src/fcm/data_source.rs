@@ -0,0 +1,334 @@
+use super::model::FCMSchedule;
+use crate::utils::{pinned_client, resolve_public_url};
+use reqwest::header::LOCATION;
+use serde::Deserialize;
+use serde_json::Value;
+use serde_json_path::JsonPath;
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+use tokio::{task::JoinSet, time::timeout};
+use tracing::warn;
+use url::Url;
+
+/// placeholder in the payload replaced by the value of the data source
+const PLACEHOLDER: &str = "{{data}}";
+const DEFAULT_TIMEOUT_MS: u64 = 5000;
+const MAX_TIMEOUT_MS: u64 = 10000;
+/// FCM payloads are limited to 4KB, so values are cut to this many characters
+const MAX_VALUE_LENGTH: usize = 512;
+/// largest response read from a data source, only a small value is extracted from it
+const MAX_BODY_BYTES: usize = 1024 * 1024;
+/// redirects followed before a data source is given up
+const MAX_REDIRECTS: usize = 5;
+/// total time data sources may hold up the schedules of one worker tick
+const TICK_FETCH_BUDGET: Duration = Duration::from_secs(10);
+
+fn default_timeout_ms() -> u64 {
+    DEFAULT_TIMEOUT_MS
+}
+
+/// HTTP source of live data, fetched just before a schedule is sent
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DataSource {
+    /// http(s) url to GET
+    pub url: String,
+    /// headers to send with the request
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    /// JSONPath of the value in a JSON response, the whole response text when not set
+    pub path: Option<String>,
+    /// request timeout in milliseconds
+    #[serde(default = "default_timeout_ms")]
+    pub timeout_ms: u64,
+    /// text used when the source can't be fetched or the path doesn't match
+    #[serde(default)]
+    pub fallback: String,
+}
+
+impl DataSource {
+    /// Parse and validate the data_source of a schedule
+    pub fn parse(value: &Value) -> Result<Self, String> {
+        let source: DataSource = match serde_json::from_value(value.clone()) {
+            Ok(source) => source,
+            Err(e) => {
+                return Err(format!("Invalid data_source: {}", e));
+            }
+        };
+
+        match Url::parse(&source.url) {
+            Ok(url) if url.scheme() == "http" || url.scheme() == "https" => {}
+            _ => return Err("Invalid data_source: url must be http(s)".to_string()),
+        }
+
+        if source.timeout_ms == 0 || source.timeout_ms > MAX_TIMEOUT_MS {
+            return Err(format!(
+                "Invalid data_source: timeout_ms must be between 1 and {}",
+                MAX_TIMEOUT_MS
+            ));
+        }
+
+        if let Some(path) = &source.path {
+            if let Err(e) = JsonPath::parse(path) {
+                return Err(format!("Invalid data_source: invalid path: {}", e));
+            }
+        }
+
+        Ok(source)
+    }
+
+    /// Parse the data_source of a schedule and check that its url is on a public address
+    pub async fn validate(value: &Value) -> Result<Self, String> {
+        let source = Self::parse(value)?;
+
+        match resolve_public_url(&source.url).await {
+            Ok(_) => Ok(source),
+            Err(e) => Err(format!("Invalid data_source: {}", e)),
+        }
+    }
+
+    fn cache_key(&self) -> String {
+        let mut headers: Vec<_> = self.headers.iter().collect();
+        headers.sort();
+        format!("{} {:?}", self.url, headers)
+    }
+
+    async fn fetch(&self) -> Result<String, String> {
+        match timeout(Duration::from_millis(self.timeout_ms), self.follow()).await {
+            Ok(response) => response,
+            Err(_) => Err("Data source timed out".to_string()),
+        }
+    }
+
+    /// GET the url, redirects are only followed to public addresses and the headers are only sent
+    /// to the origin of the url
+    async fn follow(&self) -> Result<String, String> {
+        let origin = Url::parse(&self.url).ok().map(|url| url.origin());
+        let mut url = self.url.clone();
+
+        for _ in 0..=MAX_REDIRECTS {
+            let (target, addrs) = match resolve_public_url(&url).await {
+                Ok(resolved) => resolved,
+                Err(e) => {
+                    return Err(format!("Error fetching data source: {}", e));
+                }
+            };
+
+            let client = pinned_client(&target, &addrs, Duration::from_millis(self.timeout_ms))?;
+            let mut request = client.get(target.clone());
+            if Some(target.origin()) == origin {
+                for (name, value) in &self.headers {
+                    request = request.header(name, value);
+                }
+            }
+
+            let response = match request.send().await {
+                Ok(response) => response,
+                Err(e) => {
+                    return Err(format!("Error fetching data source: {}", e));
+                }
+            };
+
+            if response.status().is_redirection() {
+                let location = response
+                    .headers()
+                    .get(LOCATION)
+                    .and_then(|location| location.to_str().ok())
+                    .and_then(|location| target.join(location).ok());
+                match location {
+                    Some(location) => {
+                        url = location.to_string();
+                        continue;
+                    }
+                    None => {
+                        return Err(format!(
+                            "Data source returned {} without a location",
+                            response.status()
+                        ));
+                    }
+                }
+            }
+
+            if !response.status().is_success() {
+                return Err(format!("Data source returned {}", response.status()));
+            }
+
+            return read_body(response).await;
+        }
+
+        Err("Data source redirected too many times".to_string())
+    }
+
+    fn extract(&self, body: &str) -> Result<String, String> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(body.trim().to_string()),
+        };
+
+        let json: Value = match serde_json::from_str(body) {
+            Ok(json) => json,
+            Err(e) => {
+                return Err(format!("Data source is not JSON: {}", e));
+            }
+        };
+
+        let path = match JsonPath::parse(path) {
+            Ok(path) => path,
+            Err(e) => {
+                return Err(format!("Invalid path: {}", e));
+            }
+        };
+
+        match path.query(&json).first() {
+            Some(Value::String(value)) => Ok(value.to_owned()),
+            Some(value) => Ok(value.to_string()),
+            None => Err("Path did not match the data source".to_string()),
+        }
+    }
+}
+
+/// Read the body in chunks, giving up once it is larger than MAX_BODY_BYTES
+async fn read_body(mut response: reqwest::Response) -> Result<String, String> {
+    let too_large = format!(
+        "Data source response is larger than {} bytes",
+        MAX_BODY_BYTES
+    );
+    if response.content_length().unwrap_or(0) > MAX_BODY_BYTES as u64 {
+        return Err(too_large);
+    }
+
+    let mut body = Vec::new();
+    loop {
+        match response.chunk().await {
+            Ok(Some(chunk)) => {
+                if body.len() + chunk.len() > MAX_BODY_BYTES {
+                    return Err(too_large);
+                }
+                body.extend_from_slice(&chunk);
+            }
+            Ok(None) => break,
+            Err(e) => {
+                return Err(format!("Error reading data source: {}", e));
+            }
+        }
+    }
+
+    Ok(String::from_utf8_lossy(&body).into_owned())
+}
+
+/// Responses fetched during one worker tick, shared by the schedules using the same source
+#[derive(Default)]
+pub struct SourceCache {
+    responses: HashMap<String, Result<String, String>>,
+    /// time spent fetching, sources are not waited for once it reaches TICK_FETCH_BUDGET
+    spent: Duration,
+}
+
+impl SourceCache {
+    /// Fetch the sources of the schedules concurrently ahead of sending them, sources that don't
+    /// respond within the budget use their fallback
+    pub async fn prefetch(&mut self, schedules: &[FCMSchedule]) {
+        let mut tasks = JoinSet::new();
+        let mut pending = Vec::new();
+
+        for schedule in schedules {
+            let source = match schedule.data_source.as_ref().map(DataSource::parse) {
+                Some(Ok(source)) => source,
+                _ => continue,
+            };
+            let key = source.cache_key();
+            if self.responses.contains_key(&key) || pending.contains(&key) {
+                continue;
+            }
+            pending.push(key.clone());
+            tasks.spawn(async move { (key, source.fetch().await) });
+        }
+
+        let started = Instant::now();
+        let budget = self.remaining();
+        loop {
+            let remaining = budget.saturating_sub(started.elapsed());
+            match timeout(remaining, tasks.join_next()).await {
+                Ok(Some(Ok((key, response)))) => {
+                    pending.retain(|pending| *pending != key);
+                    self.responses.insert(key, response);
+                }
+                Ok(Some(Err(e))) => warn!(error=%e, "Data source fetch failed"),
+                Ok(None) | Err(_) => break,
+            }
+        }
+        self.spent += started.elapsed();
+
+        // the unfinished fetches are aborted when the set is dropped
+        for key in pending {
+            self.responses
+                .insert(key, Err("Data source timed out".to_string()));
+        }
+    }
+
+    async fn fetch(&mut self, source: &DataSource) -> Result<String, String> {
+        let key = source.cache_key();
+        if let Some(response) = self.responses.get(&key) {
+            return response.clone();
+        }
+
+        let started = Instant::now();
+        let response = match timeout(self.remaining(), source.fetch()).await {
+            Ok(response) => response,
+            Err(_) => Err("Data source timed out".to_string()),
+        };
+        self.spent += started.elapsed();
+
+        self.responses.insert(key, response.clone());
+        response
+    }
+
+    fn remaining(&self) -> Duration {
+        TICK_FETCH_BUDGET.saturating_sub(self.spent)
+    }
+}
+
+/// Payload of the schedule with {{data}} replaced by the value of its data source, or its fallback
+pub async fn resolve_payload(schedule: &FCMSchedule, cache: &mut SourceCache) -> Value {
+    let source = match &schedule.data_source {
+        Some(source) => source,
+        None => return schedule.payload.clone(),
+    };
+
+    let value = match DataSource::parse(source) {
+        Ok(source) => {
+            let value = match cache.fetch(&source).await {
+                Ok(body) => source.extract(&body),
+                Err(e) => Err(e),
+            };
+            match value {
+                Ok(value) => value,
+                Err(e) => {
+                    warn!(message_id=?schedule.id, error=%e, "Using the fallback of the data source");
+                    source.fallback
+                }
+            }
+        }
+        Err(e) => {
+            warn!(message_id=?schedule.id, error=%e, "Ignoring the data source");
+            String::new()
+        }
+    };
+
+    let value: String = value.chars().take(MAX_VALUE_LENGTH).collect();
+    substitute(&schedule.payload, &value)
+}
+
+fn substitute(payload: &Value, value: &str) -> Value {
+    match payload {
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .map(|(key, field)| (key.to_owned(), substitute(field, value)))
+                .collect(),
+        ),
+        Value::String(s) if s.contains(PLACEHOLDER) => Value::String(s.replace(PLACEHOLDER, value)),
+        _ => payload.clone(),
+    }
+}
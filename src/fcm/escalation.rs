@@ -0,0 +1,380 @@
+use super::data_source::{resolve_payload, SourceCache};
+use super::model::FCMSchedule;
+use super::quota;
+use super::worker::{
+    data_payload, next_delivery_id, record_delivery, send_push, with_occurrence_id,
+};
+use crate::{supervisor::WorkerHandle, utils::send_webhook};
+use chrono::{DateTime, Duration, Utc};
+use gcp_auth::AuthenticationManager;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sqlx::postgres::PgPool;
+use std::collections::HashMap;
+use tracing::{debug, error, info, warn};
+use url::Url;
+
+const MAX_STEPS: usize = 5;
+/// escalations end within a week of the occurrence
+const MAX_AFTER_MINUTES: i64 = 10080;
+
+/// What to do when an occurrence is still not acknowledged
+#[derive(Debug, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Action {
+    /// send the occurrence again to the same device with high priority
+    Resend,
+    /// send the occurrence to another device (or another user's device)
+    Push { push_token: String },
+    /// POST the occurrence to a webhook
+    Webhook { url: String },
+}
+
+/// Step of an escalation chain, `after_minutes` counts from when the occurrence was sent
+#[derive(Debug, Deserialize)]
+pub struct Step {
+    pub after_minutes: i64,
+    #[serde(flatten)]
+    pub action: Action,
+}
+
+/// Parse and validate the escalation chain of a schedule
+pub fn parse(value: &Value) -> Result<Vec<Step>, String> {
+    let steps: Vec<Step> = match serde_json::from_value(value.clone()) {
+        Ok(steps) => steps,
+        Err(e) => {
+            return Err(format!("Invalid escalation: {}", e));
+        }
+    };
+
+    if steps.is_empty() || steps.len() > MAX_STEPS {
+        return Err(format!(
+            "Invalid escalation: must have between 1 and {} steps",
+            MAX_STEPS
+        ));
+    }
+
+    let mut previous = 0;
+    for step in &steps {
+        if step.after_minutes <= previous || step.after_minutes > MAX_AFTER_MINUTES {
+            return Err(format!(
+                "Invalid escalation: after_minutes must increase and be at most {}",
+                MAX_AFTER_MINUTES
+            ));
+        }
+        previous = step.after_minutes;
+
+        match &step.action {
+            Action::Resend => {}
+            Action::Push { push_token } => {
+                if push_token.len() < 32 || push_token.len() > 512 {
+                    return Err("Invalid escalation: invalid push_token".to_string());
+                }
+            }
+            Action::Webhook { url } => match Url::parse(url) {
+                Ok(url) if url.scheme() == "http" || url.scheme() == "https" => {}
+                _ => return Err("Invalid escalation: webhook url must be http(s)".to_string()),
+            },
+        }
+    }
+
+    Ok(steps)
+}
+
+fn next_at(steps: &[Step], sent_at: DateTime<Utc>, done: usize) -> Option<DateTime<Utc>> {
+    steps
+        .get(done)
+        .map(|step| sent_at + Duration::minutes(step.after_minutes))
+}
+
+/// Arm the escalation chain of a freshly sent occurrence
+pub async fn start(pool: &PgPool, delivery_id: i64, message: &FCMSchedule) {
+    let steps = match &message.escalation {
+        Some(escalation) => match parse(escalation) {
+            Ok(steps) => steps,
+            Err(e) => {
+                warn!(message_id=?message.id, error=%e, "Ignoring the escalation");
+                return;
+            }
+        },
+        None => return,
+    };
+
+    let result = sqlx::query!(
+        "UPDATE fcm_delivery SET next_escalation_at = $1 WHERE id = $2",
+        next_at(&steps, Utc::now(), 0),
+        delivery_id
+    )
+    .execute(pool)
+    .await;
+
+    if let Err(e) = result {
+        error!(message_id=?message.id, delivery_id, error=?e, "Error starting the escalation");
+    }
+}
+
+/// Run the due steps of unacknowledged occurrences, snoozed occurrences wait for their resend
+pub async fn run_due(
+    pool: &PgPool,
+    auth_managers: &HashMap<String, AuthenticationManager>,
+    sources: &mut SourceCache,
+    worker: &WorkerHandle,
+) {
+    // claim the steps so other replicas don't run them too
+    let due = sqlx::query!(
+        "UPDATE fcm_delivery SET next_escalation_at = NULL
+        WHERE next_escalation_at <= $1 AND acked_at IS NULL AND snoozed_until IS NULL
+        RETURNING id, schedule_id, escalation_step, created_at",
+        Utc::now()
+    )
+    .fetch_all(pool)
+    .await;
+
+    let due = match due {
+        Ok(due) => due,
+        Err(e) => {
+            error!(error=?e, "Error fetching due escalations");
+            worker.error(&e.to_string());
+            return;
+        }
+    };
+
+    for occurrence in due {
+        let schedule = sqlx::query_as!(
+            FCMSchedule,
+            "SELECT * FROM fcm_schedule WHERE id = $1 AND NOT paused",
+            occurrence.schedule_id
+        )
+        .fetch_optional(pool)
+        .await;
+
+        let message = match schedule {
+            Ok(Some(message)) => message,
+            Ok(None) => {
+                debug!(
+                    occurrence_id = occurrence.id,
+                    "Schedule of the escalated occurrence is gone or paused"
+                );
+                continue;
+            }
+            Err(e) => {
+                error!(occurrence_id = occurrence.id, error=?e, "Error fetching the schedule of an escalation");
+                continue;
+            }
+        };
+
+        let steps = match message.escalation.as_ref().map(parse) {
+            Some(Ok(steps)) => steps,
+            _ => {
+                warn!(
+                    occurrence_id = occurrence.id,
+                    "Schedule no longer has a valid escalation"
+                );
+                continue;
+            }
+        };
+
+        let done = occurrence.escalation_step as usize;
+        let step = match steps.get(done) {
+            Some(step) => step,
+            None => continue,
+        };
+
+        info!(occurrence_id = occurrence.id, step = done + 1, action = ?step.action, "Escalating unacknowledged occurrence");
+
+        let (status, error) = match escalate(
+            pool,
+            auth_managers,
+            sources,
+            &message,
+            occurrence.id,
+            done + 1,
+            step,
+        )
+        .await
+        {
+            Ok(status) => (status, None),
+            Err(e) => {
+                warn!(occurrence_id = occurrence.id, error=%e, "Error escalating occurrence");
+                ("failed", Some(e))
+            }
+        };
+
+        match next_delivery_id(pool).await {
+            Ok(delivery_id) => {
+                let step = (done + 1) as i32;
+                record_delivery(
+                    pool,
+                    delivery_id,
+                    Some(occurrence.id),
+                    step,
+                    &message,
+                    status,
+                    error,
+                )
+                .await;
+            }
+            Err(e) => {
+                error!(occurrence_id = occurrence.id, error=?e, "Error recording the escalation");
+            }
+        }
+
+        let result = sqlx::query!(
+            "UPDATE fcm_delivery SET escalation_step = $1, next_escalation_at = $2 WHERE id = $3 AND acked_at IS NULL",
+            (done + 1) as i32,
+            next_at(&steps, occurrence.created_at, done + 1),
+            occurrence.id
+        )
+        .execute(pool)
+        .await;
+
+        if let Err(e) = result {
+            error!(occurrence_id = occurrence.id, error=?e, "Error advancing the escalation");
+        }
+    }
+}
+
+/// Perform a step, returns the delivery status
+async fn escalate(
+    pool: &PgPool,
+    auth_managers: &HashMap<String, AuthenticationManager>,
+    sources: &mut SourceCache,
+    message: &FCMSchedule,
+    occurrence_id: i64,
+    step: usize,
+    escalation: &Step,
+) -> Result<&'static str, String> {
+    let usage = match quota::usage(pool, &message.fb_user_id, &message.fb_project_id).await {
+        Ok(usage) => usage,
+        Err(e) => return Err(format!("Error checking quota: {}", e)),
+    };
+    if usage.sends_exceeded().is_some() {
+        return Ok("throttled");
+    }
+
+    let mut payload = with_occurrence_id(resolve_payload(message, sources).await, occurrence_id);
+    if let Value::Object(map) = &mut payload {
+        map.insert("escalation_step".to_string(), Value::from(step));
+    }
+    let message = FCMSchedule {
+        payload,
+        ..message.clone()
+    };
+
+    let push_token = match &escalation.action {
+        Action::Resend => &message.push_token,
+        Action::Push { push_token } => push_token,
+        Action::Webhook { url } => {
+            let body = json!({
+                "schedule_id": message.id,
+                "name": message.name,
+                "fb_user_id": message.fb_user_id,
+                "occurrence_id": occurrence_id,
+                "escalation_step": step,
+                "payload": message.payload,
+            });
+            return send_webhook(url, &body).await.map(|_| "sent");
+        }
+    };
+
+    let auth_manager = match auth_managers.get(&message.fb_project_id) {
+        Some(auth_manager) => auth_manager,
+        None => return Err("No service account found for the project".to_string()),
+    };
+
+    send_push(
+        auth_manager,
+        &message.fb_project_id,
+        push_token,
+        data_payload(&message),
+        true,
+    )
+    .await
+    .map(|_| "sent")
+    .map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(time: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(time)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    fn resend(after_minutes: i64) -> Value {
+        json!({"after_minutes": after_minutes, "action": "resend"})
+    }
+
+    #[test]
+    fn parses_actions() {
+        let token = "t".repeat(32);
+        let steps = parse(&json!([
+            {"after_minutes": 10, "action": "resend"},
+            {"after_minutes": 30, "action": "push", "push_token": token},
+            {"after_minutes": 60, "action": "webhook", "url": "https://example.com/hook"},
+        ]))
+        .unwrap();
+
+        assert_eq!(steps.len(), 3);
+        assert!(matches!(steps[0].action, Action::Resend));
+        assert!(matches!(&steps[1].action, Action::Push { push_token } if *push_token == token));
+        assert!(
+            matches!(&steps[2].action, Action::Webhook { url } if url == "https://example.com/hook")
+        );
+    }
+
+    #[test]
+    fn limits_steps() {
+        assert!(parse(&json!([])).is_err());
+        assert!(parse(&json!((1..=5).map(resend).collect::<Vec<_>>())).is_ok());
+        assert!(parse(&json!((1..=6).map(resend).collect::<Vec<_>>())).is_err());
+        assert!(parse(&json!({"after_minutes": 10, "action": "resend"})).is_err());
+    }
+
+    #[test]
+    fn after_minutes_strictly_increase() {
+        assert!(parse(&json!([resend(10), resend(10)])).is_err());
+        assert!(parse(&json!([resend(20), resend(10)])).is_err());
+        assert!(parse(&json!([resend(0)])).is_err());
+        assert!(parse(&json!([resend(-5)])).is_err());
+        assert!(parse(&json!([resend(10080)])).is_ok());
+        assert!(parse(&json!([resend(10081)])).is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_actions() {
+        assert!(parse(&json!([{"after_minutes": 10, "action": "call"}])).is_err());
+        assert!(parse(&json!([{"after_minutes": 10}])).is_err());
+        assert!(parse(&json!([{"after_minutes": 10, "action": "push"}])).is_err());
+        assert!(
+            parse(&json!([{"after_minutes": 10, "action": "push", "push_token": "short"}]))
+                .is_err()
+        );
+        assert!(parse(
+            &json!([{"after_minutes": 10, "action": "webhook", "url": "ftp://example.com"}])
+        )
+        .is_err());
+        assert!(
+            parse(&json!([{"after_minutes": 10, "action": "webhook", "url": "/hook"}])).is_err()
+        );
+    }
+
+    #[test]
+    fn next_step_counts_from_the_send() {
+        let steps = parse(&json!([resend(10), resend(60)])).unwrap();
+        let sent_at = at("2024-01-01T12:00:00Z");
+
+        assert_eq!(
+            next_at(&steps, sent_at, 0),
+            Some(at("2024-01-01T12:10:00Z"))
+        );
+        assert_eq!(
+            next_at(&steps, sent_at, 1),
+            Some(at("2024-01-01T13:00:00Z"))
+        );
+        assert_eq!(next_at(&steps, sent_at, 2), None);
+    }
+}
@@ -1,15 +1,34 @@
-use super::model::{FCMSchedule, UpdateSchedule};
-use super::utils::{decode_cron, extract_claims};
-use crate::utils::{ApiTags, JsonError, JsonSuccess, ResponseObject};
-use chrono::Utc;
+use super::audit;
+use super::calendar::render_calendar;
+use super::data_source::DataSource;
+use super::escalation;
+use super::model::{
+    Adherence, CalendarFeed, FCMSchedule, Occurrence, ScheduleAudit, UpdateSchedule, Usage,
+};
+use super::quota::{self, QUOTAS};
+use super::schedule::{normalize_spec, normalize_update, Recurrence};
+use super::utils::{extract_claims, next_execution};
+use crate::utils::{self, client_ip, ApiTags, JsonError, JsonSuccess, ResponseObject};
+use chrono::{Duration, Utc};
 use poem::{web::Data, Request};
-use poem_openapi::param::Path;
-use poem_openapi::{payload::Json, OpenApi};
+use poem_openapi::param::{Path, Query};
+use poem_openapi::{
+    payload::{Json, PlainText},
+    ApiResponse, OpenApi,
+};
+use rand::{distributions::Alphanumeric, Rng};
 use serde_json::Value;
 use sqlx::postgres::PgPool;
+use std::sync::Arc;
+use tokio::sync::Notify;
+use tracing::error;
+
+/// longest a notification can be snoozed for
+const MAX_SNOOZE_MINUTES: i64 = 1440;
 
 pub struct FirebaseMessaging {
     pub projects: Vec<String>,
+    wake: Arc<Notify>,
 }
 
 #[OpenApi(
@@ -23,8 +42,8 @@ pub struct FirebaseMessaging {
 )]
 impl FirebaseMessaging {
     // create new instance
-    pub fn new(projects: Vec<String>) -> Self {
-        Self { projects }
+    pub fn new(projects: Vec<String>, wake: Arc<Notify>) -> Self {
+        Self { projects, wake }
     }
 
     // create schedule
@@ -58,34 +77,115 @@ impl FirebaseMessaging {
             }
         }
 
-        let next_execution = match decode_cron(payload.cron_pattern.as_ref()) {
+        if let Some(data_source) = &payload.data_source {
+            if let Err(e) = DataSource::validate(data_source).await {
+                return Err(ResponseObject::bad_request(e));
+            }
+        }
+
+        if let Some(escalation) = &payload.escalation {
+            if let Err(e) = escalation::parse(escalation) {
+                return Err(ResponseObject::bad_request(e));
+            }
+        }
+
+        // recurrence takes precedence over rrule, which takes precedence over cron_pattern
+        let recurrence_spec = payload.recurrence.clone().map(normalize_spec);
+        let rrule = match recurrence_spec {
+            Some(_) => None,
+            None => payload.rrule.clone(),
+        };
+        let cron_pattern = match (&recurrence_spec, &rrule) {
+            (None, None) => payload.cron_pattern.clone(),
+            _ => None,
+        };
+
+        let recurrence = match Recurrence::for_schedule(
+            recurrence_spec.as_ref(),
+            cron_pattern.as_deref(),
+            rrule.as_deref(),
+        ) {
+            Ok(recurrence) => recurrence,
+            Err(e) => {
+                return Err(ResponseObject::bad_request(e));
+            }
+        };
+
+        if let Err(e) = quota::check_interval(&recurrence, QUOTAS.min_interval_seconds) {
+            return Err(ResponseObject::bad_request(e));
+        }
+
+        let next_execution = match next_execution(&recurrence) {
             Ok(next) => next,
             Err(e) => {
                 return Err(ResponseObject::bad_request(e));
             }
         };
 
-        let current_time = Utc::now().naive_local();
+        let current_time = Utc::now();
+
+        // concurrent creates of the same user are serialized so they can't exceed the quota together
+        let mut tx = match pool.0.begin().await {
+            Ok(tx) => tx,
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+
+        let locked = sqlx::query!(
+            "SELECT 1 AS locked FROM pg_advisory_xact_lock(hashtext($1))",
+            fb_user_id
+        )
+        .fetch_one(&mut *tx)
+        .await;
+
+        if let Err(e) = locked {
+            return Err(ResponseObject::internal_server_error(e));
+        }
+
+        let schedules = sqlx::query_scalar!(
+            r#"SELECT COUNT(*) as "count!" FROM fcm_schedule WHERE fb_user_id = $1"#,
+            fb_user_id
+        )
+        .fetch_one(&mut *tx)
+        .await;
+
+        match schedules {
+            Ok(schedules) if schedules >= QUOTAS.max_schedules_per_user => {
+                return Err(ResponseObject::too_many_requests(format!(
+                    "Reached the limit of {} schedules per user",
+                    QUOTAS.max_schedules_per_user
+                )));
+            }
+            Ok(_) => (),
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        }
 
         let schedule = sqlx::query_as!(
             FCMSchedule,
             "INSERT INTO fcm_schedule (
-                name, fb_user_id, push_token, fb_project_id, cron_pattern, payload, last_execution, next_execution, created_at, updated_at
+                name, fb_user_id, push_token, fb_project_id, cron_pattern, rrule, recurrence, payload, data_source, escalation, last_execution, next_execution, created_at, updated_at
             ) 
-            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)
             RETURNING *",
             payload.name,
             fb_user_id,
             payload.push_token,
             fb_project_id,
-            payload.cron_pattern,
+            cron_pattern,
+            rrule,
+            recurrence_spec,
             payload.payload,
+            payload.data_source,
+            payload.escalation,
             current_time,
             next_execution,
             current_time,
             current_time
         )
-        .fetch_one(pool.0)
+        .fetch_one(&mut *tx)
         .await;
 
         let schedule = match schedule {
@@ -95,6 +195,22 @@ impl FirebaseMessaging {
             }
         };
 
+        if let Err(e) = tx.commit().await {
+            return Err(ResponseObject::internal_server_error(e));
+        }
+
+        audit::record(
+            pool.0,
+            &audit::user(&schedule.fb_user_id),
+            "created",
+            client_ip(req),
+            None,
+            Some(&schedule),
+        )
+        .await;
+
+        self.wake.notify_one();
+
         Ok(ResponseObject::created(schedule))
     }
 
@@ -133,6 +249,27 @@ impl FirebaseMessaging {
         Ok(ResponseObject::ok(schedules))
     }
 
+    // current consumption of the user and the project against the quotas
+    #[oai(path = "/usage", method = "get", operation_id = "fcm::get_usage")]
+    async fn get_usage(
+        &self,
+        req: &Request,
+        pool: Data<&PgPool>,
+    ) -> Result<JsonSuccess<Usage>, JsonError<String>> {
+        // extract user id from token
+        let data = match extract_claims(req.header("firebase-auth")) {
+            Ok(data) => data,
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        };
+
+        match quota::usage(pool.0, &data.user_id, &data.aud).await {
+            Ok(usage) => Ok(ResponseObject::ok(usage)),
+            Err(e) => Err(ResponseObject::internal_server_error(e)),
+        }
+    }
+
     // Delete schedule by id (only if it belongs to the user)
     #[oai(
         path = "/:id",
@@ -190,6 +327,16 @@ impl FirebaseMessaging {
             return Err(ResponseObject::not_found("Schedule not found"));
         }
 
+        audit::record(
+            pool.0,
+            &audit::user(&fb_user_id),
+            "deleted",
+            client_ip(req),
+            Some(&schedule),
+            None,
+        )
+        .await;
+
         Ok(ResponseObject::ok(schedule))
     }
 
@@ -221,7 +368,7 @@ impl FirebaseMessaging {
         .fetch_one(pool.0)
         .await;
 
-        let _ = match schedule {
+        let before = match schedule {
             Ok(schedule) => schedule,
             Err(_) => {
                 return Err(ResponseObject::not_found("Schedule not found"));
@@ -235,21 +382,66 @@ impl FirebaseMessaging {
             }
         }
 
-        let next_execution = match decode_cron(&payload.cron_pattern) {
+        if let Some(data_source) = &payload.data_source {
+            if let Err(e) = DataSource::validate(data_source).await {
+                return Err(ResponseObject::bad_request(e));
+            }
+        }
+
+        if let Some(escalation) = &payload.escalation {
+            if let Err(e) = escalation::parse(escalation) {
+                return Err(ResponseObject::bad_request(e));
+            }
+        }
+
+        // recurrence takes precedence over rrule, which takes precedence over cron_pattern
+        let recurrence_spec = payload
+            .recurrence
+            .clone()
+            .map(|spec| normalize_update(spec, before.recurrence.as_ref()));
+        let rrule = match recurrence_spec {
+            Some(_) => None,
+            None => payload.rrule.clone(),
+        };
+        let cron_pattern = match (&recurrence_spec, &rrule) {
+            (None, None) => payload.cron_pattern.clone(),
+            _ => None,
+        };
+
+        let recurrence = match Recurrence::for_schedule(
+            recurrence_spec.as_ref(),
+            cron_pattern.as_deref(),
+            rrule.as_deref(),
+        ) {
+            Ok(recurrence) => recurrence,
+            Err(e) => {
+                return Err(ResponseObject::bad_request(e));
+            }
+        };
+
+        if let Err(e) = quota::check_interval(&recurrence, QUOTAS.min_interval_seconds) {
+            return Err(ResponseObject::bad_request(e));
+        }
+
+        let next_execution = match next_execution(&recurrence) {
             Ok(next) => next,
             Err(e) => {
                 return Err(ResponseObject::bad_request(e));
             }
         };
 
-        let current_time = Utc::now().naive_local();
+        let current_time = Utc::now();
 
         let result = sqlx::query!(
-            "UPDATE fcm_schedule SET name = $1, push_token = $2, cron_pattern = $3, payload = $4, next_execution = $5, updated_at = $6 WHERE id = $7 AND fb_user_id = $8",
+            "UPDATE fcm_schedule SET name = $1, push_token = $2, cron_pattern = $3, rrule = $4, recurrence = $5, payload = $6, data_source = $7, escalation = $8, next_execution = $9, updated_at = $10 WHERE id = $11 AND fb_user_id = $12",
             payload.name,
             payload.push_token,
-            payload.cron_pattern,
+            cron_pattern,
+            rrule,
+            recurrence_spec,
             payload.payload,
+            payload.data_source,
+            payload.escalation,
             next_execution,
             current_time,
             id.0,
@@ -285,6 +477,585 @@ impl FirebaseMessaging {
             }
         };
 
+        audit::record(
+            pool.0,
+            &audit::user(&fb_user_id),
+            "updated",
+            client_ip(req),
+            Some(&before),
+            Some(&schedule),
+        )
+        .await;
+
+        self.wake.notify_one();
+
+        Ok(ResponseObject::ok(schedule))
+    }
+
+    // Pause schedule by id (only if it belongs to the user)
+    #[oai(
+        path = "/:id/pause",
+        method = "post",
+        operation_id = "fcm::pause_schedule"
+    )]
+    async fn pause_schedule(
+        &self,
+        req: &Request,
+        pool: Data<&PgPool>,
+        id: Path<i32>,
+    ) -> Result<JsonSuccess<FCMSchedule>, JsonError<String>> {
+        self.set_paused(req, pool.0, id.0, true).await
+    }
+
+    // Resume paused schedule by id (only if it belongs to the user)
+    #[oai(
+        path = "/:id/resume",
+        method = "post",
+        operation_id = "fcm::resume_schedule"
+    )]
+    async fn resume_schedule(
+        &self,
+        req: &Request,
+        pool: Data<&PgPool>,
+        id: Path<i32>,
+    ) -> Result<JsonSuccess<FCMSchedule>, JsonError<String>> {
+        self.set_paused(req, pool.0, id.0, false).await
+    }
+
+    // history of changes of the schedule (only if it belongs to the user)
+    #[oai(
+        path = "/:id/audit",
+        method = "get",
+        operation_id = "fcm::get_schedule_audit"
+    )]
+    async fn get_schedule_audit(
+        &self,
+        req: &Request,
+        pool: Data<&PgPool>,
+        id: Path<i32>,
+        /// maximum number of entries to return (default 100, max 1000)
+        limit: Query<Option<i64>>,
+        /// only return entries older than this entry id, for the next page
+        before_id: Query<Option<i64>>,
+    ) -> Result<JsonSuccess<Vec<ScheduleAudit>>, JsonError<String>> {
+        // extract user id from token
+        let data = match extract_claims(req.header("firebase-auth")) {
+            Ok(data) => data,
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        };
+
+        let entries = sqlx::query_as!(
+            ScheduleAudit,
+            "SELECT * FROM fcm_schedule_audit
+            WHERE schedule_id = $1 AND fb_user_id = $2 AND ($3::BIGINT IS NULL OR id < $3)
+            ORDER BY id DESC
+            LIMIT $4",
+            id.0,
+            data.user_id,
+            before_id.0,
+            limit.0.unwrap_or(100).clamp(1, 1000)
+        )
+        .fetch_all(pool.0)
+        .await;
+
+        let entries = match entries {
+            Ok(entries) => entries,
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+
+        // an empty page past the last entry is not an unknown schedule
+        if entries.is_empty() && before_id.0.is_none() {
+            return Err(ResponseObject::not_found("Schedule not found"));
+        }
+
+        Ok(ResponseObject::ok(entries))
+    }
+
+    // deliveries of the schedule including resends and escalations, newest first
+    #[oai(
+        path = "/:id/deliveries",
+        method = "get",
+        operation_id = "fcm::get_deliveries"
+    )]
+    async fn get_deliveries(
+        &self,
+        req: &Request,
+        pool: Data<&PgPool>,
+        id: Path<i32>,
+        /// maximum number of deliveries to return (default 100)
+        limit: Query<Option<i64>>,
+    ) -> Result<JsonSuccess<Vec<Occurrence>>, JsonError<String>> {
+        // extract user id from token
+        let data = match extract_claims(req.header("firebase-auth")) {
+            Ok(data) => data,
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        };
+
+        let deliveries = sqlx::query_as!(
+            Occurrence,
+            "SELECT id, schedule_id, status, acked_at, snoozed_until, snoozes, resend_of, escalation_step, next_escalation_at, error, created_at
+            FROM fcm_delivery WHERE schedule_id = $1 AND fb_user_id = $2 ORDER BY id DESC LIMIT $3",
+            id.0,
+            data.user_id,
+            limit.0.unwrap_or(100).clamp(1, 1000)
+        )
+        .fetch_all(pool.0)
+        .await;
+
+        match deliveries {
+            Ok(deliveries) => Ok(ResponseObject::ok(deliveries)),
+            Err(e) => Err(ResponseObject::internal_server_error(e)),
+        }
+    }
+
+    // acknowledge an occurrence of the schedule, cancels a pending snooze and the escalation
+    #[oai(
+        path = "/:id/ack",
+        method = "post",
+        operation_id = "fcm::ack_occurrence"
+    )]
+    async fn ack_occurrence(
+        &self,
+        req: &Request,
+        pool: Data<&PgPool>,
+        id: Path<i32>,
+        /// occurrence_id from the FCM data payload
+        occurrence_id: Query<i64>,
+    ) -> Result<JsonSuccess<Occurrence>, JsonError<String>> {
+        // extract user id from token
+        let data = match extract_claims(req.header("firebase-auth")) {
+            Ok(data) => data,
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        };
+
+        let occurrence = sqlx::query_as!(
+            Occurrence,
+            "UPDATE fcm_delivery SET acked_at = COALESCE(acked_at, $1), snoozed_until = NULL, next_escalation_at = NULL
+            WHERE id = $2 AND schedule_id = $3 AND fb_user_id = $4 AND resend_of IS NULL AND status = 'sent'
+            RETURNING id, schedule_id, status, acked_at, snoozed_until, snoozes, resend_of, escalation_step, next_escalation_at, error, created_at",
+            Utc::now(),
+            occurrence_id.0,
+            id.0,
+            data.user_id
+        )
+        .fetch_optional(pool.0)
+        .await;
+
+        match occurrence {
+            Ok(Some(occurrence)) => Ok(ResponseObject::ok(occurrence)),
+            Ok(None) => Err(ResponseObject::not_found("Occurrence not found")),
+            Err(e) => Err(ResponseObject::internal_server_error(e)),
+        }
+    }
+
+    // send an occurrence of the schedule again after a while, the schedule itself is unchanged
+    #[oai(
+        path = "/:id/snooze",
+        method = "post",
+        operation_id = "fcm::snooze_occurrence"
+    )]
+    async fn snooze_occurrence(
+        &self,
+        req: &Request,
+        pool: Data<&PgPool>,
+        id: Path<i32>,
+        /// occurrence_id from the FCM data payload
+        occurrence_id: Query<i64>,
+        /// minutes to snooze for (1 - 1440)
+        minutes: Query<i64>,
+    ) -> Result<JsonSuccess<Occurrence>, JsonError<String>> {
+        // extract user id from token
+        let data = match extract_claims(req.header("firebase-auth")) {
+            Ok(data) => data,
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        };
+
+        if !(1..=MAX_SNOOZE_MINUTES).contains(&minutes.0) {
+            return Err(ResponseObject::bad_request(format!(
+                "minutes must be between 1 and {}",
+                MAX_SNOOZE_MINUTES
+            )));
+        }
+
+        let snoozed_until = Utc::now() + Duration::minutes(minutes.0);
+
+        let occurrence = sqlx::query_as!(
+            Occurrence,
+            "UPDATE fcm_delivery SET snoozed_until = $1, snoozes = snoozes + 1,
+            next_escalation_at = CASE WHEN next_escalation_at IS NOT NULL THEN GREATEST(next_escalation_at, $1) END
+            WHERE id = $2 AND schedule_id = $3 AND fb_user_id = $4 AND resend_of IS NULL AND status = 'sent' AND acked_at IS NULL
+            RETURNING id, schedule_id, status, acked_at, snoozed_until, snoozes, resend_of, escalation_step, next_escalation_at, error, created_at",
+            snoozed_until,
+            occurrence_id.0,
+            id.0,
+            data.user_id
+        )
+        .fetch_optional(pool.0)
+        .await;
+
+        match occurrence {
+            Ok(Some(occurrence)) => {
+                self.wake.notify_one();
+                Ok(ResponseObject::ok(occurrence))
+            }
+            Ok(None) => Err(ResponseObject::not_found(
+                "Occurrence not found or already acknowledged",
+            )),
+            Err(e) => Err(ResponseObject::internal_server_error(e)),
+        }
+    }
+
+    // acked, snoozed and ignored occurrences of the schedule (only if it belongs to the user)
+    #[oai(
+        path = "/:id/adherence",
+        method = "get",
+        operation_id = "fcm::get_adherence"
+    )]
+    async fn get_adherence(
+        &self,
+        req: &Request,
+        pool: Data<&PgPool>,
+        id: Path<i32>,
+        /// number of days to aggregate occurrences over (default 30)
+        days: Query<Option<i64>>,
+    ) -> Result<JsonSuccess<Adherence>, JsonError<String>> {
+        // extract user id from token
+        let data = match extract_claims(req.header("firebase-auth")) {
+            Ok(data) => data,
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        };
+
+        let since = Utc::now() - Duration::days(days.0.unwrap_or(30).clamp(1, 365));
+
+        let counts = sqlx::query!(
+            r#"SELECT
+                COUNT(*) AS "sent!",
+                COUNT(*) FILTER (WHERE acked_at IS NOT NULL) AS "acked!",
+                COUNT(*) FILTER (WHERE acked_at IS NULL AND snoozes > 0) AS "snoozed!"
+            FROM fcm_delivery
+            WHERE schedule_id = $1 AND fb_user_id = $2 AND resend_of IS NULL AND status = 'sent' AND created_at > $3"#,
+            id.0,
+            data.user_id,
+            since
+        )
+        .fetch_one(pool.0)
+        .await;
+
+        let counts = match counts {
+            Ok(counts) => counts,
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+
+        Ok(ResponseObject::ok(Adherence {
+            schedule_id: id.0,
+            sent: counts.sent,
+            acked: counts.acked,
+            snoozed: counts.snoozed,
+            ignored: counts.sent - counts.acked - counts.snoozed,
+            adherence: match counts.sent {
+                0 => 0.0,
+                sent => counts.acked as f64 / sent as f64,
+            },
+        }))
+    }
+
+    // create or rotate the secret calendar feed of the user
+    #[oai(
+        path = "/calendar",
+        method = "post",
+        operation_id = "fcm::create_calendar_feed"
+    )]
+    async fn create_calendar_feed(
+        &self,
+        req: &Request,
+        pool: Data<&PgPool>,
+    ) -> Result<JsonSuccess<CalendarFeed>, JsonError<String>> {
+        // extract user id from token
+        let data = match extract_claims(req.header("firebase-auth")) {
+            Ok(data) => data,
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        };
+
+        let fb_user_id = data.user_id;
+
+        let token: String = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(48)
+            .map(char::from)
+            .collect();
+
+        let current_time = Utc::now();
+
+        let feed = sqlx::query!(
+            "INSERT INTO fcm_calendar_feed (fb_user_id, token, created_at) VALUES ($1, $2, $3)
+            ON CONFLICT (fb_user_id) DO UPDATE SET token = EXCLUDED.token, created_at = EXCLUDED.created_at
+            RETURNING token, created_at",
+            fb_user_id,
+            token,
+            current_time
+        )
+        .fetch_one(pool.0)
+        .await;
+
+        let feed = match feed {
+            Ok(feed) => feed,
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+
+        Ok(ResponseObject::created(CalendarFeed {
+            url: format!(
+                "{}/api/v1/fcm/calendar/{}.ics",
+                utils::get_host(),
+                feed.token
+            ),
+            token: feed.token,
+            created_at: feed.created_at,
+        }))
+    }
+
+    // revoke the calendar feed of the user
+    #[oai(
+        path = "/calendar",
+        method = "delete",
+        operation_id = "fcm::delete_calendar_feed"
+    )]
+    async fn delete_calendar_feed(
+        &self,
+        req: &Request,
+        pool: Data<&PgPool>,
+    ) -> Result<JsonSuccess<String>, JsonError<String>> {
+        // extract user id from token
+        let data = match extract_claims(req.header("firebase-auth")) {
+            Ok(data) => data,
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        };
+
+        let result = sqlx::query!(
+            "DELETE FROM fcm_calendar_feed WHERE fb_user_id = $1",
+            data.user_id
+        )
+        .execute(pool.0)
+        .await;
+
+        let result = match result {
+            Ok(result) => result,
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+
+        if result.rows_affected() == 0 {
+            return Err(ResponseObject::not_found("Calendar feed not found"));
+        }
+
+        Ok(ResponseObject::ok("Calendar feed deleted".to_string()))
+    }
+}
+
+impl FirebaseMessaging {
+    async fn set_paused(
+        &self,
+        req: &Request,
+        pool: &PgPool,
+        id: i32,
+        paused: bool,
+    ) -> Result<JsonSuccess<FCMSchedule>, JsonError<String>> {
+        // extract user id from token
+        let data = match extract_claims(req.header("firebase-auth")) {
+            Ok(data) => data,
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        };
+
+        let fb_user_id = data.user_id;
+
+        let before = sqlx::query_as!(
+            FCMSchedule,
+            "SELECT * FROM fcm_schedule WHERE id = $1 AND fb_user_id = $2",
+            id,
+            fb_user_id
+        )
+        .fetch_one(pool)
+        .await;
+
+        let before = match before {
+            Ok(schedule) => schedule,
+            Err(_) => {
+                return Err(ResponseObject::not_found("Schedule not found"));
+            }
+        };
+
+        let schedule = update_paused(
+            pool,
+            before,
+            paused,
+            &audit::user(&fb_user_id),
+            client_ip(req),
+        )
+        .await?;
+
+        self.wake.notify_one();
+
         Ok(ResponseObject::ok(schedule))
     }
 }
+
+/// Pause or resume a schedule on behalf of `actor`
+pub async fn update_paused(
+    pool: &PgPool,
+    before: FCMSchedule,
+    paused: bool,
+    actor: &str,
+    source_ip: Option<String>,
+) -> Result<FCMSchedule, JsonError<String>> {
+    if before.paused == paused {
+        return Ok(before);
+    }
+
+    // a resumed schedule continues from now instead of catching up on missed fires
+    let next_execution = match paused {
+        true => before.next_execution,
+        false => {
+            let recurrence = match Recurrence::for_schedule(
+                before.recurrence.as_ref(),
+                before.cron_pattern.as_deref(),
+                before.rrule.as_deref(),
+            ) {
+                Ok(recurrence) => recurrence,
+                Err(e) => {
+                    return Err(ResponseObject::bad_request(e));
+                }
+            };
+            match next_execution(&recurrence) {
+                Ok(next) => next,
+                Err(e) => {
+                    return Err(ResponseObject::bad_request(e));
+                }
+            }
+        }
+    };
+
+    let schedule = sqlx::query_as!(
+        FCMSchedule,
+        "UPDATE fcm_schedule SET paused = $1, next_execution = $2, updated_at = $3 WHERE id = $4 RETURNING *",
+        paused,
+        next_execution,
+        Utc::now(),
+        before.id
+    )
+    .fetch_one(pool)
+    .await;
+
+    let schedule = match schedule {
+        Ok(schedule) => schedule,
+        Err(e) => {
+            return Err(ResponseObject::internal_server_error(e));
+        }
+    };
+
+    audit::record(
+        pool,
+        actor,
+        if paused { "paused" } else { "resumed" },
+        source_ip,
+        Some(&before),
+        Some(&schedule),
+    )
+    .await;
+
+    Ok(schedule)
+}
+
+#[derive(ApiResponse)]
+pub enum CalendarResponse {
+    #[oai(status = 200, content_type = "text/calendar; charset=utf-8")]
+    Ok(PlainText<String>),
+    #[oai(status = 404)]
+    NotFound(PlainText<String>),
+    #[oai(status = 500)]
+    InternalServerError(PlainText<String>),
+}
+
+/// Calendar feed is authenticated by the secret token in the URL, calendar apps can't send headers
+pub struct FirebaseCalendar;
+
+#[OpenApi(prefix_path = "/fcm/calendar/", tag = "ApiTags::FirebaseMessaging")]
+impl FirebaseCalendar {
+    // iCalendar feed of the upcoming occurrences of all schedules of the user
+    #[oai(
+        path = "/:token",
+        method = "get",
+        operation_id = "fcm::get_calendar_feed"
+    )]
+    async fn get_calendar_feed(
+        &self,
+        pool: Data<&PgPool>,
+        /// secret token of the feed (optionally suffixed with .ics)
+        token: Path<String>,
+    ) -> CalendarResponse {
+        let token = token.0.trim_end_matches(".ics");
+
+        let feed = sqlx::query!(
+            "SELECT fb_user_id FROM fcm_calendar_feed WHERE token = $1",
+            token
+        )
+        .fetch_optional(pool.0)
+        .await;
+
+        let fb_user_id = match feed {
+            Ok(Some(feed)) => feed.fb_user_id,
+            Ok(None) => {
+                return CalendarResponse::NotFound(PlainText(
+                    "Calendar feed not found".to_string(),
+                ));
+            }
+            Err(e) => {
+                error!(error = ?e, "Failed to get calendar feed");
+                return CalendarResponse::InternalServerError(PlainText(
+                    "Failed to get calendar feed".to_string(),
+                ));
+            }
+        };
+
+        let schedules = sqlx::query_as!(
+            FCMSchedule,
+            "SELECT * FROM fcm_schedule WHERE fb_user_id = $1",
+            fb_user_id
+        )
+        .fetch_all(pool.0)
+        .await;
+
+        let schedules = match schedules {
+            Ok(schedules) => schedules,
+            Err(e) => {
+                error!(error = ?e, "Failed to get schedules");
+                return CalendarResponse::InternalServerError(PlainText(
+                    "Failed to get schedules".to_string(),
+                ));
+            }
+        };
+
+        CalendarResponse::Ok(PlainText(render_calendar(&schedules, Utc::now())))
+    }
+}
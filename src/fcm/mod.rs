@@ -1,18 +1,69 @@
+use crate::supervisor::Supervisor;
+use gcp_auth::AuthenticationManager;
 use sqlx::postgres::PgPool;
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use tokio::sync::Notify;
 
+mod admin;
+mod audit;
+mod calendar;
+mod data_source;
+mod escalation;
 mod handler;
 mod model;
-mod utils;
-mod worker;
+pub mod quota;
+pub mod schedule;
+mod solar;
+pub mod utils;
+pub mod worker;
 
-pub async fn fcm_api(pool: PgPool) -> handler::FirebaseMessaging {
-    let service_accounts = worker::read_in_serivce_accounts().await.unwrap();
+/// the scheduler ticks at least once a minute, so it is stuck if it hasn't for this long
+const SCHEDULER_STALL_TIMEOUT: Duration = Duration::from_secs(300);
 
-    let fcm_api = handler::FirebaseMessaging::new(service_accounts.keys().cloned().collect());
+/// Authentication managers of the service accounts, by firebase project id
+pub async fn service_accounts() -> Arc<HashMap<String, AuthenticationManager>> {
+    Arc::new(worker::read_in_serivce_accounts().await.unwrap())
+}
+
+pub async fn fcm_api(
+    pool: PgPool,
+    service_accounts: Arc<HashMap<String, AuthenticationManager>>,
+    supervisor: &Supervisor,
+) -> (
+    handler::FirebaseMessaging,
+    handler::FirebaseCalendar,
+    admin::FirebaseMessagingAdmin,
+) {
+    let wake = Arc::new(Notify::new());
+
+    let fcm_api =
+        handler::FirebaseMessaging::new(service_accounts.keys().cloned().collect(), wake.clone());
+    let admin_api = admin::FirebaseMessagingAdmin::new(service_accounts.clone(), wake.clone());
 
-    tokio::spawn(async move {
-        worker::run_every_minute(service_accounts, &pool).await;
+    let listener_pool = pool.clone();
+    let listener_wake = wake.clone();
+    supervisor.spawn("fcm::listener", None, move |handle| {
+        let pool = listener_pool.clone();
+        let wake = listener_wake.clone();
+        async move { worker::listen_for_changes(&pool, wake, handle).await }
     });
 
-    fcm_api
+    let retention_pool = pool.clone();
+    supervisor.spawn("fcm::audit_retention", None, move |handle| {
+        let pool = retention_pool.clone();
+        async move { audit::run_retention(&pool, handle).await }
+    });
+
+    supervisor.spawn(
+        "fcm::scheduler",
+        Some(SCHEDULER_STALL_TIMEOUT),
+        move |handle| {
+            let service_accounts = service_accounts.clone();
+            let pool = pool.clone();
+            let wake = wake.clone();
+            async move { worker::run_scheduler(service_accounts, &pool, wake, handle).await }
+        },
+    );
+
+    (fcm_api, handler::FirebaseCalendar, admin_api)
 }
@@ -1,4 +1,4 @@
-use chrono::NaiveDateTime;
+use chrono::{DateTime, Utc};
 use poem_openapi::Object;
 use serde::Serialize;
 use serde_json::Value;
@@ -8,8 +8,8 @@ fn payload_example() -> Value {
         .unwrap()
 }
 
-fn cron_example() -> String {
-    "*/1 * * * *".to_string()
+fn cron_example() -> Option<String> {
+    Some("*/1 * * * *".to_string())
 }
 
 fn name_example() -> String {
@@ -41,28 +41,54 @@ pub struct FCMSchedule {
 
     #[oai(validator(min_length = 3, max_length = 64), default = "cron_example")]
     /// cron pattern to schedule the FCM (support multiple cron patterns separated by comma)
-    pub cron_pattern: String,
+    /// 6 field patterns take a leading seconds field (e.g. */30 * * * * *)
+    pub cron_pattern: Option<String>,
+
+    #[oai(validator(min_length = 16, max_length = 1024))]
+    /// RFC 5545 recurrence rule with DTSTART/TZID, used instead of cron_pattern when set
+    /// e.g. DTSTART;TZID=Europe/Berlin:20240105T180000\nRRULE:FREQ=MONTHLY;BYDAY=-1FR
+    pub rrule: Option<String>,
+
+    /// interval, random window or solar recurrence, used instead of rrule and cron_pattern when set
+    /// e.g. {"type": "interval", "every_minutes": 45, "active_hours": {"start": "08:00", "end": "22:00"}, "timezone": "Europe/Berlin"}
+    /// or {"type": "window", "start": "10:00", "end": "18:00", "days": ["mon", "tue", "wed", "thu", "fri"], "timezone": "Europe/Berlin"}
+    /// or {"type": "solar", "latitude": 52.52, "longitude": 13.40, "event": "sunset", "offset_minutes": -30}
+    /// (events: sunrise, sunset, civil_dawn, civil_dusk, nautical_dawn, nautical_dusk)
+    /// anchor (interval, RFC 3339) and seed (window) are set on creation when not given
+    pub recurrence: Option<Value>,
 
     /// payload to send to the FCM (JSON) e.g. {"some": "data", "another": "data"}
     /// If title and body are present, they will be used as notification
     #[oai(default = "payload_example")]
     pub payload: Value,
 
+    /// HTTP source of live data fetched just before sending, its value replaces {{data}} in the payload
+    /// e.g. {"url": "https://api.example.com/weather", "headers": {"Accept": "application/json"}, "path": "$.current.temp", "timeout_ms": 3000, "fallback": "unknown"}
+    pub data_source: Option<Value>,
+
+    /// steps to take while an occurrence is not acknowledged, after_minutes counts from the send
+    /// e.g. [{"after_minutes": 10, "action": "resend"}, {"after_minutes": 30, "action": "push", "push_token": "..."}, {"after_minutes": 60, "action": "webhook", "url": "https://example.com/hook"}]
+    pub escalation: Option<Value>,
+
+    #[oai(read_only)]
+    /// whether the schedule is paused
+    pub paused: bool,
+
     #[oai(read_only)]
     /// last time the FCM was sent
-    pub last_execution: NaiveDateTime,
+    pub last_execution: DateTime<Utc>,
 
     #[oai(read_only)]
     /// next time the FCM will be sent
-    pub next_execution: NaiveDateTime,
+    pub next_execution: DateTime<Utc>,
 
     #[oai(read_only)]
     /// created time of the schedule
-    pub created_at: NaiveDateTime,
+    pub created_at: DateTime<Utc>,
 
     #[oai(read_only)]
     /// last time the schedule was updated
-    pub updated_at: NaiveDateTime,
+    pub updated_at: DateTime<Utc>,
 }
 
 /// Update FCM Schedule schema
@@ -78,10 +104,168 @@ pub struct UpdateSchedule {
 
     #[oai(validator(min_length = 3, max_length = 64), default = "cron_example")]
     /// cron pattern to schedule the FCM (support multiple cron patterns separated by comma)
-    pub cron_pattern: String,
+    /// 6 field patterns take a leading seconds field (e.g. */30 * * * * *)
+    pub cron_pattern: Option<String>,
+
+    #[oai(validator(min_length = 16, max_length = 1024))]
+    /// RFC 5545 recurrence rule with DTSTART/TZID, used instead of cron_pattern when set
+    /// e.g. DTSTART;TZID=Europe/Berlin:20240105T180000\nRRULE:FREQ=MONTHLY;BYDAY=-1FR
+    pub rrule: Option<String>,
+
+    /// interval, random window or solar recurrence, used instead of rrule and cron_pattern when set
+    /// e.g. {"type": "interval", "every_minutes": 45, "active_hours": {"start": "08:00", "end": "22:00"}, "timezone": "Europe/Berlin"}
+    /// or {"type": "window", "start": "10:00", "end": "18:00", "days": ["mon", "tue", "wed", "thu", "fri"], "timezone": "Europe/Berlin"}
+    /// or {"type": "solar", "latitude": 52.52, "longitude": 13.40, "event": "sunset", "offset_minutes": -30}
+    /// (events: sunrise, sunset, civil_dawn, civil_dusk, nautical_dawn, nautical_dusk)
+    /// anchor (interval, RFC 3339) and seed (window) keep their stored value when not given
+    pub recurrence: Option<Value>,
 
     /// payload to send to the FCM (JSON) e.g. {"some": "data", "another": "data"}
     /// If title and body are present, they will be used as notification
     #[oai(default = "payload_example")]
     pub payload: Value,
+
+    /// HTTP source of live data fetched just before sending, its value replaces {{data}} in the payload
+    /// e.g. {"url": "https://api.example.com/weather", "headers": {"Accept": "application/json"}, "path": "$.current.temp", "timeout_ms": 3000, "fallback": "unknown"}
+    pub data_source: Option<Value>,
+
+    /// steps to take while an occurrence is not acknowledged, after_minutes counts from the send
+    /// e.g. [{"after_minutes": 10, "action": "resend"}, {"after_minutes": 30, "action": "push", "push_token": "..."}, {"after_minutes": 60, "action": "webhook", "url": "https://example.com/hook"}]
+    pub escalation: Option<Value>,
+}
+
+/// Calendar feed schema
+#[derive(Debug, Object, Clone, Eq, PartialEq)]
+pub struct CalendarFeed {
+    /// secret token of the feed, anyone with it can read the calendar
+    pub token: String,
+
+    /// iCalendar (ICS) URL to subscribe to from a calendar app
+    pub url: String,
+
+    /// created time of the feed
+    pub created_at: DateTime<Utc>,
+}
+
+/// Quota usage schema (sends are counted over the last 24 hours)
+#[derive(Debug, Object, Clone, Eq, PartialEq)]
+pub struct Usage {
+    /// number of schedules of the user
+    pub schedules: i64,
+    /// maximum number of schedules per user
+    pub max_schedules: i64,
+    /// FCMs sent to the user
+    pub user_sends: i64,
+    /// maximum FCMs sent to a user
+    pub max_user_sends: i64,
+    /// FCMs sent by the firebase project
+    pub project_sends: i64,
+    /// maximum FCMs sent by a firebase project
+    pub max_project_sends: i64,
+    /// minimum seconds between two fires of a schedule
+    pub min_interval_seconds: i64,
+}
+
+impl Usage {
+    /// Reason the next send would exceed the daily quota, if any
+    pub fn sends_exceeded(&self) -> Option<String> {
+        if self.user_sends >= self.max_user_sends {
+            return Some(format!(
+                "User reached the daily limit of {} sends",
+                self.max_user_sends
+            ));
+        }
+        if self.project_sends >= self.max_project_sends {
+            return Some(format!(
+                "Project reached the daily limit of {} sends",
+                self.max_project_sends
+            ));
+        }
+        None
+    }
+}
+
+/// Audit entry of a schedule change
+#[derive(Debug, Object, Clone, PartialEq)]
+pub struct ScheduleAudit {
+    /// ID of the audit entry
+    pub id: i64,
+    /// ID of the schedule
+    pub schedule_id: i32,
+    /// firebase user id of the schedule owner
+    pub fb_user_id: String,
+    /// who made the change (user:{id}, admin or worker)
+    pub actor: String,
+    /// what happened (created, updated, deleted, paused, resumed)
+    pub action: String,
+    /// IP address the change came from
+    pub source_ip: Option<String>,
+    /// changed fields as {"field": {"before": ..., "after": ...}}, push_token, escalation and
+    /// data_source only as {"field": {"changed": true}}
+    pub diff: Value,
+    /// time of the change
+    pub created_at: DateTime<Utc>,
+}
+
+/// Send statistics of a firebase project
+#[derive(Debug, Object, Clone, Eq, PartialEq)]
+pub struct ProjectStats {
+    /// firebase project id
+    pub fb_project_id: String,
+    /// number of schedules in the project
+    pub schedules: i64,
+    /// number of paused schedules in the project
+    pub paused_schedules: i64,
+    /// FCMs sent successfully in the period
+    pub sent: i64,
+    /// FCMs that failed to send in the period
+    pub failed: i64,
+    /// FCMs skipped due to quotas in the period
+    pub throttled: i64,
+    /// last time an FCM was sent successfully
+    pub last_sent_at: Option<DateTime<Utc>>,
+}
+
+/// Delivery of an occurrence of a schedule, or of a resend of it
+#[derive(Debug, Object, Clone, Eq, PartialEq)]
+pub struct Occurrence {
+    /// occurrence id, sent as occurrence_id in the FCM data payload
+    pub id: i64,
+    /// ID of the schedule
+    pub schedule_id: Option<i32>,
+    /// sent, failed or throttled
+    pub status: String,
+    /// time the user acknowledged the notification
+    pub acked_at: Option<DateTime<Utc>>,
+    /// time the notification will be sent again
+    pub snoozed_until: Option<DateTime<Utc>>,
+    /// number of times the notification was snoozed
+    pub snoozes: i32,
+    /// original occurrence of a snoozed or escalated resend
+    pub resend_of: Option<i64>,
+    /// escalation steps taken for an occurrence, or the step of an escalated resend
+    pub escalation_step: i32,
+    /// time the next escalation step is due
+    pub next_escalation_at: Option<DateTime<Utc>>,
+    /// error of a failed send
+    pub error: Option<String>,
+    /// time the occurrence was sent
+    pub created_at: DateTime<Utc>,
+}
+
+/// How the user responded to the occurrences of a schedule
+#[derive(Debug, Object, Clone, PartialEq)]
+pub struct Adherence {
+    /// ID of the schedule
+    pub schedule_id: i32,
+    /// occurrences sent in the period
+    pub sent: i64,
+    /// occurrences acknowledged
+    pub acked: i64,
+    /// occurrences snoozed and not acknowledged (yet)
+    pub snoozed: i64,
+    /// occurrences neither acknowledged nor snoozed
+    pub ignored: i64,
+    /// share of the sent occurrences that were acknowledged (0.0 - 1.0)
+    pub adherence: f64,
 }
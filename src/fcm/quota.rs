@@ -0,0 +1,92 @@
+use super::{model::Usage, schedule::Recurrence};
+use crate::utils::env_or;
+use chrono::{Duration, Utc};
+use lazy_static::lazy_static;
+use sqlx::postgres::PgPool;
+
+/// number of upcoming occurrences inspected to find the shortest interval of a schedule
+pub const INTERVAL_SAMPLE: u16 = 32;
+
+lazy_static! {
+    pub static ref QUOTAS: Quotas = Quotas::from_env();
+}
+
+/// Limits on schedules and sends, configured through environment variables
+pub struct Quotas {
+    /// maximum number of schedules per user (FCM_MAX_SCHEDULES_PER_USER)
+    pub max_schedules_per_user: i64,
+    /// minimum seconds between two fires of a schedule (FCM_MIN_INTERVAL_SECONDS)
+    pub min_interval_seconds: i64,
+    /// maximum sends per user in the last 24 hours (FCM_MAX_DAILY_SENDS_PER_USER)
+    pub max_daily_sends_per_user: i64,
+    /// maximum sends per project in the last 24 hours (FCM_MAX_DAILY_SENDS_PER_PROJECT)
+    pub max_daily_sends_per_project: i64,
+}
+
+impl Quotas {
+    fn from_env() -> Self {
+        Quotas {
+            max_schedules_per_user: env_or("FCM_MAX_SCHEDULES_PER_USER", 25),
+            min_interval_seconds: env_or("FCM_MIN_INTERVAL_SECONDS", 60),
+            max_daily_sends_per_user: env_or("FCM_MAX_DAILY_SENDS_PER_USER", 500),
+            max_daily_sends_per_project: env_or("FCM_MAX_DAILY_SENDS_PER_PROJECT", 10000),
+        }
+    }
+}
+
+/// Reject recurrences that fire more often than every `min_interval_seconds`
+pub fn check_interval(recurrence: &Recurrence, min_interval_seconds: i64) -> Result<(), String> {
+    let shortest = recurrence
+        .shortest_interval(INTERVAL_SAMPLE)?
+        .map(|interval| interval.num_seconds());
+
+    match shortest {
+        Some(shortest) if shortest < min_interval_seconds => Err(format!(
+            "Recurrence fires every {} seconds, minimum interval is {} seconds",
+            shortest, min_interval_seconds
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Current consumption of the user and the project against the quotas
+pub async fn usage(
+    pool: &PgPool,
+    fb_user_id: &str,
+    fb_project_id: &str,
+) -> Result<Usage, sqlx::Error> {
+    let since = Utc::now() - Duration::days(1);
+
+    let schedules = sqlx::query_scalar!(
+        r#"SELECT COUNT(*) as "count!" FROM fcm_schedule WHERE fb_user_id = $1"#,
+        fb_user_id
+    )
+    .fetch_one(pool)
+    .await?;
+
+    let user_sends = sqlx::query_scalar!(
+        r#"SELECT COUNT(*) as "count!" FROM fcm_delivery WHERE fb_user_id = $1 AND created_at > $2 AND status != 'throttled'"#,
+        fb_user_id,
+        since
+    )
+    .fetch_one(pool)
+    .await?;
+
+    let project_sends = sqlx::query_scalar!(
+        r#"SELECT COUNT(*) as "count!" FROM fcm_delivery WHERE fb_project_id = $1 AND created_at > $2 AND status != 'throttled'"#,
+        fb_project_id,
+        since
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(Usage {
+        schedules,
+        max_schedules: QUOTAS.max_schedules_per_user,
+        user_sends,
+        max_user_sends: QUOTAS.max_daily_sends_per_user,
+        project_sends,
+        max_project_sends: QUOTAS.max_daily_sends_per_project,
+        min_interval_seconds: QUOTAS.min_interval_seconds,
+    })
+}
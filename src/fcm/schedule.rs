@@ -0,0 +1,737 @@
+use super::solar::{event_time, SolarEvent};
+use chrono::{
+    DateTime, Datelike, Duration, NaiveDateTime, NaiveTime, TimeZone, Timelike, Utc, Weekday,
+};
+use chrono_tz::Tz as Timezone;
+use cron_parser::{parse, parse_field, ParseError};
+use rand::Rng;
+use rrule::{RRuleSet, Tz};
+use serde::Deserialize;
+use serde_json::Value;
+
+/// interval and window recurrences give up after this many skipped days or steps
+const MAX_SEARCH: usize = 1000;
+/// longest interval of an interval recurrence (a year)
+const MAX_INTERVAL_MINUTES: i64 = 525600;
+/// largest offset from a solar event (12 hours)
+const MAX_SOLAR_OFFSET_MINUTES: i64 = 720;
+
+/// How often a schedule fires
+pub enum Recurrence {
+    /// cron pattern (e.g. `*/5 * * * *`), with an optional leading seconds field (e.g. `*/10 * * * * *`)
+    Cron(String),
+    /// RFC 5545 recurrence rule, including DTSTART (and optionally TZID)
+    RRule(Box<RRuleSet>),
+    /// fixed interval from an anchor, optionally only within active hours
+    Interval(Interval),
+    /// once a day at a random but deterministic time within a window
+    Window(Window),
+    /// once a day relative to the position of the sun at a location
+    Solar(Solar),
+}
+
+/// Interval or random window recurrence of a schedule, stored as JSON
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum Spec {
+    Interval {
+        every_minutes: i64,
+        anchor: DateTime<Utc>,
+        active_hours: Option<Hours>,
+        timezone: Option<String>,
+    },
+    Window {
+        start: String,
+        end: String,
+        #[serde(default)]
+        days: Vec<Weekday>,
+        timezone: Option<String>,
+        seed: u64,
+    },
+    Solar {
+        latitude: f64,
+        longitude: f64,
+        event: SolarEvent,
+        #[serde(default)]
+        offset_minutes: i64,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Hours {
+    start: String,
+    end: String,
+}
+
+pub struct Interval {
+    every: Duration,
+    anchor: DateTime<Utc>,
+    /// local start and end of the active hours, end before start spans midnight
+    active_hours: Option<(NaiveTime, NaiveTime)>,
+    timezone: Timezone,
+}
+
+pub struct Window {
+    start: NaiveTime,
+    end: NaiveTime,
+    days: Vec<Weekday>,
+    timezone: Timezone,
+    seed: u64,
+}
+
+pub struct Solar {
+    latitude: f64,
+    longitude: f64,
+    event: SolarEvent,
+    offset: Duration,
+}
+
+impl Recurrence {
+    /// Build the recurrence of a schedule, rrule takes precedence over cron_pattern
+    pub fn new(cron_pattern: Option<&str>, rrule: Option<&str>) -> Result<Self, String> {
+        if let Some(rrule) = rrule {
+            let rrule_set = match rrule.trim().parse::<RRuleSet>() {
+                Ok(rrule_set) => rrule_set,
+                Err(e) => {
+                    return Err(format!("Invalid rrule: {}", e));
+                }
+            };
+            return Ok(Recurrence::RRule(Box::new(rrule_set)));
+        }
+
+        match cron_pattern {
+            Some(cron_pattern) => {
+                next_cron(cron_pattern, Utc::now())?;
+                Ok(Recurrence::Cron(cron_pattern.to_string()))
+            }
+            None => Err("Either cron_pattern or rrule must be set".to_string()),
+        }
+    }
+
+    /// Build the recurrence of an FCM schedule, recurrence takes precedence over rrule and cron_pattern
+    pub fn for_schedule(
+        recurrence: Option<&Value>,
+        cron_pattern: Option<&str>,
+        rrule: Option<&str>,
+    ) -> Result<Self, String> {
+        match recurrence {
+            Some(recurrence) => Recurrence::from_spec(recurrence),
+            None => Recurrence::new(cron_pattern, rrule),
+        }
+    }
+
+    /// Build an interval or random window recurrence from its JSON spec
+    pub fn from_spec(spec: &Value) -> Result<Self, String> {
+        let spec: Spec = match serde_json::from_value(spec.clone()) {
+            Ok(spec) => spec,
+            Err(e) => {
+                return Err(format!("Invalid recurrence: {}", e));
+            }
+        };
+
+        match spec {
+            Spec::Interval {
+                every_minutes,
+                anchor,
+                active_hours,
+                timezone,
+            } => {
+                if !(1..=MAX_INTERVAL_MINUTES).contains(&every_minutes) {
+                    return Err(format!(
+                        "Invalid recurrence: every_minutes must be between 1 and {}",
+                        MAX_INTERVAL_MINUTES
+                    ));
+                }
+
+                let active_hours = match active_hours {
+                    Some(hours) => {
+                        let start = parse_time(&hours.start)?;
+                        let end = parse_time(&hours.end)?;
+                        if start == end {
+                            return Err(
+                                "Invalid recurrence: active hours must not be empty".to_string()
+                            );
+                        }
+                        Some((start, end))
+                    }
+                    None => None,
+                };
+
+                Ok(Recurrence::Interval(Interval {
+                    every: Duration::minutes(every_minutes),
+                    anchor: anchor.with_nanosecond(0).unwrap_or(anchor),
+                    active_hours,
+                    timezone: parse_timezone(timezone.as_deref())?,
+                }))
+            }
+            Spec::Window {
+                start,
+                end,
+                days,
+                timezone,
+                seed,
+            } => {
+                let start = parse_time(&start)?;
+                let end = parse_time(&end)?;
+                if end <= start {
+                    return Err("Invalid recurrence: window must end after it starts".to_string());
+                }
+
+                Ok(Recurrence::Window(Window {
+                    start,
+                    end,
+                    days,
+                    timezone: parse_timezone(timezone.as_deref())?,
+                    seed,
+                }))
+            }
+            Spec::Solar {
+                latitude,
+                longitude,
+                event,
+                offset_minutes,
+            } => {
+                if !(-90.0..=90.0).contains(&latitude) || !(-180.0..=180.0).contains(&longitude) {
+                    return Err("Invalid recurrence: invalid latitude or longitude".to_string());
+                }
+                if offset_minutes.abs() > MAX_SOLAR_OFFSET_MINUTES {
+                    return Err(format!(
+                        "Invalid recurrence: offset_minutes must be within {} minutes",
+                        MAX_SOLAR_OFFSET_MINUTES
+                    ));
+                }
+
+                Ok(Recurrence::Solar(Solar {
+                    latitude,
+                    longitude,
+                    event,
+                    offset: Duration::minutes(offset_minutes),
+                }))
+            }
+        }
+    }
+
+    /// Next occurrence strictly after `after`, `None` if the recurrence has ended
+    pub fn next_after(&self, after: DateTime<Utc>) -> Result<Option<DateTime<Utc>>, String> {
+        Ok(self.upcoming(after, 1)?.into_iter().next())
+    }
+
+    /// Next occurrence strictly after `after`, never() once the recurrence has ended
+    pub fn next_or_never(&self, after: DateTime<Utc>) -> Result<DateTime<Utc>, String> {
+        Ok(self.next_after(after)?.unwrap_or_else(never))
+    }
+
+    /// Shortest gap between the next `sample` occurrences, `None` if there are less than two
+    pub fn shortest_interval(&self, sample: u16) -> Result<Option<Duration>, String> {
+        let occurrences = self.upcoming(Utc::now(), sample)?;
+
+        Ok(occurrences.windows(2).map(|pair| pair[1] - pair[0]).min())
+    }
+
+    /// Up to `limit` occurrences strictly after `after`
+    pub fn upcoming(&self, after: DateTime<Utc>, limit: u16) -> Result<Vec<DateTime<Utc>>, String> {
+        match self {
+            Recurrence::Cron(cron_pattern) => {
+                let mut occurrences = Vec::with_capacity(limit as usize);
+                let mut cursor = after;
+                for _ in 0..limit {
+                    cursor = next_cron(cron_pattern, cursor)?;
+                    occurrences.push(cursor);
+                }
+                Ok(occurrences)
+            }
+            Recurrence::Interval(interval) => Ok(collect(after, limit, |t| interval.next_after(t))),
+            Recurrence::Window(window) => Ok(collect(after, limit, |t| window.next_after(t))),
+            Recurrence::Solar(solar) => Ok(collect(after, limit, |t| solar.next_after(t))),
+            Recurrence::RRule(rrule_set) => {
+                // `after` is inclusive in rrule, so ask for one extra and drop `after` itself
+                let result = rrule_set
+                    .as_ref()
+                    .clone()
+                    .after(after.with_timezone(&Tz::UTC))
+                    .all(limit.saturating_add(1));
+
+                Ok(result
+                    .dates
+                    .into_iter()
+                    .map(|date| date.with_timezone(&Utc))
+                    .filter(|date| *date > after)
+                    .take(limit as usize)
+                    .collect())
+            }
+        }
+    }
+}
+
+impl Interval {
+    fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let every = self.every.num_seconds();
+        let mut from = after;
+
+        for _ in 0..MAX_SEARCH {
+            let elapsed = (from - self.anchor).num_seconds();
+            let steps = match elapsed < 0 {
+                true => 0,
+                false => elapsed.div_euclid(every) + 1,
+            };
+            let candidate = self.anchor + Duration::seconds(steps * every);
+
+            let (start, end) = match self.active_hours {
+                Some(hours) => hours,
+                None => return Some(candidate),
+            };
+
+            let local = candidate.with_timezone(&self.timezone).time();
+            let active = match start < end {
+                true => local >= start && local < end,
+                false => local >= start || local < end,
+            };
+            if active {
+                return Some(candidate);
+            }
+
+            // continue from the next time the active hours start
+            from = next_local_time(candidate, start, &self.timezone) - Duration::seconds(1);
+        }
+
+        None
+    }
+}
+
+impl Window {
+    fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let length = (self.end - self.start).num_seconds() as u64;
+        // start a day early, the window of the previous local day may still be ahead in UTC
+        let mut date = after.with_timezone(&self.timezone).date_naive() - Duration::days(1);
+
+        for _ in 0..MAX_SEARCH {
+            if self.days.is_empty() || self.days.contains(&date.weekday()) {
+                let offset = mix(self.seed, date.num_days_from_ce()) % length;
+                if let Some(start) = to_utc(date.and_time(self.start), &self.timezone) {
+                    let candidate = start + Duration::seconds(offset as i64);
+                    if candidate > after {
+                        return Some(candidate);
+                    }
+                }
+            }
+            date = date.succ_opt()?;
+        }
+
+        None
+    }
+}
+
+impl Solar {
+    fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        // start a few days early, offsets and far east/west longitudes shift events across days
+        let mut date = (after - self.offset).date_naive() - Duration::days(2);
+
+        // polar day or night skip the days without the event
+        for _ in 0..MAX_SEARCH {
+            if let Some(time) = event_time(date, self.latitude, self.longitude, self.event) {
+                let candidate = time + self.offset;
+                if candidate > after {
+                    return Some(candidate);
+                }
+            }
+            date = date.succ_opt()?;
+        }
+
+        None
+    }
+}
+
+/// Fill in the parts of a recurrence spec chosen at creation: the random seed of a window and
+/// the anchor of an interval (now)
+pub fn normalize_spec(spec: Value) -> Value {
+    let mut spec = match spec {
+        Value::Object(spec) => spec,
+        spec => return spec,
+    };
+
+    match spec.get("type").and_then(Value::as_str) {
+        Some("window") if !spec.contains_key("seed") => {
+            spec.insert(
+                "seed".to_string(),
+                Value::from(rand::thread_rng().gen::<u32>()),
+            );
+        }
+        Some("interval") if !spec.contains_key("anchor") => {
+            let now = Utc::now()
+                .with_second(0)
+                .and_then(|now| now.with_nanosecond(0));
+            spec.insert(
+                "anchor".to_string(),
+                Value::from(now.map(|now| now.to_rfc3339())),
+            );
+        }
+        _ => {}
+    }
+
+    Value::Object(spec)
+}
+
+/// normalize_spec for an updated spec, an anchor or seed left out keeps its stored value so the
+/// update doesn't shift when the schedule fires
+pub fn normalize_update(spec: Value, stored: Option<&Value>) -> Value {
+    let spec = match (spec, stored) {
+        (Value::Object(mut spec), Some(Value::Object(stored)))
+            if spec.get("type") == stored.get("type") =>
+        {
+            for key in ["anchor", "seed"] {
+                if let (false, Some(value)) = (spec.contains_key(key), stored.get(key)) {
+                    spec.insert(key.to_string(), value.clone());
+                }
+            }
+            Value::Object(spec)
+        }
+        (spec, _) => spec,
+    };
+
+    normalize_spec(spec)
+}
+
+fn collect(
+    after: DateTime<Utc>,
+    limit: u16,
+    next_after: impl Fn(DateTime<Utc>) -> Option<DateTime<Utc>>,
+) -> Vec<DateTime<Utc>> {
+    let mut occurrences = Vec::with_capacity(limit as usize);
+    let mut cursor = after;
+    for _ in 0..limit {
+        match next_after(cursor) {
+            Some(next) => {
+                occurrences.push(next);
+                cursor = next;
+            }
+            None => break,
+        }
+    }
+    occurrences
+}
+
+fn parse_time(time: &str) -> Result<NaiveTime, String> {
+    NaiveTime::parse_from_str(time, "%H:%M")
+        .map_err(|_| format!("Invalid recurrence: invalid time {}, expected HH:MM", time))
+}
+
+fn parse_timezone(timezone: Option<&str>) -> Result<Timezone, String> {
+    match timezone {
+        Some(timezone) => timezone
+            .parse()
+            .map_err(|_| format!("Invalid recurrence: unknown timezone {}", timezone)),
+        None => Ok(Timezone::UTC),
+    }
+}
+
+/// First instant after `after` at which the local time is `time`
+fn next_local_time(after: DateTime<Utc>, time: NaiveTime, timezone: &Timezone) -> DateTime<Utc> {
+    let mut date = after.with_timezone(timezone).date_naive();
+    loop {
+        if let Some(next) = to_utc(date.and_time(time), timezone) {
+            if next > after {
+                return next;
+            }
+        }
+        date = match date.succ_opt() {
+            Some(date) => date,
+            None => return after + Duration::days(1),
+        };
+    }
+}
+
+/// Local time to UTC, times skipped by a DST change are moved forward by an hour
+fn to_utc(local: NaiveDateTime, timezone: &Timezone) -> Option<DateTime<Utc>> {
+    timezone
+        .from_local_datetime(&local)
+        .earliest()
+        .or_else(|| {
+            timezone
+                .from_local_datetime(&(local + Duration::hours(1)))
+                .earliest()
+        })
+        .map(|time| time.with_timezone(&Utc))
+}
+
+/// splitmix64 of the seed and the day, stable across builds unlike std hashers
+fn mix(seed: u64, day: i32) -> u64 {
+    let mut z = seed ^ (day as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// next_execution of a schedule whose recurrence has ended, so the worker never picks it up again
+pub fn never() -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(9999, 12, 31, 23, 59, 59).unwrap()
+}
+
+fn next_cron(cron_pattern: &str, after: DateTime<Utc>) -> Result<DateTime<Utc>, String> {
+    // cron_parser panics on some malformed patterns
+    let next = std::panic::catch_unwind(|| next_cron_unchecked(cron_pattern, after));
+
+    match next {
+        Ok(Ok(next)) => Ok(next),
+        _ => Err("Invalid cron pattern".to_string()),
+    }
+}
+
+// 6 field patterns have a leading seconds field, the rest is matched as a regular 5 field pattern
+fn next_cron_unchecked(
+    cron_pattern: &str,
+    after: DateTime<Utc>,
+) -> Result<DateTime<Utc>, ParseError> {
+    let fields: Vec<&str> = cron_pattern.split_whitespace().collect();
+    if fields.len() != 6 {
+        return parse(cron_pattern, &after);
+    }
+
+    let seconds = parse_field(fields[0], 0, 59)?;
+    let first_second = match seconds.first() {
+        Some(second) => *second,
+        None => return Err(ParseError::InvalidCron),
+    };
+    let minute_pattern = fields[1..].join(" ");
+
+    let current_minute = after
+        .with_second(0)
+        .and_then(|minute| minute.with_nanosecond(0))
+        .ok_or(ParseError::InvalidCron)?;
+
+    // parse returns the first matching minute strictly after the given one
+    if parse(&minute_pattern, &(current_minute - Duration::minutes(1)))? == current_minute {
+        let next_second = seconds
+            .iter()
+            .map(|second| current_minute + Duration::seconds(*second as i64))
+            .find(|candidate| *candidate > after);
+        if let Some(next) = next_second {
+            return Ok(next);
+        }
+    }
+
+    let next_minute = parse(&minute_pattern, &current_minute)?;
+    Ok(next_minute + Duration::seconds(first_second as i64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn at(time: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(time)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    fn spec(spec: Value) -> Recurrence {
+        Recurrence::from_spec(&spec).unwrap()
+    }
+
+    #[test]
+    fn interval_is_anchored() {
+        let recurrence = spec(json!({
+            "type": "interval",
+            "every_minutes": 45,
+            "anchor": "2024-01-01T00:00:30.500Z",
+        }));
+
+        // before the anchor the anchor itself is next, nanoseconds are dropped
+        assert_eq!(
+            recurrence.next_after(at("2023-12-31T12:00:00Z")).unwrap(),
+            Some(at("2024-01-01T00:00:30Z"))
+        );
+        // the anchor and exact occurrences are excluded
+        assert_eq!(
+            recurrence.next_after(at("2024-01-01T00:00:30Z")).unwrap(),
+            Some(at("2024-01-01T00:45:30Z"))
+        );
+        assert_eq!(
+            recurrence.next_after(at("2024-01-01T00:50:00Z")).unwrap(),
+            Some(at("2024-01-01T01:30:30Z"))
+        );
+        assert_eq!(
+            recurrence.upcoming(at("2024-01-02T00:00:00Z"), 3).unwrap(),
+            vec![
+                at("2024-01-02T00:00:30Z"),
+                at("2024-01-02T00:45:30Z"),
+                at("2024-01-02T01:30:30Z"),
+            ]
+        );
+    }
+
+    #[test]
+    fn interval_skips_inactive_hours() {
+        let recurrence = spec(json!({
+            "type": "interval",
+            "every_minutes": 60,
+            "anchor": "2024-03-01T00:00:00Z",
+            "active_hours": {"start": "08:00", "end": "22:00"},
+            "timezone": "Europe/Berlin",
+        }));
+
+        // the start is active
+        assert_eq!(
+            recurrence.next_after(at("2024-03-29T06:30:00Z")).unwrap(),
+            Some(at("2024-03-29T07:00:00Z"))
+        );
+        // the end is not, the next day starts at 08:00 summer time
+        assert_eq!(
+            recurrence.next_after(at("2024-03-30T20:30:00Z")).unwrap(),
+            Some(at("2024-03-31T06:00:00Z"))
+        );
+    }
+
+    #[test]
+    fn interval_active_hours_span_midnight() {
+        let recurrence = spec(json!({
+            "type": "interval",
+            "every_minutes": 60,
+            "anchor": "2024-01-01T00:00:00Z",
+            "active_hours": {"start": "22:00", "end": "02:00"},
+        }));
+
+        assert_eq!(
+            recurrence.upcoming(at("2024-01-01T12:00:00Z"), 5).unwrap(),
+            vec![
+                at("2024-01-01T22:00:00Z"),
+                at("2024-01-01T23:00:00Z"),
+                at("2024-01-02T00:00:00Z"),
+                at("2024-01-02T01:00:00Z"),
+                at("2024-01-02T22:00:00Z"),
+            ]
+        );
+    }
+
+    #[test]
+    fn window_fires_once_a_day_within_the_window() {
+        let recurrence = spec(json!({
+            "type": "window",
+            "start": "10:00",
+            "end": "18:00",
+            "days": ["mon", "wed"],
+            "timezone": "Europe/Berlin",
+            "seed": 42,
+        }));
+        let berlin: Timezone = "Europe/Berlin".parse().unwrap();
+
+        let occurrences = recurrence.upcoming(at("2024-01-01T00:00:00Z"), 6).unwrap();
+        assert_eq!(occurrences.len(), 6);
+        let mut days = Vec::new();
+        for occurrence in &occurrences {
+            let local = occurrence.with_timezone(&berlin);
+            assert!(local.time() >= NaiveTime::from_hms_opt(10, 0, 0).unwrap());
+            assert!(local.time() < NaiveTime::from_hms_opt(18, 0, 0).unwrap());
+            assert!([Weekday::Mon, Weekday::Wed].contains(&local.weekday()));
+            days.push(local.date_naive());
+        }
+        days.dedup();
+        assert_eq!(days.len(), 6);
+
+        // the time of a day is stable, right before it is still next
+        let first = occurrences[0];
+        assert_eq!(
+            recurrence.next_after(first - Duration::seconds(1)).unwrap(),
+            Some(first)
+        );
+        assert_eq!(recurrence.next_after(first).unwrap(), Some(occurrences[1]));
+    }
+
+    #[test]
+    fn cron_with_seconds() {
+        let recurrence = Recurrence::new(Some("*/10 * * * * *"), None).unwrap();
+        assert_eq!(
+            recurrence.upcoming(at("2024-01-01T12:00:45Z"), 3).unwrap(),
+            vec![
+                at("2024-01-01T12:00:50Z"),
+                at("2024-01-01T12:01:00Z"),
+                at("2024-01-01T12:01:10Z"),
+            ]
+        );
+
+        let recurrence = Recurrence::new(Some("15 30 9 * * *"), None).unwrap();
+        assert_eq!(
+            recurrence.next_after(at("2024-01-01T09:30:00Z")).unwrap(),
+            Some(at("2024-01-01T09:30:15Z"))
+        );
+        assert_eq!(
+            recurrence.next_after(at("2024-01-01T09:30:15Z")).unwrap(),
+            Some(at("2024-01-02T09:30:15Z"))
+        );
+    }
+
+    #[test]
+    fn cron_without_seconds() {
+        let recurrence = Recurrence::new(Some("*/5 * * * *"), None).unwrap();
+        assert_eq!(
+            recurrence.next_after(at("2024-01-01T12:00:00Z")).unwrap(),
+            Some(at("2024-01-01T12:05:00Z"))
+        );
+        assert!(Recurrence::new(Some("not a cron"), None).is_err());
+    }
+
+    #[test]
+    fn exhausted_recurrence_never_fires() {
+        let recurrence = Recurrence::new(
+            None,
+            Some("DTSTART:20240101T090000Z\nRRULE:FREQ=DAILY;COUNT=2"),
+        )
+        .unwrap();
+
+        assert_eq!(
+            recurrence.upcoming(at("2023-12-31T00:00:00Z"), 5).unwrap(),
+            vec![at("2024-01-01T09:00:00Z"), at("2024-01-02T09:00:00Z")]
+        );
+        assert_eq!(
+            recurrence.next_after(at("2024-01-02T09:00:00Z")).unwrap(),
+            None
+        );
+        assert_eq!(
+            recurrence
+                .next_or_never(at("2024-01-02T09:00:00Z"))
+                .unwrap(),
+            never()
+        );
+    }
+
+    #[test]
+    fn invalid_specs_are_rejected() {
+        for invalid in [
+            json!({"type": "interval", "every_minutes": 0, "anchor": "2024-01-01T00:00:00Z"}),
+            json!({"type": "interval", "every_minutes": 10}),
+            json!({"type": "interval", "every_minutes": 10, "anchor": "2024-01-01T00:00:00Z", "active_hours": {"start": "08:00", "end": "08:00"}}),
+            json!({"type": "window", "start": "18:00", "end": "10:00", "seed": 1}),
+            json!({"type": "window", "start": "10:00", "end": "18:00", "seed": 1, "timezone": "Mars/Olympus"}),
+            json!({"type": "interval", "every_minutes": 10, "anchor": "2024-01-01T00:00:00Z", "unknown": 1}),
+        ] {
+            assert!(Recurrence::from_spec(&invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn update_keeps_the_stored_anchor() {
+        let stored =
+            json!({"type": "interval", "every_minutes": 30, "anchor": "2024-01-01T00:10:00+00:00"});
+
+        let updated = normalize_update(
+            json!({"type": "interval", "every_minutes": 60}),
+            Some(&stored),
+        );
+        assert_eq!(updated["anchor"], stored["anchor"]);
+
+        let anchor = json!("2024-02-01T00:00:00+00:00");
+        let updated = normalize_update(
+            json!({"type": "interval", "every_minutes": 60, "anchor": anchor}),
+            Some(&stored),
+        );
+        assert_eq!(updated["anchor"], anchor);
+
+        let updated = normalize_update(
+            json!({"type": "window", "start": "10:00", "end": "18:00"}),
+            Some(&stored),
+        );
+        assert!(updated.get("anchor").is_none());
+        assert!(updated["seed"].is_u64());
+    }
+}
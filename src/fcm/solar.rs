@@ -0,0 +1,206 @@
+//! Sunrise equation as used by NOAA, accurate to about a minute between the polar circles
+//! (https://en.wikipedia.org/wiki/Sunrise_equation)
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::Deserialize;
+
+/// Julian day of 2000-01-01 12:00 UTC
+const J2000: f64 = 2451545.0;
+/// Julian day of the unix epoch
+const UNIX_EPOCH_JD: f64 = 2440587.5;
+/// obliquity of the ecliptic in degrees
+const OBLIQUITY: f64 = 23.4397;
+
+/// Position of the sun a schedule fires on
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SolarEvent {
+    Sunrise,
+    Sunset,
+    CivilDawn,
+    CivilDusk,
+    NauticalDawn,
+    NauticalDusk,
+}
+
+impl SolarEvent {
+    /// altitude of the sun's centre in degrees, sunrise and sunset account for refraction and
+    /// the sun's radius
+    fn altitude(&self) -> f64 {
+        match self {
+            SolarEvent::Sunrise | SolarEvent::Sunset => -0.833,
+            SolarEvent::CivilDawn | SolarEvent::CivilDusk => -6.0,
+            SolarEvent::NauticalDawn | SolarEvent::NauticalDusk => -12.0,
+        }
+    }
+
+    fn is_morning(&self) -> bool {
+        matches!(
+            self,
+            SolarEvent::Sunrise | SolarEvent::CivilDawn | SolarEvent::NauticalDawn
+        )
+    }
+}
+
+/// Time of the event on the solar day of `date` at the given position (degrees, east and north
+/// positive), `None` when the sun doesn't reach the event's altitude that day (polar day or night)
+pub fn event_time(
+    date: NaiveDate,
+    latitude: f64,
+    longitude: f64,
+    event: SolarEvent,
+) -> Option<DateTime<Utc>> {
+    // julian day number of the date, counted from J2000
+    let midnight = UNIX_EPOCH_JD
+        + date
+            .signed_duration_since(DateTime::UNIX_EPOCH.date_naive())
+            .num_days() as f64;
+    let day = (midnight - J2000 + 0.0008).ceil();
+
+    // mean solar noon
+    let mean_noon = day - longitude / 360.0;
+    let anomaly = (357.5291 + 0.98560028 * mean_noon).rem_euclid(360.0);
+    let m = anomaly.to_radians();
+    let center = 1.9148 * m.sin() + 0.0200 * (2.0 * m).sin() + 0.0003 * (3.0 * m).sin();
+    let ecliptic_longitude = (anomaly + center + 180.0 + 102.9372)
+        .rem_euclid(360.0)
+        .to_radians();
+    let transit = J2000 + mean_noon + 0.0053 * m.sin() - 0.0069 * (2.0 * ecliptic_longitude).sin();
+
+    let declination = (ecliptic_longitude.sin() * OBLIQUITY.to_radians().sin()).asin();
+    let latitude = latitude.to_radians();
+    let cos_hour_angle = (event.altitude().to_radians().sin() - latitude.sin() * declination.sin())
+        / (latitude.cos() * declination.cos());
+
+    // the sun stays above (< -1) or below (> 1) the altitude all day
+    if !(-1.0..=1.0).contains(&cos_hour_angle) {
+        return None;
+    }
+
+    let hour_angle = cos_hour_angle.acos().to_degrees() / 360.0;
+    let julian_day = match event.is_morning() {
+        true => transit - hour_angle,
+        false => transit + hour_angle,
+    };
+
+    let millis = ((julian_day - UNIX_EPOCH_JD) * 86_400_000.0).round() as i64;
+    DateTime::from_timestamp_millis(millis)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    const LONDON: (f64, f64) = (51.5074, -0.1278);
+    const NEW_YORK: (f64, f64) = (40.7128, -74.0060);
+    const SYDNEY: (f64, f64) = (-33.8688, 151.2093);
+    const TROMSO: (f64, f64) = (69.6492, 18.9553);
+
+    fn date(date: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap()
+    }
+
+    /// almanac times are rounded to the minute, the equation is accurate to about a minute
+    fn assert_near(
+        (latitude, longitude): (f64, f64),
+        day: &str,
+        event: SolarEvent,
+        expected: &str,
+    ) {
+        let expected = DateTime::parse_from_rfc3339(expected)
+            .unwrap()
+            .with_timezone(&Utc);
+        let actual = event_time(date(day), latitude, longitude, event).unwrap();
+        assert!(
+            (actual - expected).abs() <= Duration::minutes(2),
+            "{:?} on {} at {}, {}: {} instead of {}",
+            event,
+            day,
+            latitude,
+            longitude,
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn sunrise_and_sunset_match_the_almanac() {
+        assert_near(
+            LONDON,
+            "2024-06-21",
+            SolarEvent::Sunrise,
+            "2024-06-21T04:43:00+01:00",
+        );
+        assert_near(
+            LONDON,
+            "2024-06-21",
+            SolarEvent::Sunset,
+            "2024-06-21T21:21:00+01:00",
+        );
+        assert_near(
+            NEW_YORK,
+            "2024-12-21",
+            SolarEvent::Sunrise,
+            "2024-12-21T07:16:00-05:00",
+        );
+        assert_near(
+            NEW_YORK,
+            "2024-12-21",
+            SolarEvent::Sunset,
+            "2024-12-21T16:32:00-05:00",
+        );
+        // the local morning of the solar day is still the previous day in UTC
+        assert_near(
+            SYDNEY,
+            "2024-12-21",
+            SolarEvent::Sunrise,
+            "2024-12-21T05:41:00+11:00",
+        );
+        assert_near(
+            SYDNEY,
+            "2024-12-21",
+            SolarEvent::Sunset,
+            "2024-12-21T20:05:00+11:00",
+        );
+    }
+
+    #[test]
+    fn twilight_brackets_sunrise_and_sunset() {
+        let (latitude, longitude) = LONDON;
+        let day = date("2024-03-20");
+        let time = |event| event_time(day, latitude, longitude, event).unwrap();
+
+        assert!(time(SolarEvent::NauticalDawn) < time(SolarEvent::CivilDawn));
+        assert!(time(SolarEvent::CivilDawn) < time(SolarEvent::Sunrise));
+        assert!(time(SolarEvent::Sunset) < time(SolarEvent::CivilDusk));
+        assert!(time(SolarEvent::CivilDusk) < time(SolarEvent::NauticalDusk));
+    }
+
+    #[test]
+    fn polar_day_and_night_have_no_sunrise() {
+        let (latitude, longitude) = TROMSO;
+
+        // midnight sun
+        for event in [SolarEvent::Sunrise, SolarEvent::Sunset] {
+            assert_eq!(
+                event_time(date("2024-06-21"), latitude, longitude, event),
+                None
+            );
+        }
+        // polar night, the sun still gets close enough to the horizon for civil twilight
+        for event in [SolarEvent::Sunrise, SolarEvent::Sunset] {
+            assert_eq!(
+                event_time(date("2024-12-21"), latitude, longitude, event),
+                None
+            );
+        }
+        assert!(event_time(
+            date("2024-12-21"),
+            latitude,
+            longitude,
+            SolarEvent::CivilDawn
+        )
+        .is_some());
+    }
+}
@@ -1,5 +1,5 @@
-use chrono::{NaiveDateTime, Utc};
-use cron_parser::parse;
+use super::schedule::Recurrence;
+use chrono::{DateTime, Utc};
 use jsonwebtoken::{decode, Algorithm, DecodingKey, Validation};
 use serde::{Deserialize, Serialize};
 
@@ -35,20 +35,9 @@ pub fn extract_claims(token: Option<&str>) -> Result<Claims, String> {
     }
 }
 
-pub fn decode_cron(cron_pattern: &str) -> Result<NaiveDateTime, String> {
-    let next = std::panic::catch_unwind(|| parse(cron_pattern, &Utc::now()));
-
-    let next = match next {
-        Ok(next) => next,
-        Err(_) => {
-            return Err("Invalid cron pattern".to_string());
-        }
-    };
-    let next = match next {
-        Ok(next) => next,
-        Err(_) => {
-            return Err("Invalid cron pattern".to_string());
-        }
-    };
-    Ok(next.naive_utc())
+pub fn next_execution(recurrence: &Recurrence) -> Result<DateTime<Utc>, String> {
+    match recurrence.next_after(Utc::now())? {
+        Some(next) => Ok(next),
+        None => Err("Schedule has no upcoming occurrences".to_string()),
+    }
 }
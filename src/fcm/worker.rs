@@ -1,13 +1,21 @@
+use super::audit;
+use super::data_source::{resolve_payload, SourceCache};
+use super::escalation;
 use super::model::FCMSchedule;
-use chrono::Utc;
-use cron_parser::parse;
+use super::quota;
+use super::schedule::{never, Recurrence};
+use crate::{metrics, supervisor::WorkerHandle};
+use chrono::{DateTime, Utc};
 use gcp_auth::{AuthenticationManager, CustomServiceAccount, Error};
-use reqwest::header::{HeaderMap, AUTHORIZATION};
+use reqwest::{
+    header::{HeaderMap, AUTHORIZATION},
+    StatusCode,
+};
 use serde::{Deserialize, Serialize};
-use serde_json::{from_str, Value};
-use sqlx::postgres::PgPool;
-use std::{collections::HashMap, fs, time::Duration};
-use tokio::time::sleep;
+use serde_json::{from_str, json, Value};
+use sqlx::postgres::{PgListener, PgPool};
+use std::{collections::HashMap, fmt, fs, sync::Arc, time::Duration};
+use tokio::{sync::Notify, time::sleep};
 use tracing::{debug, error, info, warn};
 
 // https://firebase.google.com/docs/cloud-messaging/concept-options#notification-messages-with-optional-data-payload
@@ -34,9 +42,25 @@ struct FCMBody {
     notification: Notification,
     data: HashMap<String, String>,
     token: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    android: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    apns: Option<Value>,
 }
 
 const SCOPES: &[&str; 1] = &["https://www.googleapis.com/auth/firebase.messaging"];
+/// upper bound of a scheduler sleep, picks up changes missed while the change listener is down
+const MAX_SLEEP: Duration = Duration::from_secs(60);
+/// first sleep before retrying schedules that are still overdue, doubled on every retry
+const OVERDUE_RETRY: Duration = Duration::from_secs(1);
+/// upper bound of the delay before a schedule that failed to send is retried
+const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(3600);
+/// postgres channel notified by the fcm_schedule_changed trigger
+const SCHEDULE_CHANGED_CHANNEL: &str = "fcm_schedule_changed";
+/// wait before reconnecting the schedule change listener
+const LISTENER_RETRY: Duration = Duration::from_secs(5);
+/// messages sent later than this are logged as late
+const LATENESS_WARNING_MS: i64 = 5000;
 
 pub async fn read_in_serivce_accounts() -> Result<HashMap<String, AuthenticationManager>, Error> {
     info!("Reading in service accounts");
@@ -69,24 +93,39 @@ pub async fn read_in_serivce_accounts() -> Result<HashMap<String, Authentication
     Ok(service_accounts)
 }
 
-pub async fn run_every_minute(
-    auth_managers: HashMap<String, AuthenticationManager>,
+/// Sends due messages, then sleeps until the next one is due or `wake` is notified
+pub async fn run_scheduler(
+    auth_managers: Arc<HashMap<String, AuthenticationManager>>,
     pool: &PgPool,
+    wake: Arc<Notify>,
+    worker: WorkerHandle,
 ) {
-    loop {
-        let current_time = Utc::now().naive_local();
+    // consecutive failed sends per schedule, and consecutive sleeps with schedules still overdue
+    let mut failures: HashMap<i32, u32> = HashMap::new();
+    let mut overdue: u32 = 0;
 
-        let messages = sqlx::query_as!(
-            FCMSchedule,
-            "SELECT * FROM fcm_schedule WHERE next_execution < NOW()"
-        )
-        .fetch_all(pool)
-        .await
-        .unwrap_or_else(|_| vec![]);
+    while !worker.is_cancelled() {
+        let tick = metrics::FCM_WORKER_TICK_DURATION.start_timer();
+        let current_time = Utc::now();
 
-        info!(message_count = messages.len(), "Found messages to process");
+        let messages = due_schedules(pool, current_time).await.unwrap_or_else(|e| {
+            error!(error=?e, "Error fetching due messages");
+            worker.error(&e.to_string());
+            vec![]
+        });
+
+        debug!(message_count = messages.len(), "Found messages to process");
+        metrics::FCM_WORKER_BACKLOG.set(messages.len() as i64);
+
+        // responses of data sources, shared by the messages of this tick
+        let mut sources = SourceCache::default();
+        sources.prefetch(&messages).await;
 
         for message in messages {
+            // finish the message being sent, but don't start new ones once shutting down
+            if worker.is_cancelled() {
+                break;
+            }
             debug!(message = ?message, "Processing message");
 
             let project_id = message.fb_project_id.to_owned();
@@ -94,119 +133,730 @@ pub async fn run_every_minute(
             let auth_manager = match auth_managers.get(&project_id) {
                 Some(auth_manager) => auth_manager,
                 None => {
-                    warn!(project_id = ?project_id, message_id=?message.id, "No auth manager found for project id");
+                    warn!(project_id = ?project_id, message_id=?message.id, "No auth manager found for project id, pausing schedule");
+                    pause(pool, &message).await;
                     continue;
                 }
             };
 
-            let token = match auth_manager.get_token(SCOPES).await {
-                Ok(token) => token,
+            let recurrence = match Recurrence::for_schedule(
+                message.recurrence.as_ref(),
+                message.cron_pattern.as_deref(),
+                message.rrule.as_deref(),
+            ) {
+                Ok(recurrence) => recurrence,
                 Err(e) => {
-                    error!(project_id = ?project_id, message_id=?message.id, error=?e, "Error getting token");
+                    error!(project_id = ?project_id, message_id=?message.id, error=?e, "Error parsing schedule, pausing schedule");
+                    pause(pool, &message).await;
                     continue;
                 }
             };
 
-            let mut payload: HashMap<String, String> = HashMap::new();
-            match message.payload {
-                Value::Object(map) => {
-                    for (key, value) in map {
-                        payload.insert(key, value.to_string());
+            let next = match recurrence.next_or_never(Utc::now()) {
+                Ok(next) => {
+                    if next == never() {
+                        info!(project_id = ?project_id, message_id=?message.id, "Schedule has no upcoming occurrences");
                     }
+                    next
                 }
-                Value::String(s) => {
-                    payload = from_str::<HashMap<String, String>>(&s).unwrap_or({
-                        warn!(project_id = ?project_id, message_id=?message.id, payload=&s, "Error parsing payload, defaulting to empty hashmap");
-                        HashMap::new()
-                    });
-                }
-                _ => {}
-            }
-
-            let notification = Notification {
-                title: payload.remove("title"),
-                body: payload.remove("body"),
-            };
-
-            let firebase_message = Fcm {
-                message: FCMBody {
-                    notification,
-                    data: payload,
-                    token: message.push_token.to_owned(),
-                },
-            };
-
-            let header = match format!("Bearer {}", token.as_str()).parse() {
-                Ok(header) => header,
                 Err(e) => {
-                    error!(project_id = ?project_id, message_id=?message.id, error=?e, "Error parsing header");
+                    error!(project_id = ?project_id, message_id=?message.id, error=?e, "Error computing next execution, pausing schedule");
+                    pause(pool, &message).await;
                     continue;
                 }
             };
 
-            // Create the authorization header with the token
-            let mut headers = HeaderMap::new();
-            headers.insert(AUTHORIZATION, header);
-
-            let endpoint = format!(
-                "https://fcm.googleapis.com/v1/projects/{}/messages:send",
-                project_id
-            );
-
-            // Send the HTTP POST request
-            let client = reqwest::Client::new();
-            let response = client
-                .post(endpoint)
-                .headers(headers)
-                .json(&firebase_message)
-                .send()
-                .await;
-
-            match response {
-                Ok(response) => {
-                    if response.status().is_success() {
-                        debug!(project_id = ?project_id, message_id=?message.id, "Successfully sent request")
-                    } else {
-                        let resp = response.text().await;
-                        warn!(project_id = ?project_id, message_id=?message.id, response=?resp, "Error sending request");
-                    }
+            let claimed = match claim(pool, &message, next, current_time).await {
+                Ok(Some(claimed)) => claimed,
+                Ok(None) => {
+                    debug!(message_id=?message.id, "Message was claimed by another worker");
+                    continue;
                 }
                 Err(e) => {
-                    error!(project_id = ?project_id, message_id=?message.id, error=?e, "Error sending request");
+                    error!(message_id=?message.id, error=?e, "Error claiming message");
                     continue;
                 }
+            };
+            debug!(message_id=?message.id, next_execution=?next, "Successfully updated next execution time");
+
+            let lateness = (Utc::now() - message.next_execution).num_milliseconds();
+            metrics::FCM_SCHEDULE_LATENESS.observe(lateness.max(0) as f64 / 1000.0);
+            if lateness > LATENESS_WARNING_MS {
+                warn!(message_id=?message.id, lateness_ms=lateness, "Message is sent late");
             }
 
-            // Update the next execution time
-            let next = match parse(&message.cron_pattern.to_owned(), &Utc::now()) {
-                Ok(next) => next.naive_utc(),
+            // sends are recorded as deliveries, only changes made by the worker are audited
+            if let Err(e) = deliver(pool, auth_manager, &message, None, &mut sources).await {
+                error!(project_id = ?project_id, message_id=?message.id, error=?e, "Error delivering message");
+                retry_later(pool, &claimed, &mut failures).await;
+                continue;
+            }
+            failures.remove(&message.id);
+        }
+
+        resend_snoozed(pool, &auth_managers, &mut sources, &worker).await;
+        escalation::run_due(pool, &auth_managers, &mut sources, &worker).await;
+
+        tick.observe_duration();
+        worker.tick();
+
+        let sleep_for = time_until_next_execution(pool, &mut overdue).await;
+        debug!(sleep_for = ?sleep_for, "Sleeping until the next execution");
+
+        tokio::select! {
+            _ = sleep(sleep_for) => {}
+            _ = wake.notified() => debug!("Woken up by a schedule change"),
+            _ = worker.cancelled() => {}
+        }
+    }
+}
+
+async fn due_schedules(pool: &PgPool, now: DateTime<Utc>) -> Result<Vec<FCMSchedule>, sqlx::Error> {
+    sqlx::query_as!(
+        FCMSchedule,
+        "SELECT * FROM fcm_schedule WHERE next_execution <= $1 AND NOT paused ORDER BY next_execution",
+        now
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Claim the occurrence by moving the schedule on to `next`, so other replicas woken by the same
+/// change skip it, nothing is returned when one of them got there first
+async fn claim(
+    pool: &PgPool,
+    message: &FCMSchedule,
+    next: DateTime<Utc>,
+    now: DateTime<Utc>,
+) -> Result<Option<FCMSchedule>, sqlx::Error> {
+    sqlx::query_as!(
+        FCMSchedule,
+        "UPDATE fcm_schedule SET next_execution = $1, last_execution = $2, updated_at = $3
+        WHERE id = $4 AND next_execution = $5 AND NOT paused
+        RETURNING *",
+        next,
+        now,
+        now,
+        message.id,
+        message.next_execution
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+/// Wakes the scheduler whenever a schedule is changed by any replica, the scheduler keeps polling
+/// every MAX_SLEEP while the listener connection is down
+pub async fn listen_for_changes(pool: &PgPool, wake: Arc<Notify>, worker: WorkerHandle) {
+    while !worker.is_cancelled() {
+        let mut listener = match PgListener::connect_with(pool).await {
+            Ok(listener) => listener,
+            Err(e) => {
+                error!(error=?e, "Error connecting the schedule change listener");
+                worker.error(&e.to_string());
+                sleep(LISTENER_RETRY).await;
+                continue;
+            }
+        };
+
+        if let Err(e) = listener.listen(SCHEDULE_CHANGED_CHANNEL).await {
+            error!(error=?e, "Error listening for schedule changes");
+            worker.error(&e.to_string());
+            sleep(LISTENER_RETRY).await;
+            continue;
+        }
+
+        info!("Listening for schedule changes");
+        // the connection may have dropped while changes were made
+        wake.notify_one();
+
+        loop {
+            let notification = tokio::select! {
+                notification = listener.try_recv() => notification,
+                _ = worker.cancelled() => return,
+            };
+
+            match notification {
+                Ok(Some(notification)) => {
+                    debug!(operation = notification.payload(), "Schedule changed");
+                    worker.tick();
+                    wake.notify_one();
+                }
+                Ok(None) => {
+                    warn!("Schedule change listener connection lost, reconnecting");
+                    wake.notify_one();
+                }
                 Err(e) => {
-                    println!("Error parsing cron pattern: {}", e);
-                    error!(project_id = ?project_id, message_id=?message.id, error=?e, "Error parsing cron pattern");
-                    continue;
+                    error!(error=?e, "Error receiving schedule changes");
+                    worker.error(&e.to_string());
+                    break;
                 }
-            };
+            }
+        }
+
+        sleep(LISTENER_RETRY).await;
+    }
+}
+
+/// Send an occurrence of the schedule unless the quotas are exceeded, the delivery is recorded
+/// under a new id which is sent as occurrence_id in the data payload (snoozed resends keep the
+/// occurrence_id of the original delivery). Errors are worth retrying: database errors and
+/// transient send failures, which are recorded as failed deliveries too
+async fn deliver(
+    pool: &PgPool,
+    auth_manager: &AuthenticationManager,
+    message: &FCMSchedule,
+    resend_of: Option<i64>,
+    sources: &mut SourceCache,
+) -> Result<(), String> {
+    let usage = quota::usage(pool, &message.fb_user_id, &message.fb_project_id)
+        .await
+        .map_err(|e| e.to_string())?;
+    let delivery_id = next_delivery_id(pool).await.map_err(|e| e.to_string())?;
+
+    if let Some(reason) = usage.sends_exceeded() {
+        warn!(project_id = ?message.fb_project_id, message_id=?message.id, reason=%reason, "Quota exceeded, skipping message");
+        record_delivery(
+            pool,
+            delivery_id,
+            resend_of,
+            0,
+            message,
+            "throttled",
+            Some(reason),
+        )
+        .await;
+        return Ok(());
+    }
+
+    let payload = resolve_payload(message, sources).await;
+    let occurrence_id = resend_of.unwrap_or(delivery_id);
+
+    let result = send_message(
+        auth_manager,
+        &FCMSchedule {
+            payload: with_occurrence_id(payload, occurrence_id),
+            ..message.clone()
+        },
+    )
+    .await;
+
+    match result {
+        Ok(_) => {
+            debug!(project_id = ?message.fb_project_id, message_id=?message.id, occurrence_id, "Successfully sent request");
+            record_delivery(pool, delivery_id, resend_of, 0, message, "sent", None).await;
+            if resend_of.is_none() {
+                escalation::start(pool, delivery_id, message).await;
+            }
+        }
+        Err(e) => {
+            warn!(project_id = ?message.fb_project_id, message_id=?message.id, error=%e, "Error sending request");
+            record_delivery(
+                pool,
+                delivery_id,
+                resend_of,
+                0,
+                message,
+                "failed",
+                Some(e.to_string()),
+            )
+            .await;
+            if let PushError::Transient(e) = e {
+                return Err(e);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Resend occurrences whose snooze is over, without touching the schedule itself
+async fn resend_snoozed(
+    pool: &PgPool,
+    auth_managers: &HashMap<String, AuthenticationManager>,
+    sources: &mut SourceCache,
+    worker: &WorkerHandle,
+) {
+    // claim the snoozes so other replicas don't resend them too
+    let snoozed = sqlx::query!(
+        "UPDATE fcm_delivery SET snoozed_until = NULL WHERE snoozed_until <= $1 RETURNING id, schedule_id",
+        Utc::now()
+    )
+    .fetch_all(pool)
+    .await;
+
+    let snoozed = match snoozed {
+        Ok(snoozed) => snoozed,
+        Err(e) => {
+            error!(error=?e, "Error fetching snoozed occurrences");
+            worker.error(&e.to_string());
+            return;
+        }
+    };
+
+    for occurrence in snoozed {
+        let schedule = sqlx::query_as!(
+            FCMSchedule,
+            "SELECT * FROM fcm_schedule WHERE id = $1 AND NOT paused",
+            occurrence.schedule_id
+        )
+        .fetch_optional(pool)
+        .await;
+
+        let message = match schedule {
+            Ok(Some(message)) => message,
+            Ok(None) => {
+                debug!(
+                    occurrence_id = occurrence.id,
+                    "Schedule of the snoozed occurrence is gone or paused"
+                );
+                continue;
+            }
+            Err(e) => {
+                error!(occurrence_id = occurrence.id, error=?e, "Error fetching the schedule of a snoozed occurrence");
+                continue;
+            }
+        };
+
+        let auth_manager = match auth_managers.get(&message.fb_project_id) {
+            Some(auth_manager) => auth_manager,
+            None => {
+                warn!(project_id = ?message.fb_project_id, message_id=?message.id, "No auth manager found for project id");
+                continue;
+            }
+        };
+
+        if let Err(e) = deliver(pool, auth_manager, &message, Some(occurrence.id), sources).await {
+            error!(occurrence_id = occurrence.id, error=?e, "Error resending snoozed occurrence");
+        }
+    }
+}
+
+/// Pause a schedule the worker can't process until its owner changes or resumes it
+async fn pause(pool: &PgPool, message: &FCMSchedule) {
+    let result = sqlx::query_as!(
+        FCMSchedule,
+        "UPDATE fcm_schedule SET paused = TRUE, updated_at = $1 WHERE id = $2 RETURNING *",
+        Utc::now(),
+        message.id
+    )
+    .fetch_one(pool)
+    .await;
+
+    match result {
+        Ok(schedule) => {
+            audit::record(
+                pool,
+                audit::WORKER,
+                "paused",
+                None,
+                Some(message),
+                Some(&schedule),
+            )
+            .await;
+        }
+        Err(e) => {
+            error!(message_id=?message.id, error=?e, "Error pausing schedule");
+        }
+    }
+}
+
+/// Retry a claimed schedule whose delivery failed with a database error or a transient send
+/// failure, backing off exponentially but not past its next occurrence
+async fn retry_later(pool: &PgPool, message: &FCMSchedule, failures: &mut HashMap<i32, u32>) {
+    let attempt = failures.entry(message.id).or_insert(0);
+    *attempt += 1;
+    let delay = backoff(*attempt).min(MAX_RETRY_BACKOFF);
+
+    // the schedule may have been changed meanwhile, then its new next_execution wins
+    let result = sqlx::query!(
+        "UPDATE fcm_schedule SET next_execution = LEAST($1, next_execution) WHERE id = $2 AND next_execution = $3",
+        Utc::now() + chrono::Duration::from_std(delay).unwrap_or_default(),
+        message.id,
+        message.next_execution
+    )
+    .execute(pool)
+    .await;
+
+    if let Err(e) = result {
+        error!(message_id=?message.id, error=?e, "Error postponing failed schedule");
+    }
+}
+
+/// OVERDUE_RETRY doubled for every attempt after the first
+fn backoff(attempt: u32) -> Duration {
+    OVERDUE_RETRY * 2u32.pow(attempt.clamp(1, 16) - 1)
+}
 
-            // Update database
-            let result = sqlx::query!(
-                r#"UPDATE fcm_schedule SET next_execution = $1, last_execution = $2, updated_at = $3 WHERE id = $4"#,
-                next,
-                current_time,
-                current_time,
-                message.id,
-            ).execute(pool).await;
-
-            match result {
-                Ok(data) => println!(
-                    "Successfully updated next execution time: {:?}, rows affected: {}",
-                    next,
-                    data.rows_affected()
-                ),
-                Err(e) => println!("Error updating next execution time: {}", e),
+async fn time_until_next_execution(pool: &PgPool, overdue: &mut u32) -> Duration {
+    let next = sqlx::query_scalar!(
+        "SELECT LEAST(
+            (SELECT MIN(next_execution) FROM fcm_schedule WHERE NOT paused),
+            (SELECT MIN(snoozed_until) FROM fcm_delivery),
+            (SELECT MIN(next_escalation_at) FROM fcm_delivery WHERE acked_at IS NULL AND snoozed_until IS NULL)
+        )"
+    )
+    .fetch_one(pool)
+    .await;
+
+    match next {
+        Ok(Some(next)) => match (next - Utc::now()).to_std() {
+            Ok(until) => {
+                *overdue = 0;
+                until.min(MAX_SLEEP)
+            }
+            // schedules still overdue after a tick could not be processed, retry them less often
+            Err(_) => {
+                *overdue += 1;
+                backoff(*overdue).min(MAX_SLEEP)
             }
+        },
+        Ok(None) => MAX_SLEEP,
+        Err(e) => {
+            error!(error=?e, "Error getting the next execution time");
+            MAX_SLEEP
+        }
+    }
+}
+
+pub async fn send_message(
+    auth_manager: &AuthenticationManager,
+    message: &FCMSchedule,
+) -> Result<(), PushError> {
+    send_push(
+        auth_manager,
+        &message.fb_project_id,
+        &message.push_token,
+        data_payload(message),
+        false,
+    )
+    .await
+}
+
+/// FCM data of the schedule, every value of the payload is sent as a string
+pub fn data_payload(message: &FCMSchedule) -> HashMap<String, String> {
+    let mut payload: HashMap<String, String> = HashMap::new();
+    match &message.payload {
+        Value::Object(map) => {
+            for (key, value) in map {
+                payload.insert(key.to_owned(), value.to_string());
+            }
+        }
+        Value::String(s) => {
+            payload = from_str::<HashMap<String, String>>(s).unwrap_or({
+                warn!(message_id=?message.id, payload=s, "Error parsing payload, defaulting to empty hashmap");
+                HashMap::new()
+            });
+        }
+        _ => {}
+    }
+
+    payload
+}
+
+/// Reasons a push was not sent
+#[derive(Debug)]
+pub enum PushError {
+    /// FCM or the network is unavailable for now, the push can be retried
+    Transient(String),
+    /// FCM rejected the push or it could not be built, retrying doesn't help
+    Rejected(String),
+}
+
+impl fmt::Display for PushError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PushError::Transient(e) | PushError::Rejected(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+/// Send a push to a device, `title` and `body` of the payload are used as the notification
+pub async fn send_push(
+    auth_manager: &AuthenticationManager,
+    fb_project_id: &str,
+    push_token: &str,
+    mut payload: HashMap<String, String>,
+    high_priority: bool,
+) -> Result<(), PushError> {
+    let token = match auth_manager.get_token(SCOPES).await {
+        Ok(token) => token,
+        Err(e) => {
+            return Err(PushError::Transient(format!("Error getting token: {}", e)));
+        }
+    };
+
+    let notification = Notification {
+        title: payload.remove("title"),
+        body: payload.remove("body"),
+    };
+
+    let firebase_message = Fcm {
+        message: FCMBody {
+            notification,
+            data: payload,
+            token: push_token.to_owned(),
+            android: high_priority.then(|| json!({ "priority": "HIGH" })),
+            apns: high_priority.then(|| json!({ "headers": { "apns-priority": "10" } })),
+        },
+    };
+
+    let header = match format!("Bearer {}", token.as_str()).parse() {
+        Ok(header) => header,
+        Err(e) => {
+            return Err(PushError::Rejected(format!("Error parsing header: {}", e)));
+        }
+    };
+
+    // Create the authorization header with the token
+    let mut headers = HeaderMap::new();
+    headers.insert(AUTHORIZATION, header);
+
+    let endpoint = format!(
+        "https://fcm.googleapis.com/v1/projects/{}/messages:send",
+        fb_project_id
+    );
+
+    // Send the HTTP POST request
+    let client = reqwest::Client::new();
+    let response = client
+        .post(endpoint)
+        .headers(headers)
+        .json(&firebase_message)
+        .send()
+        .await;
+
+    match response {
+        Ok(response) => {
+            if response.status().is_success() {
+                Ok(())
+            } else {
+                let status = response.status();
+                let resp = response.text().await.unwrap_or_default();
+                let error = format!("FCM returned {}: {}", status, resp);
+                // FCM asks to retry on 429 and 5xx (QUOTA_EXCEEDED, UNAVAILABLE, INTERNAL)
+                match status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error() {
+                    true => Err(PushError::Transient(error)),
+                    false => Err(PushError::Rejected(error)),
+                }
+            }
+        }
+        Err(e) => Err(PushError::Transient(format!(
+            "Error sending request: {}",
+            e
+        ))),
+    }
+}
+
+/// Reserve the id of a delivery before sending, so it can be included in the payload
+pub async fn next_delivery_id(pool: &PgPool) -> Result<i64, sqlx::Error> {
+    sqlx::query_scalar!(r#"SELECT nextval(pg_get_serial_sequence('fcm_delivery', 'id')) as "id!""#)
+        .fetch_one(pool)
+        .await
+}
+
+/// Payload with the occurrence id the app uses to ack or snooze the notification
+pub fn with_occurrence_id(payload: Value, occurrence_id: i64) -> Value {
+    match payload {
+        Value::Object(mut map) => {
+            map.insert("occurrence_id".to_string(), Value::from(occurrence_id));
+            Value::Object(map)
+        }
+        payload => payload,
+    }
+}
+
+/// Record a delivery, `resend_of` is the original occurrence of snoozed and escalated resends
+pub async fn record_delivery(
+    pool: &PgPool,
+    id: i64,
+    resend_of: Option<i64>,
+    escalation_step: i32,
+    message: &FCMSchedule,
+    status: &str,
+    error: Option<String>,
+) {
+    let result = sqlx::query!(
+        "INSERT INTO fcm_delivery (id, resend_of, escalation_step, schedule_id, fb_user_id, fb_project_id, status, error, created_at) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)",
+        id,
+        resend_of,
+        escalation_step,
+        message.id,
+        message.fb_user_id,
+        message.fb_project_id,
+        status,
+        error,
+        Utc::now()
+    )
+    .execute(pool)
+    .await;
+
+    if let Err(e) = result {
+        error!(message_id=?message.id, error=?e, "Error recording delivery");
+    }
+
+    metrics::FCM_SENDS
+        .with_label_values(&[&message.fb_project_id, status])
+        .inc();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::SubsecRound;
+    use rand::Rng;
+    use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+    use std::{env, str::FromStr};
+
+    /// Scratch database with the migrations applied, dropped at the end of the test
+    struct TestDatabase {
+        url: String,
+        name: String,
+    }
+
+    impl TestDatabase {
+        async fn create() -> Self {
+            let url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+
+            let name = format!("toolkit_test_{}", rand::thread_rng().gen::<u32>());
+            let admin = PgPool::connect(&url).await.unwrap();
+            sqlx::query(&format!("CREATE DATABASE {}", name))
+                .execute(&admin)
+                .await
+                .unwrap();
+            admin.close().await;
+
+            let database = TestDatabase { url, name };
+            let pool = database.pool("UTC").await;
+            sqlx::migrate!().run(&pool).await.unwrap();
+            pool.close().await;
+
+            database
+        }
+
+        /// Pool whose sessions use the Postgres `timezone`
+        async fn pool(&self, timezone: &str) -> PgPool {
+            let options = PgConnectOptions::from_str(&self.url)
+                .unwrap()
+                .database(&self.name)
+                .options([("timezone", timezone)]);
+
+            PgPoolOptions::new()
+                .max_connections(1)
+                .connect_with(options)
+                .await
+                .unwrap()
         }
 
-        // Sleep for 1 minute
-        sleep(Duration::from_secs(60)).await;
+        async fn drop(self) {
+            let admin = PgPool::connect(&self.url).await.unwrap();
+            sqlx::query(&format!("DROP DATABASE {} WITH (FORCE)", self.name))
+                .execute(&admin)
+                .await
+                .unwrap();
+            admin.close().await;
+        }
+    }
+
+    fn at(time: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(time)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    async fn insert(pool: &PgPool, next_execution: DateTime<Utc>) -> i32 {
+        sqlx::query_scalar(
+            "INSERT INTO fcm_schedule (
+                name, fb_user_id, push_token, fb_project_id, cron_pattern, payload, last_execution, next_execution, created_at, updated_at
+            )
+            VALUES ('test', 'user', 'token', 'project', '* * * * *', '{}', $1, $2, $1, $1)
+            RETURNING id",
+        )
+        .bind(Utc::now())
+        .bind(next_execution)
+        .fetch_one(pool)
+        .await
+        .unwrap()
+    }
+
+    // the database tests need DATABASE_URL, CI runs them with --ignored and a non-UTC TZ
+    #[tokio::test]
+    #[ignore]
+    async fn next_execution_round_trips_in_any_timezone() {
+        let database = TestDatabase::create().await;
+        let los_angeles = database.pool("America/Los_Angeles").await;
+        let kiritimati = database.pool("Pacific/Kiritimati").await;
+
+        // the night the clocks go forward in Los Angeles
+        let next_execution = at("2024-03-10T10:30:00Z");
+        let id = insert(&los_angeles, next_execution).await;
+
+        for pool in [&los_angeles, &kiritimati] {
+            let stored: DateTime<Utc> =
+                sqlx::query_scalar("SELECT next_execution FROM fcm_schedule WHERE id = $1")
+                    .bind(id)
+                    .fetch_one(pool)
+                    .await
+                    .unwrap();
+            assert_eq!(stored, next_execution);
+
+            let same_instant: bool = sqlx::query_scalar(
+                "SELECT next_execution = '2024-03-10T10:30:00Z'::timestamptz FROM fcm_schedule WHERE id = $1",
+            )
+            .bind(id)
+            .fetch_one(pool)
+            .await
+            .unwrap();
+            assert!(same_instant);
+        }
+
+        los_angeles.close().await;
+        kiritimati.close().await;
+        database.drop().await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn due_selection_matches_across_timezones() {
+        let database = TestDatabase::create().await;
+        let pago_pago = database.pool("Pacific/Pago_Pago").await;
+        let kiritimati = database.pool("Pacific/Kiritimati").await;
+
+        // Postgres keeps microseconds
+        let now = Utc::now().trunc_subsecs(6);
+        let due = insert(&pago_pago, now - chrono::Duration::minutes(1)).await;
+        insert(&kiritimati, now + chrono::Duration::seconds(30)).await;
+
+        for pool in [&pago_pago, &kiritimati] {
+            let ids: Vec<i32> = due_schedules(pool, now)
+                .await
+                .unwrap()
+                .iter()
+                .map(|schedule| schedule.id)
+                .collect();
+            assert_eq!(ids, vec![due]);
+        }
+
+        // a claim made in one timezone is seen at the same instant in the other
+        let message = due_schedules(&pago_pago, now).await.unwrap().remove(0);
+        let next = now + chrono::Duration::minutes(5);
+        let claimed = claim(&pago_pago, &message, next, now)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(claimed.next_execution, next);
+        assert!(claim(&kiritimati, &message, next, now)
+            .await
+            .unwrap()
+            .is_none());
+        assert!(due_schedules(&kiritimati, now).await.unwrap().is_empty());
+
+        // the scheduler sleeps until the one due in 30 seconds
+        let sleep_for = time_until_next_execution(&kiritimati, &mut 0).await;
+        assert!(sleep_for <= Duration::from_secs(30));
+        assert!(sleep_for > Duration::from_secs(20));
+
+        pago_pago.close().await;
+        kiritimati.close().await;
+        database.drop().await;
     }
 }
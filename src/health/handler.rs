@@ -1,24 +1,40 @@
-use crate::{browser::handler::Selenium, utils::ApiTags};
+use crate::{
+    browser::{handler::Selenium, model::PoolStats},
+    supervisor::{model::WorkerStatus, Supervisor},
+    utils::ApiTags,
+};
 use poem::{error::InternalServerError, http::StatusCode, Error, Result};
-use poem_openapi::{payload::PlainText, OpenApi};
+use poem_openapi::{
+    payload::{Json, PlainText},
+    OpenApi,
+};
 use sqlx::postgres::PgPool;
 
 pub struct HealthCheck {
     pool: PgPool,
     browser_api: Selenium,
+    supervisor: Supervisor,
 }
 
 #[OpenApi(prefix_path = "/health/", tag = "ApiTags::HealthCheck")]
 impl HealthCheck {
-    pub fn new(pool: PgPool, browser_api: Selenium) -> Self {
-        Self { pool, browser_api }
+    pub fn new(pool: PgPool, browser_api: Selenium, supervisor: Supervisor) -> Self {
+        Self {
+            pool,
+            browser_api,
+            supervisor,
+        }
     }
 
-    #[oai(path = "/liveness", method = "get")]
+    #[oai(path = "/liveness", method = "get", operation_id = "health::liveness")]
     async fn liveness(&self) -> PlainText<String> {
         PlainText("OK".to_string())
     }
-    #[oai(path = "/readiness", method = "get")]
+    #[oai(
+        path = "/readiness",
+        method = "get",
+        operation_id = "health::readiness"
+    )]
     async fn readiness(&self) -> Result<PlainText<String>> {
         sqlx::query("SELECT 1")
             .execute(&self.pool)
@@ -35,6 +51,30 @@ impl HealthCheck {
             }
         };
 
+        let stuck: Vec<String> = self
+            .supervisor
+            .statuses()
+            .into_iter()
+            .filter(|status| status.stuck)
+            .map(|status| status.name)
+            .collect();
+        if !stuck.is_empty() {
+            return Err(Error::from_string(
+                format!("Workers are stuck: {}", stuck.join(", ")),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ));
+        }
+
         Ok(PlainText("OK".to_string()))
     }
+
+    #[oai(path = "/workers", method = "get", operation_id = "health::workers")]
+    async fn workers(&self) -> Json<Vec<WorkerStatus>> {
+        Json(self.supervisor.statuses())
+    }
+
+    #[oai(path = "/browser", method = "get", operation_id = "health::browser")]
+    async fn browser(&self) -> Json<PoolStats> {
+        Json(self.browser_api.stats())
+    }
 }
@@ -1,8 +1,12 @@
-use crate::browser::handler::Selenium;
+use crate::{browser::handler::Selenium, supervisor::Supervisor};
 use sqlx::postgres::PgPool;
 
 pub mod handler;
 
-pub async fn health_checks(pool: PgPool, browser_api: Selenium) -> handler::HealthCheck {
-    handler::HealthCheck::new(pool, browser_api)
+pub async fn health_checks(
+    pool: PgPool,
+    browser_api: Selenium,
+    supervisor: Supervisor,
+) -> handler::HealthCheck {
+    handler::HealthCheck::new(pool, browser_api, supervisor)
 }
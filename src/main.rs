@@ -2,6 +2,7 @@
 use dotenv::dotenv;
 use fcm::fcm_api;
 use poem::{
+    get,
     listener::TcpListener,
     middleware::{Cors, Tracing},
     EndpointExt, Route, Server,
@@ -14,6 +15,9 @@ use utils::get_db_pool;
 mod browser;
 mod fcm;
 mod health;
+mod metrics;
+mod monitor;
+mod supervisor;
 mod utils;
 mod yt_dlp;
 
@@ -29,15 +33,36 @@ async fn main() -> Result<(), std::io::Error> {
     let hostname = utils::get_host();
     let port = utils::get_port();
 
-    let fcm_api = fcm_api(pool.clone()).await;
+    let supervisor = supervisor::Supervisor::default();
 
-    let (browser_api, driver) = browser::selenium().await;
+    let service_accounts = fcm::service_accounts().await;
+
+    let (fcm_api, fcm_calendar_api, fcm_admin_api) =
+        fcm_api(pool.clone(), service_accounts.clone(), &supervisor).await;
+
+    let (browser_api, browser_pool) = browser::selenium(&supervisor).await;
+    let monitor_api = monitor::monitor_api(
+        pool.clone(),
+        browser_api.clone(),
+        service_accounts,
+        &supervisor,
+    )
+    .await;
     let yt_dlp_api = yt_dlp::yt_dlp().await;
 
-    let health_api = health::health_checks(pool.clone(), browser_api.clone()).await;
+    let health_api =
+        health::health_checks(pool.clone(), browser_api.clone(), supervisor.clone()).await;
 
     let api_service = OpenApiService::new(
-        (fcm_api, browser_api, health_api, yt_dlp_api),
+        (
+            fcm_api,
+            fcm_calendar_api,
+            fcm_admin_api,
+            browser_api,
+            monitor_api,
+            health_api,
+            yt_dlp_api,
+        ),
         "ToolKit",
         "1.0",
     )
@@ -51,6 +76,11 @@ async fn main() -> Result<(), std::io::Error> {
         .nest("/api/v1", api_service)
         .nest("/swagger", ui)
         .nest("/swagger/spec", spec)
+        .at(
+            "/metrics",
+            get(metrics::handler::metrics).with(utils::BasicAuth::default()),
+        )
+        .around(metrics::track_requests)
         .with(Cors::new())
         .with(Tracing)
         .data(pool.clone());
@@ -60,8 +90,9 @@ async fn main() -> Result<(), std::io::Error> {
             route,
             async move {
                 let _ = tokio::signal::ctrl_c().await;
+                supervisor.shutdown(Duration::from_secs(10)).await;
                 pool.close().await;
-                _ = driver.quit().await;
+                browser_pool.close().await;
             },
             Some(Duration::from_secs(5)),
         )
@@ -0,0 +1,21 @@
+use poem::{handler, http::StatusCode, Response};
+use prometheus::{Encoder, TextEncoder};
+use tracing::error;
+
+/// Prometheus text exposition of all registered metrics
+#[handler]
+pub fn metrics() -> Response {
+    let encoder = TextEncoder::new();
+    let mut buffer = Vec::new();
+
+    if let Err(e) = encoder.encode(&prometheus::gather(), &mut buffer) {
+        error!(error = ?e, "Failed to encode metrics");
+        return Response::builder()
+            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .body("Failed to encode metrics");
+    }
+
+    Response::builder()
+        .content_type(encoder.format_type())
+        .body(buffer)
+}
@@ -0,0 +1,89 @@
+use lazy_static::lazy_static;
+use poem::{Endpoint, IntoResponse, Request, Response, Result};
+use poem_openapi::OperationId;
+use prometheus::{
+    exponential_buckets, register_histogram, register_histogram_vec, register_int_counter,
+    register_int_counter_vec, register_int_gauge, Histogram, HistogramVec, IntCounter,
+    IntCounterVec, IntGauge,
+};
+use std::time::Instant;
+
+pub mod handler;
+
+lazy_static! {
+    pub static ref HTTP_REQUEST_DURATION: HistogramVec = register_histogram_vec!(
+        "toolkit_http_request_duration_seconds",
+        "HTTP request latency by operation id and status code",
+        &["operation_id", "status"]
+    )
+    .unwrap();
+    pub static ref FCM_SENDS: IntCounterVec = register_int_counter_vec!(
+        "toolkit_fcm_sends_total",
+        "FCM send attempts by firebase project and outcome",
+        &["project", "outcome"]
+    )
+    .unwrap();
+    pub static ref FCM_WORKER_TICK_DURATION: Histogram = register_histogram!(
+        "toolkit_fcm_worker_tick_duration_seconds",
+        "Time taken by one tick of the FCM worker"
+    )
+    .unwrap();
+    pub static ref FCM_WORKER_BACKLOG: IntGauge = register_int_gauge!(
+        "toolkit_fcm_worker_backlog",
+        "Schedules due at the last tick of the FCM worker"
+    )
+    .unwrap();
+    pub static ref FCM_SCHEDULE_LATENESS: Histogram = register_histogram!(
+        "toolkit_fcm_schedule_lateness_seconds",
+        "Delay between the scheduled and the actual send time of an FCM",
+        exponential_buckets(0.01, 2.5, 12).unwrap()
+    )
+    .unwrap();
+    pub static ref BROWSER_DRIVER_WAIT: Histogram = register_histogram!(
+        "toolkit_browser_driver_wait_seconds",
+        "Time spent waiting for a WebDriver session",
+        exponential_buckets(0.001, 4.0, 10).unwrap()
+    )
+    .unwrap();
+    pub static ref BROWSER_DRIVER_HOLD: Histogram = register_histogram!(
+        "toolkit_browser_driver_hold_seconds",
+        "Time a WebDriver session is held by a request",
+        exponential_buckets(0.01, 2.5, 10).unwrap()
+    )
+    .unwrap();
+    pub static ref YT_DLP_DOWNLOAD_DURATION: HistogramVec = register_histogram_vec!(
+        "toolkit_yt_dlp_download_duration_seconds",
+        "Time taken to download media by source",
+        &["source"],
+        exponential_buckets(0.5, 2.0, 10).unwrap()
+    )
+    .unwrap();
+    pub static ref YT_DLP_DOWNLOAD_BYTES: IntCounter = register_int_counter!(
+        "toolkit_yt_dlp_download_bytes_total",
+        "Bytes of media downloaded"
+    )
+    .unwrap();
+    pub static ref WHISPER_TRANSCRIPTION_DURATION: Histogram = register_histogram!(
+        "toolkit_whisper_transcription_duration_seconds",
+        "Latency of Whisper transcription requests",
+        exponential_buckets(0.5, 2.0, 10).unwrap()
+    )
+    .unwrap();
+}
+
+/// Middleware recording the latency of every request by its OpenAPI operation id
+pub async fn track_requests<E: Endpoint>(next: E, req: Request) -> Result<Response> {
+    let start = Instant::now();
+    let result = next.call(req).await.map(IntoResponse::into_response);
+
+    let (operation_id, status) = match &result {
+        Ok(resp) => (resp.data::<OperationId>().map(|id| id.0), resp.status()),
+        Err(err) => (err.data::<OperationId>().map(|id| id.0), err.status()),
+    };
+
+    HTTP_REQUEST_DURATION
+        .with_label_values(&[operation_id.unwrap_or("unknown"), status.as_str()])
+        .observe(start.elapsed().as_secs_f64());
+
+    result
+}
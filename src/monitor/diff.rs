@@ -0,0 +1,155 @@
+use similar::{ChangeTag, TextDiff};
+
+/// longest changed line quoted in a summary
+const SUMMARY_LINE_LENGTH: usize = 160;
+
+/// Line level changes between two versions of the watched text
+pub struct Change {
+    pub added: i32,
+    pub removed: i32,
+    /// changed lines relative to the longer version
+    pub ratio: f64,
+    pub summary: String,
+}
+
+impl Change {
+    /// Whether the change is large enough to notify about, any change passes a sensitivity of 0
+    pub fn passes(&self, sensitivity: f64) -> bool {
+        self.ratio > 0.0 && self.ratio >= sensitivity
+    }
+}
+
+/// Trim every line and drop empty ones, so whitespace-only changes are ignored
+pub fn normalize(text: &str) -> String {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<&str>>()
+        .join("\n")
+}
+
+/// Newline terminated text, otherwise an unterminated last line differs from the same line
+/// once another one is appended after it
+fn terminated(text: &str) -> String {
+    if text.is_empty() || text.ends_with('\n') {
+        text.to_string()
+    } else {
+        format!("{}\n", text)
+    }
+}
+
+pub fn compare(previous: &str, current: &str) -> Change {
+    let (previous, current) = (terminated(previous), terminated(current));
+    let diff = TextDiff::from_lines(&previous, &current);
+
+    let mut added = 0;
+    let mut removed = 0;
+    let mut first_added = None;
+    let mut first_removed = None;
+
+    for change in diff.iter_all_changes() {
+        match change.tag() {
+            ChangeTag::Insert => {
+                added += 1;
+                first_added.get_or_insert_with(|| change.value().trim().to_string());
+            }
+            ChangeTag::Delete => {
+                removed += 1;
+                first_removed.get_or_insert_with(|| change.value().trim().to_string());
+            }
+            ChangeTag::Equal => {}
+        }
+    }
+
+    let lines = previous.lines().count().max(current.lines().count()).max(1);
+    let ratio = (added.max(removed) as f64 / lines as f64).min(1.0);
+
+    let mut summary = format!("{} lines added, {} removed", added, removed);
+    if let Some(line) = first_added {
+        summary.push_str(&format!("\n+ {}", truncate(&line)));
+    }
+    if let Some(line) = first_removed {
+        summary.push_str(&format!("\n- {}", truncate(&line)));
+    }
+
+    Change {
+        added,
+        removed,
+        ratio,
+        summary,
+    }
+}
+
+pub fn unified(previous: &str, current: &str) -> String {
+    TextDiff::from_lines(&terminated(previous), &terminated(current))
+        .unified_diff()
+        .context_radius(3)
+        .header("previous", "current")
+        .to_string()
+}
+
+fn truncate(line: &str) -> String {
+    match line.char_indices().nth(SUMMARY_LINE_LENGTH) {
+        Some((index, _)) => format!("{}…", &line[..index]),
+        None => line.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_ignores_whitespace() {
+        assert_eq!(normalize("  a  \n\n\t b\r\n   \n"), "a\nb");
+        assert_eq!(normalize("a\n  b"), normalize("  a\n\nb  \n"));
+        assert_eq!(normalize(" \n\t\n"), "");
+    }
+
+    #[test]
+    fn counts_added_and_removed_lines() {
+        let change = compare("a\nb\nc\nd", "a\nB\nc\nd\ne");
+        assert_eq!((change.added, change.removed), (2, 1));
+        assert_eq!(change.ratio, 2.0 / 5.0);
+        assert_eq!(change.summary, "2 lines added, 1 removed\n+ B\n- b");
+
+        let change = compare("a\nb", "a\nb");
+        assert_eq!((change.added, change.removed), (0, 0));
+        assert_eq!(change.ratio, 0.0);
+    }
+
+    #[test]
+    fn appending_a_line_only_adds_it() {
+        let change = compare("a\nb", "a\nb\nc");
+        assert_eq!((change.added, change.removed), (1, 0));
+        assert_eq!(change.summary, "1 lines added, 0 removed\n+ c");
+    }
+
+    #[test]
+    fn ratio_is_capped() {
+        let change = compare("", "a\nb");
+        assert_eq!((change.added, change.removed), (2, 0));
+        assert_eq!(change.ratio, 1.0);
+    }
+
+    #[test]
+    fn sensitivity_is_a_minimum_share_of_changed_lines() {
+        // 1 of 4 lines changed
+        let change = compare("a\nb\nc\nd", "a\nb\nc\nD");
+        assert_eq!(change.ratio, 0.25);
+        assert!(change.passes(0.0));
+        assert!(change.passes(0.25));
+        assert!(!change.passes(0.3));
+
+        // nothing changed never notifies
+        assert!(!compare("a", "a").passes(0.0));
+    }
+
+    #[test]
+    fn summary_lines_are_truncated() {
+        let line = "é".repeat(SUMMARY_LINE_LENGTH + 10);
+        let change = compare("a", &line);
+        let added = change.summary.lines().nth(1).unwrap();
+        assert_eq!(added, format!("+ {}…", "é".repeat(SUMMARY_LINE_LENGTH)));
+    }
+}
@@ -0,0 +1,531 @@
+use super::diff::unified;
+use super::model::{Monitor, Snapshot, SnapshotDiff, UpdateMonitor};
+use super::utils::{validate_url, MAX_MONITORS_PER_USER, MIN_INTERVAL_SECONDS};
+use crate::fcm::utils::{extract_claims, next_execution};
+use crate::fcm::{quota, schedule::Recurrence};
+use crate::utils::{ApiTags, JsonError, JsonSuccess, ResponseObject};
+use chrono::{DateTime, Utc};
+use poem::{web::Data, Request};
+use poem_openapi::{param::Path, payload::Json, OpenApi};
+use sqlx::postgres::PgPool;
+
+pub struct PageMonitor {
+    pub projects: Vec<String>,
+}
+
+#[OpenApi(
+    prefix_path = "/monitor/",
+    request_header(
+        name = "firebase-auth",
+        ty = "String",
+        description = "Bearer token generated from firebase project (example: <code>Bearer {token}</code>)"
+    ),
+    tag = "ApiTags::Monitor"
+)]
+impl PageMonitor {
+    // create new instance
+    pub fn new(projects: Vec<String>) -> Self {
+        Self { projects }
+    }
+
+    // create monitor
+    #[oai(path = "/", method = "post", operation_id = "monitor::create_monitor")]
+    async fn create_monitor(
+        &self,
+        req: &Request,
+        pool: Data<&PgPool>,
+        payload: Json<Monitor>,
+    ) -> Result<JsonSuccess<Monitor>, JsonError<String>> {
+        // extract user id from token
+        let data = match extract_claims(req.header("firebase-auth")) {
+            Ok(data) => data,
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        };
+
+        let fb_user_id = data.user_id;
+        let fb_project_id = data.aud;
+
+        if !self.projects.contains(&fb_project_id) {
+            return Err(ResponseObject::unauthorized("Invalid project id"));
+        }
+
+        let (cron_pattern, next_check) = validate(
+            &payload.url,
+            payload.webhook_url.as_deref(),
+            payload.push_token.as_deref(),
+            payload.cron_pattern.as_deref(),
+            payload.rrule.as_deref(),
+        )
+        .await?;
+
+        // concurrent creates of the same user are serialized so they can't exceed the limit together
+        let mut tx = match pool.0.begin().await {
+            Ok(tx) => tx,
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+
+        let locked = sqlx::query!(
+            "SELECT 1 AS locked FROM pg_advisory_xact_lock(hashtext($1))",
+            fb_user_id
+        )
+        .fetch_one(&mut *tx)
+        .await;
+
+        if let Err(e) = locked {
+            return Err(ResponseObject::internal_server_error(e));
+        }
+
+        let count = sqlx::query_scalar!(
+            r#"SELECT COUNT(*) as "count!" FROM monitor WHERE fb_user_id = $1"#,
+            fb_user_id
+        )
+        .fetch_one(&mut *tx)
+        .await;
+
+        match count {
+            Ok(count) if count >= *MAX_MONITORS_PER_USER => {
+                return Err(ResponseObject::too_many_requests(format!(
+                    "Reached the limit of {} monitors per user",
+                    *MAX_MONITORS_PER_USER
+                )));
+            }
+            Ok(_) => {}
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        }
+
+        let current_time = Utc::now();
+
+        let monitor = sqlx::query_as!(
+            Monitor,
+            "INSERT INTO monitor (
+                name, fb_user_id, fb_project_id, url, selector, cron_pattern, rrule, sensitivity, push_token, webhook_url, next_check, created_at, updated_at
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)
+            RETURNING *",
+            payload.name,
+            fb_user_id,
+            fb_project_id,
+            payload.url,
+            payload.selector,
+            cron_pattern,
+            payload.rrule,
+            payload.sensitivity,
+            payload.push_token,
+            payload.webhook_url,
+            next_check,
+            current_time,
+            current_time
+        )
+        .fetch_one(&mut *tx)
+        .await;
+
+        let monitor = match monitor {
+            Ok(monitor) => monitor,
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+
+        match tx.commit().await {
+            Ok(_) => Ok(ResponseObject::created(monitor)),
+            Err(e) => Err(ResponseObject::internal_server_error(e)),
+        }
+    }
+
+    // find all monitors of the user
+    #[oai(
+        path = "/",
+        method = "get",
+        operation_id = "monitor::find_all_monitors"
+    )]
+    async fn find_all_monitors(
+        &self,
+        req: &Request,
+        pool: Data<&PgPool>,
+    ) -> Result<JsonSuccess<Vec<Monitor>>, JsonError<String>> {
+        // extract user id from token
+        let data = match extract_claims(req.header("firebase-auth")) {
+            Ok(data) => data,
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        };
+
+        let monitors = sqlx::query_as!(
+            Monitor,
+            "SELECT * FROM monitor WHERE fb_user_id = $1 ORDER BY id",
+            data.user_id
+        )
+        .fetch_all(pool.0)
+        .await;
+
+        match monitors {
+            Ok(monitors) => Ok(ResponseObject::ok(monitors)),
+            Err(e) => Err(ResponseObject::internal_server_error(e)),
+        }
+    }
+
+    // Update monitor by id (only if it belongs to the user)
+    #[oai(
+        path = "/:id",
+        method = "put",
+        operation_id = "monitor::update_monitor"
+    )]
+    async fn update_monitor(
+        &self,
+        req: &Request,
+        pool: Data<&PgPool>,
+        id: Path<i32>,
+        payload: Json<UpdateMonitor>,
+    ) -> Result<JsonSuccess<Monitor>, JsonError<String>> {
+        // extract user id from token
+        let data = match extract_claims(req.header("firebase-auth")) {
+            Ok(data) => data,
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        };
+
+        let (cron_pattern, next_check) = validate(
+            &payload.url,
+            payload.webhook_url.as_deref(),
+            payload.push_token.as_deref(),
+            payload.cron_pattern.as_deref(),
+            payload.rrule.as_deref(),
+        )
+        .await?;
+
+        let monitor = sqlx::query_as!(
+            Monitor,
+            "UPDATE monitor SET name = $1, url = $2, selector = $3, cron_pattern = $4, rrule = $5, sensitivity = $6, push_token = $7, webhook_url = $8, next_check = $9, updated_at = $10
+            WHERE id = $11 AND fb_user_id = $12
+            RETURNING *",
+            payload.name,
+            payload.url,
+            payload.selector,
+            cron_pattern,
+            payload.rrule,
+            payload.sensitivity,
+            payload.push_token,
+            payload.webhook_url,
+            next_check,
+            Utc::now(),
+            id.0,
+            data.user_id
+        )
+        .fetch_optional(pool.0)
+        .await;
+
+        match monitor {
+            Ok(Some(monitor)) => Ok(ResponseObject::ok(monitor)),
+            Ok(None) => Err(ResponseObject::not_found("Monitor not found")),
+            Err(e) => Err(ResponseObject::internal_server_error(e)),
+        }
+    }
+
+    // Delete monitor by id along with its snapshots (only if it belongs to the user)
+    #[oai(
+        path = "/:id",
+        method = "delete",
+        operation_id = "monitor::delete_monitor"
+    )]
+    async fn delete_monitor(
+        &self,
+        req: &Request,
+        pool: Data<&PgPool>,
+        id: Path<i32>,
+    ) -> Result<JsonSuccess<Monitor>, JsonError<String>> {
+        // extract user id from token
+        let data = match extract_claims(req.header("firebase-auth")) {
+            Ok(data) => data,
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        };
+
+        let monitor = sqlx::query_as!(
+            Monitor,
+            "DELETE FROM monitor WHERE id = $1 AND fb_user_id = $2 RETURNING *",
+            id.0,
+            data.user_id
+        )
+        .fetch_optional(pool.0)
+        .await;
+
+        match monitor {
+            Ok(Some(monitor)) => Ok(ResponseObject::ok(monitor)),
+            Ok(None) => Err(ResponseObject::not_found("Monitor not found")),
+            Err(e) => Err(ResponseObject::internal_server_error(e)),
+        }
+    }
+
+    // Pause monitor by id (only if it belongs to the user)
+    #[oai(
+        path = "/:id/pause",
+        method = "post",
+        operation_id = "monitor::pause_monitor"
+    )]
+    async fn pause_monitor(
+        &self,
+        req: &Request,
+        pool: Data<&PgPool>,
+        id: Path<i32>,
+    ) -> Result<JsonSuccess<Monitor>, JsonError<String>> {
+        self.set_paused(req, pool.0, id.0, true).await
+    }
+
+    // Resume a paused monitor by id (only if it belongs to the user)
+    #[oai(
+        path = "/:id/resume",
+        method = "post",
+        operation_id = "monitor::resume_monitor"
+    )]
+    async fn resume_monitor(
+        &self,
+        req: &Request,
+        pool: Data<&PgPool>,
+        id: Path<i32>,
+    ) -> Result<JsonSuccess<Monitor>, JsonError<String>> {
+        self.set_paused(req, pool.0, id.0, false).await
+    }
+
+    // detected changes of a monitor, newest first
+    #[oai(
+        path = "/:id/history",
+        method = "get",
+        operation_id = "monitor::get_history"
+    )]
+    async fn get_history(
+        &self,
+        req: &Request,
+        pool: Data<&PgPool>,
+        id: Path<i32>,
+    ) -> Result<JsonSuccess<Vec<Snapshot>>, JsonError<String>> {
+        // extract user id from token
+        let data = match extract_claims(req.header("firebase-auth")) {
+            Ok(data) => data,
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        };
+
+        let monitor = find_monitor(pool.0, id.0, &data.user_id).await?;
+
+        let snapshots = sqlx::query_as!(
+            Snapshot,
+            "SELECT id, monitor_id, added, removed, summary, notified, created_at
+            FROM monitor_snapshot WHERE monitor_id = $1 ORDER BY id DESC LIMIT 100",
+            monitor.id
+        )
+        .fetch_all(pool.0)
+        .await;
+
+        match snapshots {
+            Ok(snapshots) => Ok(ResponseObject::ok(snapshots)),
+            Err(e) => Err(ResponseObject::internal_server_error(e)),
+        }
+    }
+
+    // diff of a snapshot against the previous one
+    #[oai(
+        path = "/:id/history/:snapshot_id/diff",
+        method = "get",
+        operation_id = "monitor::get_diff"
+    )]
+    async fn get_diff(
+        &self,
+        req: &Request,
+        pool: Data<&PgPool>,
+        id: Path<i32>,
+        snapshot_id: Path<i64>,
+    ) -> Result<JsonSuccess<SnapshotDiff>, JsonError<String>> {
+        // extract user id from token
+        let data = match extract_claims(req.header("firebase-auth")) {
+            Ok(data) => data,
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        };
+
+        let monitor = find_monitor(pool.0, id.0, &data.user_id).await?;
+
+        let snapshot = sqlx::query!(
+            "SELECT id, content, added, removed, created_at FROM monitor_snapshot WHERE id = $1 AND monitor_id = $2",
+            snapshot_id.0,
+            monitor.id
+        )
+        .fetch_optional(pool.0)
+        .await;
+
+        let snapshot = match snapshot {
+            Ok(Some(snapshot)) => snapshot,
+            Ok(None) => {
+                return Err(ResponseObject::not_found("Snapshot not found"));
+            }
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+
+        let previous = sqlx::query!(
+            "SELECT id, content FROM monitor_snapshot WHERE monitor_id = $1 AND id < $2 ORDER BY id DESC LIMIT 1",
+            monitor.id,
+            snapshot.id
+        )
+        .fetch_optional(pool.0)
+        .await;
+
+        let previous = match previous {
+            Ok(previous) => previous,
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+
+        let previous_content = previous.as_ref().map(|p| p.content.as_str()).unwrap_or("");
+
+        Ok(ResponseObject::ok(SnapshotDiff {
+            snapshot_id: snapshot.id,
+            previous_snapshot_id: previous.as_ref().map(|p| p.id),
+            added: snapshot.added,
+            removed: snapshot.removed,
+            unified_diff: unified(previous_content, &snapshot.content),
+            content: snapshot.content,
+            created_at: snapshot.created_at,
+        }))
+    }
+}
+
+impl PageMonitor {
+    async fn set_paused(
+        &self,
+        req: &Request,
+        pool: &PgPool,
+        id: i32,
+        paused: bool,
+    ) -> Result<JsonSuccess<Monitor>, JsonError<String>> {
+        // extract user id from token
+        let data = match extract_claims(req.header("firebase-auth")) {
+            Ok(data) => data,
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        };
+
+        let monitor = find_monitor(pool, id, &data.user_id).await?;
+
+        // checks missed while paused are skipped, resume from now
+        let next_check = match paused {
+            true => monitor.next_check,
+            false => {
+                let recurrence = match Recurrence::new(
+                    monitor.cron_pattern.as_deref(),
+                    monitor.rrule.as_deref(),
+                ) {
+                    Ok(recurrence) => recurrence,
+                    Err(e) => {
+                        return Err(ResponseObject::bad_request(e));
+                    }
+                };
+                match next_execution(&recurrence) {
+                    Ok(next) => next,
+                    Err(e) => {
+                        return Err(ResponseObject::bad_request(e));
+                    }
+                }
+            }
+        };
+
+        let monitor = sqlx::query_as!(
+            Monitor,
+            "UPDATE monitor SET paused = $1, next_check = $2, updated_at = $3 WHERE id = $4 RETURNING *",
+            paused,
+            next_check,
+            Utc::now(),
+            monitor.id
+        )
+        .fetch_one(pool)
+        .await;
+
+        match monitor {
+            Ok(monitor) => Ok(ResponseObject::ok(monitor)),
+            Err(e) => Err(ResponseObject::internal_server_error(e)),
+        }
+    }
+}
+
+async fn find_monitor(
+    pool: &PgPool,
+    id: i32,
+    fb_user_id: &str,
+) -> Result<Monitor, JsonError<String>> {
+    let monitor = sqlx::query_as!(
+        Monitor,
+        "SELECT * FROM monitor WHERE id = $1 AND fb_user_id = $2",
+        id,
+        fb_user_id
+    )
+    .fetch_optional(pool)
+    .await;
+
+    match monitor {
+        Ok(Some(monitor)) => Ok(monitor),
+        Ok(None) => Err(ResponseObject::not_found("Monitor not found")),
+        Err(e) => Err(ResponseObject::internal_server_error(e)),
+    }
+}
+
+/// Validate the urls, targets and recurrence of a monitor, returns the cron pattern to store
+/// (rrule takes precedence) and the first check time
+async fn validate(
+    url: &str,
+    webhook_url: Option<&str>,
+    push_token: Option<&str>,
+    cron_pattern: Option<&str>,
+    rrule: Option<&str>,
+) -> Result<(Option<String>, DateTime<Utc>), JsonError<String>> {
+    validate_url(url)
+        .await
+        .map_err(ResponseObject::bad_request)?;
+
+    match (webhook_url, push_token) {
+        (None, None) => {
+            return Err(ResponseObject::bad_request(
+                "Either push_token or webhook_url must be set",
+            ));
+        }
+        (Some(webhook_url), _) => {
+            validate_url(webhook_url)
+                .await
+                .map_err(ResponseObject::bad_request)?;
+        }
+        _ => {}
+    }
+
+    // rrule takes precedence over cron_pattern
+    let cron_pattern = match rrule {
+        Some(_) => None,
+        None => cron_pattern,
+    };
+
+    let recurrence = match Recurrence::new(cron_pattern, rrule) {
+        Ok(recurrence) => recurrence,
+        Err(e) => {
+            return Err(ResponseObject::bad_request(e));
+        }
+    };
+
+    quota::check_interval(&recurrence, *MIN_INTERVAL_SECONDS)
+        .map_err(ResponseObject::bad_request)?;
+
+    match next_execution(&recurrence) {
+        Ok(next) => Ok((cron_pattern.map(str::to_string), next)),
+        Err(e) => Err(ResponseObject::bad_request(e)),
+    }
+}
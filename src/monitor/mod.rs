@@ -0,0 +1,35 @@
+use crate::{browser::handler::Selenium, supervisor::Supervisor};
+use gcp_auth::AuthenticationManager;
+use sqlx::postgres::PgPool;
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+mod diff;
+mod handler;
+mod model;
+mod utils;
+mod worker;
+
+/// the checker polls every 30 seconds, but a check renders a page so allow for slow sites
+const CHECKER_STALL_TIMEOUT: Duration = Duration::from_secs(900);
+
+pub async fn monitor_api(
+    pool: PgPool,
+    browser: Selenium,
+    service_accounts: Arc<HashMap<String, AuthenticationManager>>,
+    supervisor: &Supervisor,
+) -> handler::PageMonitor {
+    let monitor_api = handler::PageMonitor::new(service_accounts.keys().cloned().collect());
+
+    supervisor.spawn(
+        "monitor::checker",
+        Some(CHECKER_STALL_TIMEOUT),
+        move |handle| {
+            let pool = pool.clone();
+            let browser = browser.clone();
+            let service_accounts = service_accounts.clone();
+            async move { worker::run_checker(&pool, browser, service_accounts, handle).await }
+        },
+    );
+
+    monitor_api
+}
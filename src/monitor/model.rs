@@ -0,0 +1,160 @@
+use chrono::{DateTime, Utc};
+use poem_openapi::Object;
+
+fn name_example() -> String {
+    "Visa appointment slots".to_string()
+}
+
+fn url_example() -> String {
+    "https://example.com/appointments".to_string()
+}
+
+fn cron_example() -> Option<String> {
+    Some("*/15 * * * *".to_string())
+}
+
+/// Web page change monitor schema
+#[derive(Debug, Object, Clone, PartialEq)]
+pub struct Monitor {
+    #[oai(read_only)]
+    /// ID of the monitor
+    pub id: i32,
+
+    #[oai(validator(min_length = 3, max_length = 64), default = "name_example")]
+    /// Friendly name of the monitor
+    pub name: String,
+
+    #[oai(read_only)]
+    /// firebase user id (decoded from token)
+    pub fb_user_id: String,
+
+    #[oai(read_only)]
+    /// firebase project id (decoded from token)
+    pub fb_project_id: String,
+
+    #[oai(validator(min_length = 8, max_length = 2048), default = "url_example")]
+    /// http(s) url of the page to watch
+    pub url: String,
+
+    #[oai(validator(min_length = 1, max_length = 512))]
+    /// CSS selector of the part of the page to watch, the whole body when not set
+    pub selector: Option<String>,
+
+    #[oai(validator(min_length = 3, max_length = 64), default = "cron_example")]
+    /// cron pattern to check the page on
+    pub cron_pattern: Option<String>,
+
+    #[oai(validator(min_length = 16, max_length = 1024))]
+    /// RFC 5545 recurrence rule with DTSTART/TZID, used instead of cron_pattern when set
+    pub rrule: Option<String>,
+
+    #[oai(validator(minimum(value = "0"), maximum(value = "1")), default)]
+    /// minimum share of lines changed since the last notification (0.0 - 1.0) to notify again, 0 notifies on any change
+    pub sensitivity: f64,
+
+    #[oai(validator(min_length = 32, max_length = 512))]
+    /// device registration token to send an FCM to when the page changes
+    pub push_token: Option<String>,
+
+    #[oai(validator(min_length = 8, max_length = 2048))]
+    /// url to POST a JSON summary to when the page changes
+    pub webhook_url: Option<String>,
+
+    #[oai(read_only)]
+    /// whether the monitor is paused
+    pub paused: bool,
+
+    #[oai(read_only)]
+    /// error of the last check or notification, if any
+    pub last_error: Option<String>,
+
+    #[oai(read_only)]
+    /// last time the page was checked
+    pub last_checked_at: Option<DateTime<Utc>>,
+
+    #[oai(read_only)]
+    /// next time the page will be checked
+    pub next_check: DateTime<Utc>,
+
+    #[oai(read_only)]
+    /// created time of the monitor
+    pub created_at: DateTime<Utc>,
+
+    #[oai(read_only)]
+    /// last time the monitor was updated
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Update monitor schema
+#[derive(Debug, Object, Clone, PartialEq)]
+pub struct UpdateMonitor {
+    #[oai(validator(min_length = 3, max_length = 64), default = "name_example")]
+    /// Friendly name of the monitor
+    pub name: String,
+
+    #[oai(validator(min_length = 8, max_length = 2048), default = "url_example")]
+    /// http(s) url of the page to watch
+    pub url: String,
+
+    #[oai(validator(min_length = 1, max_length = 512))]
+    /// CSS selector of the part of the page to watch, the whole body when not set
+    pub selector: Option<String>,
+
+    #[oai(validator(min_length = 3, max_length = 64), default = "cron_example")]
+    /// cron pattern to check the page on
+    pub cron_pattern: Option<String>,
+
+    #[oai(validator(min_length = 16, max_length = 1024))]
+    /// RFC 5545 recurrence rule with DTSTART/TZID, used instead of cron_pattern when set
+    pub rrule: Option<String>,
+
+    #[oai(validator(minimum(value = "0"), maximum(value = "1")), default)]
+    /// minimum share of lines changed since the last notification (0.0 - 1.0) to notify again, 0 notifies on any change
+    pub sensitivity: f64,
+
+    #[oai(validator(min_length = 32, max_length = 512))]
+    /// device registration token to send an FCM to when the page changes
+    pub push_token: Option<String>,
+
+    #[oai(validator(min_length = 8, max_length = 2048))]
+    /// url to POST a JSON summary to when the page changes
+    pub webhook_url: Option<String>,
+}
+
+/// A recorded version of the watched content
+#[derive(Debug, Object, Clone, Eq, PartialEq)]
+pub struct Snapshot {
+    /// ID of the snapshot
+    pub id: i64,
+    /// ID of the monitor
+    pub monitor_id: i32,
+    /// lines added since the previous snapshot
+    pub added: i32,
+    /// lines removed since the previous snapshot
+    pub removed: i32,
+    /// short description of the change
+    pub summary: Option<String>,
+    /// whether the change since the last notified snapshot passed the sensitivity and was notified
+    pub notified: bool,
+    /// time the change was detected
+    pub created_at: DateTime<Utc>,
+}
+
+/// Changes of a snapshot against the previous one
+#[derive(Debug, Object, Clone, Eq, PartialEq)]
+pub struct SnapshotDiff {
+    /// ID of the snapshot
+    pub snapshot_id: i64,
+    /// ID of the previous snapshot, none for the first snapshot
+    pub previous_snapshot_id: Option<i64>,
+    /// lines added since the previous snapshot
+    pub added: i32,
+    /// lines removed since the previous snapshot
+    pub removed: i32,
+    /// unified diff of the watched text
+    pub unified_diff: String,
+    /// watched text at the time of the snapshot
+    pub content: String,
+    /// time the change was detected
+    pub created_at: DateTime<Utc>,
+}
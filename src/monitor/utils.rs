@@ -0,0 +1,16 @@
+use crate::utils::{env_or, resolve_public_url};
+use lazy_static::lazy_static;
+
+lazy_static! {
+    /// maximum number of monitors per user (MONITOR_MAX_PER_USER)
+    pub static ref MAX_MONITORS_PER_USER: i64 = env_or("MONITOR_MAX_PER_USER", 10);
+    /// minimum seconds between two checks of a monitor (MONITOR_MIN_INTERVAL_SECONDS)
+    pub static ref MIN_INTERVAL_SECONDS: i64 = env_or("MONITOR_MIN_INTERVAL_SECONDS", 300);
+    /// snapshots kept per monitor, older ones are deleted (MONITOR_MAX_SNAPSHOTS)
+    pub static ref MAX_SNAPSHOTS_PER_MONITOR: i64 = env_or("MONITOR_MAX_SNAPSHOTS", 50);
+}
+
+/// Only http(s) urls on public addresses can be watched or called back
+pub async fn validate_url(url: &str) -> Result<(), String> {
+    resolve_public_url(url).await.map(|_| ())
+}
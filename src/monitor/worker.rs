@@ -0,0 +1,283 @@
+use super::diff::{compare, normalize};
+use super::model::Monitor;
+use super::utils::{validate_url, MAX_SNAPSHOTS_PER_MONITOR};
+use crate::browser::handler::Selenium;
+use crate::fcm::{
+    schedule::{never, Recurrence},
+    worker::send_push,
+};
+use crate::supervisor::WorkerHandle;
+use crate::utils::send_webhook;
+use chrono::Utc;
+use gcp_auth::AuthenticationManager;
+use serde_json::json;
+use sqlx::postgres::PgPool;
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use tokio::time::sleep;
+use tracing::{debug, error, info, warn};
+
+/// how often due monitors are looked up, checks are minutes apart so polling is enough
+const POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Checks due monitors and notifies their owners about changes
+pub async fn run_checker(
+    pool: &PgPool,
+    browser: Selenium,
+    auth_managers: Arc<HashMap<String, AuthenticationManager>>,
+    worker: WorkerHandle,
+) {
+    while !worker.is_cancelled() {
+        let monitors = sqlx::query_as!(
+            Monitor,
+            "SELECT * FROM monitor WHERE next_check <= $1 AND NOT paused ORDER BY next_check",
+            Utc::now()
+        )
+        .fetch_all(pool)
+        .await
+        .unwrap_or_else(|e| {
+            error!(error=?e, "Error fetching due monitors");
+            worker.error(&e.to_string());
+            vec![]
+        });
+
+        debug!(monitor_count = monitors.len(), "Found monitors to check");
+
+        for monitor in monitors {
+            if worker.is_cancelled() {
+                break;
+            }
+            check_monitor(pool, &browser, &auth_managers, &monitor).await;
+        }
+
+        worker.tick();
+
+        tokio::select! {
+            _ = sleep(POLL_INTERVAL) => {}
+            _ = worker.cancelled() => {}
+        }
+    }
+}
+
+async fn check_monitor(
+    pool: &PgPool,
+    browser: &Selenium,
+    auth_managers: &HashMap<String, AuthenticationManager>,
+    monitor: &Monitor,
+) {
+    let next_check =
+        match Recurrence::new(monitor.cron_pattern.as_deref(), monitor.rrule.as_deref())
+            .and_then(|recurrence| recurrence.next_or_never(Utc::now()))
+        {
+            Ok(next) => next,
+            Err(e) => {
+                error!(monitor_id=?monitor.id, error=?e, "Error computing next check");
+                never()
+            }
+        };
+
+    // claim the check by moving the monitor on, so other replicas skip it, nothing is returned
+    // when one of them got there first
+    let claimed = sqlx::query_as!(
+        Monitor,
+        "UPDATE monitor SET next_check = $1 WHERE id = $2 AND next_check = $3 AND NOT paused RETURNING *",
+        next_check,
+        monitor.id,
+        monitor.next_check
+    )
+    .fetch_optional(pool)
+    .await;
+
+    let monitor = match claimed {
+        Ok(Some(monitor)) => monitor,
+        Ok(None) => {
+            debug!(monitor_id=?monitor.id, "Monitor was claimed by another worker");
+            return;
+        }
+        Err(e) => {
+            error!(monitor_id=?monitor.id, error=?e, "Error claiming monitor");
+            return;
+        }
+    };
+
+    // the host is resolved again on every check, it may point somewhere else since creation
+    let rendered = match validate_url(&monitor.url).await {
+        Ok(_) => {
+            browser
+                .render_text(&monitor.url, monitor.selector.as_deref())
+                .await
+        }
+        Err(e) => Err(e),
+    };
+
+    let result = match rendered {
+        Ok(text) => record_snapshot(pool, auth_managers, &monitor, normalize(&text)).await,
+        Err(e) => Err(e),
+    };
+
+    let last_error = match result {
+        Ok(_) => None,
+        Err(e) => {
+            warn!(monitor_id=?monitor.id, error=%e, "Error checking monitor");
+            Some(e)
+        }
+    };
+
+    let current_time = Utc::now();
+    let result = sqlx::query!(
+        "UPDATE monitor SET last_checked_at = $1, last_error = $2 WHERE id = $3",
+        current_time,
+        last_error,
+        monitor.id
+    )
+    .execute(pool)
+    .await;
+
+    if let Err(e) = result {
+        error!(monitor_id=?monitor.id, error=?e, "Error recording the check");
+    }
+}
+
+/// Store the content if it changed since the last snapshot and notify when the change since the
+/// last notified snapshot passes the sensitivity, so small changes on every check add up
+async fn record_snapshot(
+    pool: &PgPool,
+    auth_managers: &HashMap<String, AuthenticationManager>,
+    monitor: &Monitor,
+    content: String,
+) -> Result<(), String> {
+    let previous = sqlx::query_scalar!(
+        "SELECT content FROM monitor_snapshot WHERE monitor_id = $1 ORDER BY id DESC LIMIT 1",
+        monitor.id
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| format!("Error fetching the previous snapshot: {}", e))?;
+
+    if previous.as_deref() == Some(content.as_str()) {
+        debug!(monitor_id=?monitor.id, "Page has not changed");
+        return Ok(());
+    }
+
+    // the last notified snapshot, or the baseline while nothing was notified yet
+    let reference = sqlx::query!(
+        "SELECT id, content FROM monitor_snapshot WHERE id = COALESCE(
+            (SELECT MAX(id) FROM monitor_snapshot WHERE monitor_id = $1 AND notified),
+            (SELECT MIN(id) FROM monitor_snapshot WHERE monitor_id = $1)
+        )",
+        monitor.id
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| format!("Error fetching the last notified snapshot: {}", e))?;
+
+    // the first snapshot is the baseline, there is nothing to notify about
+    let (change, drift) = match (&previous, &reference) {
+        (Some(previous), Some(reference)) => (
+            Some(compare(previous, &content)),
+            Some(compare(&reference.content, &content)),
+        ),
+        _ => (None, None),
+    };
+    let notify = drift
+        .as_ref()
+        .is_some_and(|drift| drift.passes(monitor.sensitivity));
+
+    let snapshot_id = sqlx::query_scalar!(
+        "INSERT INTO monitor_snapshot (monitor_id, content, added, removed, summary, notified, created_at)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        RETURNING id",
+        monitor.id,
+        content,
+        change.as_ref().map(|change| change.added).unwrap_or(content.lines().count() as i32),
+        change.as_ref().map(|change| change.removed).unwrap_or(0),
+        change.as_ref().map(|change| change.summary.clone()),
+        notify,
+        Utc::now()
+    )
+    .fetch_one(pool)
+    .await
+    .map_err(|e| format!("Error storing the snapshot: {}", e))?;
+
+    let keep = match (&reference, notify) {
+        (Some(reference), false) => reference.id,
+        _ => snapshot_id,
+    };
+    prune_snapshots(pool, monitor.id, keep).await;
+
+    let change = match drift {
+        Some(drift) if notify => drift,
+        _ => return Ok(()),
+    };
+
+    info!(monitor_id=?monitor.id, snapshot_id, added=change.added, removed=change.removed, "Page changed");
+
+    let mut errors = Vec::new();
+
+    if let Some(push_token) = &monitor.push_token {
+        let payload = HashMap::from([
+            ("title".to_string(), format!("{} changed", monitor.name)),
+            ("body".to_string(), change.summary.clone()),
+            ("monitor_id".to_string(), monitor.id.to_string()),
+            ("snapshot_id".to_string(), snapshot_id.to_string()),
+            ("url".to_string(), monitor.url.clone()),
+        ]);
+
+        let result = match auth_managers.get(&monitor.fb_project_id) {
+            Some(auth_manager) => send_push(
+                auth_manager,
+                &monitor.fb_project_id,
+                push_token,
+                payload,
+                false,
+            )
+            .await
+            .map_err(|e| e.to_string()),
+            None => Err("No service account found for the project".to_string()),
+        };
+
+        if let Err(e) = result {
+            errors.push(format!("FCM: {}", e));
+        }
+    }
+
+    if let Some(webhook_url) = &monitor.webhook_url {
+        let body = json!({
+            "monitor_id": monitor.id,
+            "snapshot_id": snapshot_id,
+            "name": monitor.name,
+            "url": monitor.url,
+            "added": change.added,
+            "removed": change.removed,
+            "summary": change.summary,
+        });
+
+        if let Err(e) = send_webhook(webhook_url, &body).await {
+            errors.push(format!("Webhook: {}", e));
+        }
+    }
+
+    match errors.is_empty() {
+        true => Ok(()),
+        false => Err(errors.join(", ")),
+    }
+}
+
+/// Delete all but the latest snapshots of the monitor, `keep` is the reference of the next check
+async fn prune_snapshots(pool: &PgPool, monitor_id: i32, keep: i64) {
+    let result = sqlx::query!(
+        "DELETE FROM monitor_snapshot WHERE monitor_id = $1 AND id <> $2 AND id < (
+            SELECT MIN(id) FROM (
+                SELECT id FROM monitor_snapshot WHERE monitor_id = $1 ORDER BY id DESC LIMIT $3
+            ) AS latest
+        )",
+        monitor_id,
+        keep,
+        *MAX_SNAPSHOTS_PER_MONITOR
+    )
+    .execute(pool)
+    .await;
+
+    if let Err(e) = result {
+        error!(monitor_id, error=?e, "Error deleting old snapshots");
+    }
+}
@@ -0,0 +1,185 @@
+use chrono::Utc;
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn};
+
+pub mod model;
+
+use model::WorkerStatus;
+
+/// first restart delay of a crashed worker, doubled on every consecutive crash
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+/// a worker that ran this long before crashing restarts with MIN_BACKOFF again
+const HEALTHY_RUN: Duration = Duration::from_secs(300);
+
+struct Worker {
+    status: WorkerStatus,
+    stall_after: Option<Duration>,
+}
+
+/// Runs background workers, restarts them when they crash and stops them on shutdown
+#[derive(Clone, Default)]
+pub struct Supervisor {
+    workers: Arc<Mutex<BTreeMap<String, Worker>>>,
+    tasks: Arc<Mutex<Vec<JoinHandle<()>>>>,
+    shutdown: CancellationToken,
+}
+
+/// Handle given to a worker to report progress and observe shutdown
+#[derive(Clone)]
+pub struct WorkerHandle {
+    name: String,
+    workers: Arc<Mutex<BTreeMap<String, Worker>>>,
+    shutdown: CancellationToken,
+}
+
+impl Supervisor {
+    /// Spawn a worker, `stall_after` marks it as stuck when it does not tick for that long
+    pub fn spawn<F, Fut>(&self, name: &str, stall_after: Option<Duration>, worker: F)
+    where
+        F: Fn(WorkerHandle) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.workers.lock().unwrap().insert(
+            name.to_string(),
+            Worker {
+                status: WorkerStatus {
+                    name: name.to_string(),
+                    running: false,
+                    stuck: false,
+                    started_at: Utc::now(),
+                    last_tick: None,
+                    last_error: None,
+                    last_error_at: None,
+                    restarts: 0,
+                },
+                stall_after,
+            },
+        );
+
+        let handle = WorkerHandle {
+            name: name.to_string(),
+            workers: self.workers.clone(),
+            shutdown: self.shutdown.clone(),
+        };
+
+        let task = tokio::spawn(async move {
+            let mut backoff = MIN_BACKOFF;
+
+            loop {
+                handle.update(|status| {
+                    status.running = true;
+                    status.started_at = Utc::now();
+                });
+                info!(worker = handle.name, "Starting worker");
+
+                let started = tokio::time::Instant::now();
+                let result = tokio::spawn(worker(handle.clone())).await;
+                handle.update(|status| status.running = false);
+
+                if handle.is_cancelled() {
+                    info!(worker = handle.name, "Worker stopped");
+                    return;
+                }
+
+                let reason = match result {
+                    Ok(_) => "Worker exited unexpectedly".to_string(),
+                    Err(e) => format!("Worker crashed: {}", e),
+                };
+                error!(worker = handle.name, reason = %reason, "Restarting worker");
+                handle.error(&reason);
+
+                if started.elapsed() > HEALTHY_RUN {
+                    backoff = MIN_BACKOFF;
+                }
+
+                tokio::select! {
+                    _ = tokio::time::sleep(backoff) => {}
+                    _ = handle.cancelled() => return,
+                }
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+                handle.update(|status| status.restarts += 1);
+            }
+        });
+
+        self.tasks.lock().unwrap().push(task);
+    }
+
+    /// Status of every worker, ordered by name
+    pub fn statuses(&self) -> Vec<WorkerStatus> {
+        let now = Utc::now();
+
+        self.workers
+            .lock()
+            .unwrap()
+            .values()
+            .map(|worker| {
+                let mut status = worker.status.clone();
+                status.stuck = match worker.stall_after {
+                    Some(stall_after) => {
+                        let since = status.last_tick.unwrap_or(status.started_at);
+                        (now - since).to_std().unwrap_or_default() > stall_after
+                    }
+                    None => false,
+                };
+                status
+            })
+            .collect()
+    }
+
+    /// Signal every worker to stop and wait up to `timeout` for them to finish their current work
+    pub async fn shutdown(&self, timeout: Duration) {
+        info!("Stopping background workers");
+        self.shutdown.cancel();
+
+        let tasks: Vec<JoinHandle<()>> = self.tasks.lock().unwrap().drain(..).collect();
+        let stopped = tokio::time::timeout(timeout, async {
+            for task in tasks {
+                let _ = task.await;
+            }
+        })
+        .await;
+
+        if stopped.is_err() {
+            warn!("Background workers did not stop in time");
+        }
+    }
+}
+
+impl WorkerHandle {
+    /// Record that the worker completed a unit of work
+    pub fn tick(&self) {
+        self.update(|status| status.last_tick = Some(Utc::now()));
+    }
+
+    /// Record an error the worker recovered from
+    pub fn error(&self, error: &str) {
+        self.update(|status| {
+            status.last_error = Some(error.to_string());
+            status.last_error_at = Some(Utc::now());
+        });
+    }
+
+    /// Whether shutdown has been requested
+    pub fn is_cancelled(&self) -> bool {
+        self.shutdown.is_cancelled()
+    }
+
+    /// Completes when shutdown is requested
+    pub async fn cancelled(&self) {
+        self.shutdown.cancelled().await
+    }
+
+    fn update(&self, f: impl FnOnce(&mut WorkerStatus)) {
+        if let Some(worker) = self.workers.lock().unwrap().get_mut(&self.name) {
+            f(&mut worker.status);
+        }
+    }
+}
@@ -0,0 +1,23 @@
+use chrono::{DateTime, Utc};
+use poem_openapi::Object;
+
+/// State of a supervised background worker
+#[derive(Debug, Object, Clone)]
+pub struct WorkerStatus {
+    /// Name of the worker
+    pub name: String,
+    /// Whether the worker is currently running
+    pub running: bool,
+    /// Whether the worker has not ticked within its stall timeout
+    pub stuck: bool,
+    /// When the worker was last (re)started
+    pub started_at: DateTime<Utc>,
+    /// When the worker last completed a unit of work
+    pub last_tick: Option<DateTime<Utc>>,
+    /// Last error reported by the worker or the reason of its last crash
+    pub last_error: Option<String>,
+    /// When the last error happened
+    pub last_error_at: Option<DateTime<Utc>>,
+    /// Number of times the worker was restarted after crashing or exiting
+    pub restarts: u32,
+}
@@ -12,14 +12,30 @@ use poem_openapi::{
     {ApiResponse, Object, Tags},
 };
 use sqlx::{postgres::PgPoolOptions, PgPool};
-use std::env;
+use std::{
+    env,
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
+    time::Duration,
+};
+use url::Url;
 
 lazy_static! {
     static ref API_KEY: String = env::var("API_KEY").expect("API_KEY must be set");
+    static ref ADMIN_API_KEY: Option<String> = env::var("ADMIN_API_KEY").ok();
     pub static ref OPENAI_API_KEY: String =
         env::var("OPENAI_API_KEY").expect("OPENAI_API_KEY must be set");
     pub static ref CHROME_DRIVER_ENDPOINT: String =
         env::var("CHROME_DRIVER_ENDPOINT").expect("CHROME_DRIVER_ENDPOINT must be set");
+    /// reverse proxies allowed to set X-Forwarded-For (comma separated IP addresses)
+    static ref TRUSTED_PROXIES: Vec<IpAddr> = env::var("TRUSTED_PROXIES")
+        .map(|proxies| {
+            proxies
+                .split(',')
+                .filter_map(|ip| ip.trim().parse().ok())
+                .collect()
+        })
+        .unwrap_or_default();
 }
 
 #[derive(Tags)]
@@ -32,6 +48,10 @@ pub enum ApiTags {
     Selenium,
     /// Youtube-dl service
     YoutubeDL,
+    /// Administration endpoints (admin scoped API Key)
+    Admin,
+    /// Web page change monitors
+    Monitor,
 }
 
 pub async fn get_db_pool() -> PgPool {
@@ -45,13 +65,19 @@ pub async fn get_db_pool() -> PgPool {
     pool
 }
 
+/// Value of an environment variable, `default` when it is not set or doesn't parse
+pub fn env_or<T: FromStr>(key: &str, default: T) -> T {
+    env::var(key)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+
 pub fn get_host() -> String {
-    
     env::var("HOST").expect("HOST must be set")
 }
 
 pub fn get_port() -> String {
-    
     env::var("PORT").unwrap_or("3000".to_string())
 }
 
@@ -62,11 +88,15 @@ pub struct ResponseObject<T: ParseFromJSON + ToJSON + Send + Sync> {
 }
 
 impl<T: ParseFromJSON + ToJSON + Send + Sync> ResponseObject<T> {
-    pub fn ok(data: T) -> JsonSuccess<T> {
-        JsonSuccess::Ok(Json(ResponseObject {
+    pub fn json(data: T) -> Json<ResponseObject<T>> {
+        Json(ResponseObject {
             data: Some(data),
             error: None,
-        }))
+        })
+    }
+
+    pub fn ok(data: T) -> JsonSuccess<T> {
+        JsonSuccess::Ok(Self::json(data))
     }
 
     pub fn created(data: T) -> JsonSuccess<T> {
@@ -97,12 +127,33 @@ impl<T: ParseFromJSON + ToJSON + Send + Sync> ResponseObject<T> {
         }))
     }
 
+    pub fn too_many_requests(error: impl ToString) -> JsonError<T> {
+        JsonError::TooManyRequests(Json(ResponseObject {
+            data: None,
+            error: Some(error.to_string()),
+        }))
+    }
+
     pub fn internal_server_error(error: impl ToString) -> JsonError<T> {
         JsonError::InternalServerError(Json(ResponseObject {
             data: None,
             error: Some(error.to_string()),
         }))
     }
+
+    pub fn gateway_timeout(error: impl ToString) -> JsonError<T> {
+        JsonError::GatewayTimeout(Json(ResponseObject {
+            data: None,
+            error: Some(error.to_string()),
+        }))
+    }
+
+    pub fn service_unavailable(error: impl ToString) -> JsonError<T> {
+        JsonError::ServiceUnavailable(Json(ResponseObject {
+            data: None,
+            error: Some(error.to_string()),
+        }))
+    }
 }
 
 #[derive(ApiResponse)]
@@ -122,8 +173,14 @@ pub enum JsonError<T: ParseFromJSON + ToJSON + Send + Sync> {
     Unauthorized(Json<ResponseObject<T>>),
     #[oai(status = 404)]
     NotFound(Json<ResponseObject<T>>),
+    #[oai(status = 429)]
+    TooManyRequests(Json<ResponseObject<T>>),
     #[oai(status = 500)]
     InternalServerError(Json<ResponseObject<T>>),
+    #[oai(status = 503)]
+    ServiceUnavailable(Json<ResponseObject<T>>),
+    #[oai(status = 504)]
+    GatewayTimeout(Json<ResponseObject<T>>),
 }
 
 impl From<anyhow::Error> for JsonError<String> {
@@ -215,3 +272,132 @@ pub async fn verify_apikey(req: &Request) -> Result<(), String> {
 
     Ok(())
 }
+
+pub async fn verify_admin_apikey(req: &Request) -> Result<(), String> {
+    let admin_api_key = match ADMIN_API_KEY.as_ref() {
+        Some(key) => key,
+        None => {
+            return Err("Admin API is disabled".to_string());
+        }
+    };
+    let api_key = match req.header("API-Key") {
+        Some(key) => key,
+        None => {
+            return Err("API-Key header is missing".to_string());
+        }
+    };
+    if !admin_api_key.eq(api_key) {
+        return Err("Invalid API-Key".to_string());
+    }
+
+    Ok(())
+}
+
+/// IP address of the caller, X-Forwarded-For is only honoured on requests from a trusted proxy
+pub fn client_ip(req: &Request) -> Option<String> {
+    let peer = req
+        .remote_addr()
+        .as_socket_addr()
+        .map(|addr| addr.ip().to_canonical())?;
+
+    if !TRUSTED_PROXIES.contains(&peer) {
+        return Some(peer.to_string());
+    }
+
+    // the closest hop that isn't one of our proxies is the caller, earlier hops are client supplied
+    if let Some(forwarded) = req.header("X-Forwarded-For") {
+        for hop in forwarded.rsplit(',') {
+            match hop.trim().parse::<IpAddr>() {
+                Ok(ip) if TRUSTED_PROXIES.contains(&ip.to_canonical()) => continue,
+                Ok(ip) => return Some(ip.to_canonical().to_string()),
+                Err(_) => break,
+            }
+        }
+    }
+
+    Some(peer.to_string())
+}
+
+/// Resolve the host of a user supplied http(s) url, rejecting urls that reach loopback, private,
+/// link-local (cloud metadata) or other non public addresses
+pub async fn resolve_public_url(url: &str) -> Result<(Url, Vec<SocketAddr>), String> {
+    let parsed = match Url::parse(url) {
+        Ok(parsed) if parsed.scheme() == "http" || parsed.scheme() == "https" => parsed,
+        _ => return Err(format!("Invalid url: {}", url)),
+    };
+
+    let (host, port) = match (parsed.host_str(), parsed.port_or_known_default()) {
+        (Some(host), Some(port)) => (host.trim_start_matches('[').trim_end_matches(']'), port),
+        _ => return Err(format!("Invalid url: {}", url)),
+    };
+
+    let addrs: Vec<SocketAddr> = match tokio::net::lookup_host((host, port)).await {
+        Ok(addrs) => addrs.collect(),
+        Err(e) => return Err(format!("Could not resolve {}: {}", host, e)),
+    };
+
+    if addrs.is_empty() {
+        return Err(format!("Could not resolve {}", host));
+    }
+    if addrs.iter().any(|addr| !is_public(addr.ip())) {
+        return Err(format!("{} is not a public address", host));
+    }
+
+    Ok((parsed, addrs))
+}
+
+/// Client connecting only to the addresses `url` was resolved to, so the host can't be rebound to
+/// a private address between the check and the request, redirects are not followed
+pub fn pinned_client(
+    url: &Url,
+    addrs: &[SocketAddr],
+    timeout: Duration,
+) -> Result<reqwest::Client, String> {
+    let host = url.host_str().unwrap_or_default();
+
+    reqwest::Client::builder()
+        .resolve_to_addrs(host, addrs)
+        .redirect(reqwest::redirect::Policy::none())
+        .timeout(timeout)
+        .build()
+        .map_err(|e| format!("Error creating the client: {}", e))
+}
+
+fn is_public(ip: IpAddr) -> bool {
+    match ip.to_canonical() {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_unspecified()
+                || ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_broadcast()
+                || ip.is_multicast()
+                || ip.is_documentation()
+                // this network and shared address space (carrier-grade NAT)
+                || a == 0
+                || (a == 100 && (b & 0xc0) == 64))
+        }
+        IpAddr::V6(ip) => {
+            !(ip.is_unspecified()
+                || ip.is_loopback()
+                || ip.is_multicast()
+                || ip.is_unique_local()
+                || ip.is_unicast_link_local())
+        }
+    }
+}
+
+/// POST a JSON body to a user supplied webhook, which has to be on a public address
+pub async fn send_webhook(url: &str, body: &serde_json::Value) -> Result<(), String> {
+    let (url, addrs) = resolve_public_url(url).await?;
+    let client = pinned_client(&url, &addrs, Duration::from_secs(10))?;
+
+    let response = client.post(url).json(body).send().await;
+
+    match response {
+        Ok(response) if response.status().is_success() => Ok(()),
+        Ok(response) => Err(format!("returned {}", response.status())),
+        Err(e) => Err(format!("error sending request: {}", e)),
+    }
+}
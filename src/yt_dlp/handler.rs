@@ -2,6 +2,7 @@ use super::{
     model::Metadata,
     utils::{download_instgram_video, is_instagram_url},
 };
+use crate::metrics;
 use crate::utils::{self, verify_apikey, ApiTags, JsonError, JsonSuccess, ResponseObject};
 use poem::Request;
 use poem_openapi::{
@@ -218,6 +219,7 @@ impl YoutubeDL {
             .headers(headers)
             .multipart(form);
 
+        let transcription_timer = metrics::WHISPER_TRANSCRIPTION_DURATION.start_timer();
         let response = match request.send().await {
             Ok(response) => response,
             Err(error) => {
@@ -244,6 +246,8 @@ impl YoutubeDL {
             }
         };
 
+        transcription_timer.observe_duration();
+
         let transcription = match body.get("text") {
             Some(transcription) => transcription,
             None => {
@@ -266,9 +270,19 @@ impl YoutubeDL {
         // Check if the url is instagram.com
         if is_instagram_url(&url).unwrap_or(false) {
             debug!(url = %url, "Instagram URL detected");
-            return download_instgram_video(url, dir_path).await;
+            let download_timer = metrics::YT_DLP_DOWNLOAD_DURATION
+                .with_label_values(&["instagram"])
+                .start_timer();
+            let file = download_instgram_video(url, dir_path).await?;
+            download_timer.observe_duration();
+            track_download_size(&file);
+            return Ok(file);
         }
 
+        let download_timer = metrics::YT_DLP_DOWNLOAD_DURATION
+            .with_label_values(&["yt_dlp"])
+            .start_timer();
+
         let output = YoutubeDl::new(url.clone())
             .format(format)
             .output_template("%(id)s.%(ext)s")
@@ -312,6 +326,15 @@ impl YoutubeDL {
             }
         };
 
+        download_timer.observe_duration();
+        track_download_size(&file);
+
         Ok(file)
     }
 }
+
+fn track_download_size(file: &DirEntry) {
+    if let Ok(metadata) = file.metadata() {
+        metrics::YT_DLP_DOWNLOAD_BYTES.inc_by(metadata.len());
+    }
+}